@@ -1,33 +1,54 @@
-use compact_str::CompactString;
+use std::collections::BTreeMap;
+
+use compact_str::{CompactString, format_compact};
 use criterion::{BatchSize, Criterion, Throughput, black_box, criterion_group, criterion_main};
 use matchina::{
     engine::Engine,
-    order::{OrderRequest, OrderSide, util::DEFAULT_PAIR},
+    instrument::InstrumentSpec,
+    order::{OrderPrice, OrderQuantity, OrderRequest, OrderSide, util::DEFAULT_PAIR},
 };
 use rand::{Rng, SeedableRng, rngs::StdRng};
 use rust_decimal::Decimal;
 
 const WORKLOAD_SIZE: usize = 20_000;
 const MID_PRICE_CENTS: i64 = 10_000;
+const ACCOUNT_ID: &str = "bench";
+const FUNDS: i64 = 1_000_000_000_000;
+const TAKER_QUANTITY: i64 = 1_000_000_000;
 
 pub fn process(c: &mut Criterion) {
-    let workloads = [
-        ("normal", normal_workload(WORKLOAD_SIZE)),
-        ("same_price_fifo", same_price_fifo_workload(WORKLOAD_SIZE)),
-        ("cancel_heavy", cancel_heavy_workload(WORKLOAD_SIZE)),
-        ("market_sweep", market_sweep_workload(WORKLOAD_SIZE)),
-        ("flash_crash", flash_crash_workload(WORKLOAD_SIZE)),
-    ];
-
     let mut group = c.benchmark_group("process_batch");
     group.sample_size(10);
 
-    for (name, workload) in workloads {
+    for (name, workload) in workloads() {
+        group.throughput(Throughput::Elements(workload.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || workload.clone(),
+                |orders| process_orders(InstrumentSpec::default(), black_box(orders)),
+                BatchSize::LargeInput,
+            );
+        });
+    }
+
+    group.finish();
+}
+
+// the same workloads with the book in arrays indexed by tick (one cent, up to twice the mid price)
+pub fn ladders(c: &mut Criterion) {
+    let instrument = InstrumentSpec::new(cents(1), Decimal::ONE)
+        .and_then(|instrument| instrument.with_price_range(cents(1), cents(2 * MID_PRICE_CENTS)))
+        .unwrap();
+
+    let mut group = c.benchmark_group("array_ladder");
+    group.sample_size(10);
+
+    for (name, workload) in workloads() {
         group.throughput(Throughput::Elements(workload.len() as u64));
         group.bench_function(name, |b| {
             b.iter_batched(
                 || workload.clone(),
-                |orders| process_orders(black_box(orders)),
+                |orders| process_orders(instrument, black_box(orders)),
                 BatchSize::LargeInput,
             );
         });
@@ -36,8 +57,131 @@ pub fn process(c: &mut Criterion) {
     group.finish();
 }
 
-fn process_orders(orders: Vec<OrderRequest>) {
-    let mut engine = Engine::new(DEFAULT_PAIR);
+fn workloads() -> [(&'static str, Vec<OrderRequest>); 5] {
+    [
+        ("normal", normal_workload(WORKLOAD_SIZE)),
+        ("same_price_fifo", same_price_fifo_workload(WORKLOAD_SIZE)),
+        ("cancel_heavy", cancel_heavy_workload(WORKLOAD_SIZE)),
+        ("market_sweep", market_sweep_workload(WORKLOAD_SIZE)),
+        ("flash_crash", flash_crash_workload(WORKLOAD_SIZE)),
+    ]
+}
+
+// what the book does the most (compare prices, add quantities to the levels and take them off) on both
+// representations of prices and quantities, with the same workload
+pub fn arithmetic(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0xF1CED);
+    let levels: Vec<(i64, i64)> = (0..WORKLOAD_SIZE)
+        .map(|_| (MID_PRICE_CENTS + rng.gen_range(-120..=120), rng.gen_range(1..=25)))
+        .collect();
+    let decimals: Vec<(Decimal, Decimal)> = levels
+        .iter()
+        .map(|(price, quantity)| (cents(*price), Decimal::from(*quantity)))
+        .collect();
+    let fixed: Vec<(OrderPrice, OrderQuantity)> = decimals
+        .iter()
+        .map(|(price, quantity)| ((*price).try_into().unwrap(), (*quantity).try_into().unwrap()))
+        .collect();
+
+    let mut group = c.benchmark_group("price_arithmetic");
+    group.throughput(Throughput::Elements(WORKLOAD_SIZE as u64));
+    group.bench_function("decimal", |b| {
+        b.iter(|| {
+            let limit_price = cents(MID_PRICE_CENTS);
+            let mut remaining = Decimal::from(TAKER_QUANTITY);
+            for (price, quantity) in black_box(&decimals) {
+                if *price <= limit_price {
+                    remaining -= remaining.min(*quantity);
+                }
+            }
+            remaining
+        });
+    });
+    group.bench_function("fixed_point", |b| {
+        b.iter(|| {
+            let limit_price = OrderPrice::try_from(cents(MID_PRICE_CENTS)).unwrap();
+            let mut remaining = OrderQuantity::try_from(TAKER_QUANTITY).unwrap();
+            for (price, quantity) in black_box(&fixed) {
+                if *price <= limit_price {
+                    remaining = remaining.saturating_sub(remaining.min(*quantity));
+                }
+            }
+            remaining
+        });
+    });
+    // a book of aggregated levels swept by a taker
+    group.bench_function("decimal_book", |b| {
+        b.iter(|| {
+            let limit_price = cents(MID_PRICE_CENTS);
+            build_and_sweep(black_box(&decimals), limit_price, Decimal::from(TAKER_QUANTITY))
+        });
+    });
+    group.bench_function("fixed_point_book", |b| {
+        b.iter(|| {
+            let limit_price = OrderPrice::try_from(cents(MID_PRICE_CENTS)).unwrap();
+            build_and_sweep(
+                black_box(&fixed),
+                limit_price,
+                OrderQuantity::try_from(TAKER_QUANTITY).unwrap(),
+            )
+        });
+    });
+    group.finish();
+}
+
+// the fixed-point quantities have no operators, only the checked and saturating methods the book uses
+trait Quantity: Ord + Copy + Default {
+    fn add(self, rhs: Self) -> Self;
+    fn sub(self, rhs: Self) -> Self;
+}
+
+impl Quantity for Decimal {
+    fn add(self, rhs: Self) -> Self {
+        self + rhs
+    }
+
+    fn sub(self, rhs: Self) -> Self {
+        self - rhs
+    }
+}
+
+impl Quantity for OrderQuantity {
+    fn add(self, rhs: Self) -> Self {
+        self.saturating_add(rhs)
+    }
+
+    fn sub(self, rhs: Self) -> Self {
+        self.saturating_sub(rhs)
+    }
+}
+
+fn build_and_sweep<P: Ord + Copy, Q: Quantity>(levels: &[(P, Q)], limit_price: P, mut remaining: Q) -> Q {
+    let mut book: BTreeMap<P, Q> = BTreeMap::new();
+    for (price, quantity) in levels {
+        let level = book.entry(*price).or_default();
+        *level = level.add(*quantity);
+    }
+    for (price, quantity) in &book {
+        if *price > limit_price || remaining == Q::default() {
+            break;
+        }
+        remaining = remaining.sub(remaining.min(*quantity));
+    }
+    remaining
+}
+
+fn process_orders(instrument: InstrumentSpec, orders: Vec<OrderRequest>) {
+    let mut engine = Engine::new(DEFAULT_PAIR)
+        .with_order_capacity(WORKLOAD_SIZE)
+        .with_instrument(instrument);
+    for asset in DEFAULT_PAIR.split('/') {
+        let deposit = OrderRequest::Deposit {
+            account_id: CompactString::new_inline(ACCOUNT_ID),
+            asset: asset.into(),
+            amount: Decimal::from(FUNDS),
+        };
+        engine.process_admin(deposit).unwrap();
+    }
     for order in orders {
         black_box(engine.process(black_box(order))).unwrap();
     }
@@ -93,7 +237,10 @@ fn cancel_heavy_workload(size: usize) -> Vec<OrderRequest> {
         if !live_order_ids.is_empty() && rng.gen_bool(0.45) {
             let index = rng.gen_range(0..live_order_ids.len());
             let order_id = live_order_ids.swap_remove(index);
-            orders.push(OrderRequest::Cancel { order_id });
+            orders.push(OrderRequest::Cancel {
+                account_id: CompactString::new_inline(ACCOUNT_ID),
+                client_order_id: format_compact!("{order_id}"),
+            });
         } else {
             let price = MID_PRICE_CENTS - rng.gen_range(1..=500);
             live_order_ids.push(id);
@@ -145,12 +292,17 @@ fn flash_crash_workload(size: usize) -> Vec<OrderRequest> {
 
 fn create(id: u64, side: OrderSide, limit_price: Option<Decimal>, quantity: i64) -> OrderRequest {
     OrderRequest::Create {
-        account_id: CompactString::new_inline("bench"),
-        order_id: id,
+        account_id: CompactString::new_inline(ACCOUNT_ID),
+        client_order_id: format_compact!("{id}"),
         pair: CompactString::new_inline(DEFAULT_PAIR),
         side,
         limit_price,
         quantity: Decimal::from(quantity),
+        time_in_force: None,
+        trigger: None,
+        hidden: false,
+        session_id: None,
+        persistent: false,
     }
 }
 
@@ -158,5 +310,5 @@ fn cents(value: i64) -> Decimal {
     Decimal::new(value, 2)
 }
 
-criterion_group!(benches, process);
+criterion_group!(benches, process, ladders, arithmetic);
 criterion_main!(benches);
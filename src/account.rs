@@ -0,0 +1,391 @@
+use anyhow::Result;
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    fee::{Fee, FeeSchedule, Liquidity},
+    order::{AccountId, Order, OrderId, OrderPrice, OrderSide},
+    orderbook::Orderbook,
+    trade::Trade,
+};
+
+pub type Asset = CompactString;
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Balance {
+    pub available: Decimal,
+    pub locked: Decimal,
+}
+
+// funds locked for a live order: base for asks, quote for bids
+#[derive(Clone, Copy, Debug)]
+struct Reservation {
+    account: AccountId,
+    side: OrderSide,
+    limit_price: Option<OrderPrice>,
+    locked: Decimal,
+}
+
+pub struct Accounts {
+    base: Asset,
+    quote: Asset,
+    balances: IndexMap<AccountId, IndexMap<Asset, Balance>>,
+    reservations: IndexMap<OrderId, Reservation>,
+    fees_collected: IndexMap<Asset, Decimal>,
+}
+
+impl Accounts {
+    #[inline]
+    pub fn new(pair: &str) -> Self {
+        let (base, quote) = pair.split_once('/').unwrap_or((pair, ""));
+        Self {
+            base: base.into(),
+            quote: quote.into(),
+            balances: IndexMap::new(),
+            reservations: IndexMap::new(),
+            fees_collected: IndexMap::new(),
+        }
+    }
+
+    #[inline]
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    #[inline]
+    pub fn quote(&self) -> &str {
+        &self.quote
+    }
+
+    #[inline]
+    pub fn balance(&self, account: AccountId, asset: &str) -> Balance {
+        self.balances
+            .get(&account)
+            .and_then(|balances| balances.get(asset))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    #[inline]
+    fn balance_mut(&mut self, account: AccountId, asset: &str) -> &mut Balance {
+        self.balances
+            .entry(account)
+            .or_default()
+            .entry(asset.into())
+            .or_default()
+    }
+
+    #[inline]
+    pub fn deposit(&mut self, account: AccountId, asset: &str, amount: Decimal) -> Result<(), AccountError> {
+        if amount <= Decimal::ZERO {
+            return Err(AccountError::InvalidAmount(amount));
+        }
+
+        self.balance_mut(account, asset).available += amount;
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn withdraw(&mut self, account: AccountId, asset: &str, amount: Decimal) -> Result<(), AccountError> {
+        if amount <= Decimal::ZERO {
+            return Err(AccountError::InvalidAmount(amount));
+        }
+
+        let balance = self.balance_mut(account, asset);
+        if balance.available < amount {
+            return Err(AccountError::InsufficientFunds {
+                account,
+                asset: asset.into(),
+                required: amount,
+                available: balance.available,
+            });
+        }
+        balance.available -= amount;
+
+        Ok(())
+    }
+
+    // lock the funds the order needs before it reaches the book, market bids lock the current cost of sweeping the book
+    pub fn reserve(&mut self, order: &Order, orderbook: &Orderbook) -> Result<(), AccountError> {
+        let (asset, required) = match (order.side(), order.limit_price()) {
+            (OrderSide::Ask, _) => (self.base.clone(), order.remaining().to_decimal()),
+            (OrderSide::Bid, Some(limit_price)) => (self.quote.clone(), order.remaining() * limit_price),
+            // once triggered a market stop bid could sweep the book at any price, nothing bounds what it would pay
+            (OrderSide::Bid, None) if order.is_stop() => return Err(AccountError::UnpricedOrder(order.id())),
+            (OrderSide::Bid, None) => (
+                self.quote.clone(),
+                orderbook.sweep_cost(&order.side(), order.remaining()),
+            ),
+        };
+
+        let account = order.account();
+        let balance = self.balance_mut(account, &asset);
+        if balance.available < required {
+            return Err(AccountError::InsufficientFunds {
+                account,
+                asset,
+                required,
+                available: balance.available,
+            });
+        }
+        balance.available -= required;
+        balance.locked += required;
+
+        let reservation = Reservation {
+            account,
+            side: order.side(),
+            limit_price: order.limit_price(),
+            locked: required,
+        };
+        self.reservations.insert(order.id(), reservation);
+
+        Ok(())
+    }
+
+    // move the traded funds between both sides of the trade, buyers get back any price improvement
+    // and each side pays its fee (or gets its rebate) in the asset it receives
+    pub fn settle(&mut self, trade: &Trade, fees: &FeeSchedule) -> (Fee, Fee) {
+        let taker_fee = self.settle_order(trade.taker(), trade, Liquidity::Taker, fees);
+        let maker_fee = self.settle_order(trade.maker(), trade, Liquidity::Maker, fees);
+        (taker_fee, maker_fee)
+    }
+
+    fn settle_order(&mut self, order_id: OrderId, trade: &Trade, liquidity: Liquidity, fees: &FeeSchedule) -> Fee {
+        let Some(reservation) = self.reservations.get_mut(&order_id) else {
+            return Fee::default();
+        };
+
+        let notional = trade.price() * trade.quantity();
+        let quantity = trade.quantity().to_decimal();
+        let account = reservation.account;
+        let rate = fees.rates(account).rate(liquidity);
+        let fee = match reservation.side {
+            OrderSide::Bid => {
+                let reserved = reservation
+                    .limit_price
+                    .map_or(notional, |limit_price| limit_price * trade.quantity())
+                    .min(reservation.locked);
+                reservation.locked -= reserved;
+
+                let quote = self.quote.clone();
+                let balance = self.balance_mut(account, &quote);
+                balance.locked -= reserved;
+                balance.available += reserved - notional;
+
+                let base = self.base.clone();
+                let fee = Fee {
+                    amount: quantity * rate,
+                    asset: base,
+                };
+                self.balance_mut(account, &fee.asset).available += quantity - fee.amount;
+                fee
+            }
+            OrderSide::Ask => {
+                reservation.locked -= quantity;
+
+                let base = self.base.clone();
+                self.balance_mut(account, &base).locked -= quantity;
+
+                let quote = self.quote.clone();
+                let fee = Fee {
+                    amount: notional * rate,
+                    asset: quote,
+                };
+                self.balance_mut(account, &fee.asset).available += notional - fee.amount;
+                fee
+            }
+        };
+
+        *self.fees_collected.entry(fee.asset.clone()).or_default() += fee.amount;
+        fee
+    }
+
+    // net fees collected by the venue (after rebates) per asset
+    #[inline]
+    pub fn fees_collected(&self, asset: &str) -> Decimal {
+        self.fees_collected.get(asset).copied().unwrap_or_default()
+    }
+
+    // account and side of a live order
+    #[inline]
+    pub fn owner(&self, order_id: OrderId) -> Option<(AccountId, OrderSide)> {
+        self.reservations
+            .get(&order_id)
+            .map(|reservation| (reservation.account, reservation.side))
+    }
+
+    // give back whatever is still locked once the order is no longer live (filled, canceled or not bookable)
+    #[inline]
+    pub fn release(&mut self, order_id: OrderId) {
+        let Some(reservation) = self.reservations.swap_remove(&order_id) else {
+            return;
+        };
+
+        let asset = match reservation.side {
+            OrderSide::Ask => self.base.clone(),
+            OrderSide::Bid => self.quote.clone(),
+        };
+        let balance = self.balance_mut(reservation.account, &asset);
+        balance.locked -= reservation.locked;
+        balance.available += reservation.locked;
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AccountError {
+    #[error("insufficient funds ({account}, asset={asset}, required={required}, available={available})")]
+    InsufficientFunds {
+        account: AccountId,
+        asset: Asset,
+        required: Decimal,
+        available: Decimal,
+    },
+    #[error("amount should be positive! {0}")]
+    InvalidAmount(Decimal),
+    #[error("market stop bids have no price to reserve funds for, use a stop limit! {0}")]
+    UnpricedOrder(OrderId),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::{fee::FeeRates, order::util::DEFAULT_PAIR};
+
+    // convention for order ids: 3-digit side (bid = 900, ask = 901), 3-digit quantity, 3-digit price (for market orders always 999)
+
+    const BUYER: AccountId = AccountId::new(1);
+    const SELLER: AccountId = AccountId::new(2);
+
+    #[fixture]
+    fn accounts() -> Accounts {
+        let mut accounts = Accounts::new(DEFAULT_PAIR);
+        accounts.deposit(BUYER, "USDT", 1_000.into()).unwrap();
+        accounts.deposit(SELLER, "ETH", 100.into()).unwrap();
+        accounts
+    }
+
+    #[fixture]
+    fn orderbook() -> Orderbook {
+        Orderbook::default()
+    }
+
+    #[fixture]
+    fn ask_010_at_020() -> Order {
+        let order_id = OrderId::new(901_010_020);
+        Order::limit_order(order_id, OrderSide::Ask, 10.into(), 20.into()).with_account(SELLER)
+    }
+
+    #[fixture]
+    fn bid_020_at_025() -> Order {
+        let order_id = OrderId::new(900_020_025);
+        Order::limit_order(order_id, OrderSide::Bid, 20.into(), 25.into()).with_account(BUYER)
+    }
+
+    #[rstest]
+    fn reserve_and_release(mut accounts: Accounts, orderbook: Orderbook, bid_020_at_025: Order) {
+        // the bid locks quantity times limit price
+        assert!(accounts.reserve(&bid_020_at_025, &orderbook).is_ok());
+        let expected = Balance {
+            available: 500.into(),
+            locked: 500.into(),
+        };
+        assert_eq!(accounts.balance(BUYER, "USDT"), expected);
+
+        // releasing gives everything back
+        accounts.release(bid_020_at_025.id());
+        let expected = Balance {
+            available: 1_000.into(),
+            locked: 0.into(),
+        };
+        assert_eq!(accounts.balance(BUYER, "USDT"), expected);
+    }
+
+    #[rstest]
+    fn insufficient_funds(mut accounts: Accounts, orderbook: Orderbook, ask_010_at_020: Order) {
+        // the buyer has no ETH to sell
+        let ask = ask_010_at_020.with_account(BUYER);
+        assert_eq!(
+            accounts.reserve(&ask, &orderbook),
+            Err(AccountError::InsufficientFunds {
+                account: BUYER,
+                asset: "ETH".into(),
+                required: 10.into(),
+                available: 0.into(),
+            })
+        );
+    }
+
+    #[rstest]
+    fn settle_trade(mut accounts: Accounts, mut orderbook: Orderbook, ask_010_at_020: Order, bid_020_at_025: Order) {
+        assert!(accounts.reserve(&ask_010_at_020, &orderbook).is_ok());
+        assert!(orderbook.handle_create(ask_010_at_020).is_ok());
+        assert!(accounts.reserve(&bid_020_at_025, &orderbook).is_ok());
+        assert!(orderbook.handle_create(bid_020_at_025).is_ok());
+
+        let trade = orderbook.trades().iter().next().unwrap();
+        accounts.settle(trade, &FeeSchedule::default());
+        accounts.release(ask_010_at_020.id());
+
+        // the buyer paid 10@20 and still has 10@25 locked for the rest of the bid (price improvement is back)
+        let expected = Balance {
+            available: 550.into(),
+            locked: 250.into(),
+        };
+        assert_eq!(accounts.balance(BUYER, "USDT"), expected);
+        assert_eq!(accounts.balance(BUYER, "ETH").available, 10.into());
+
+        // the seller got 200 USDT for the 10 ETH
+        assert_eq!(
+            accounts.balance(SELLER, "ETH"),
+            Balance {
+                available: 90.into(),
+                locked: 0.into(),
+            }
+        );
+        assert_eq!(accounts.balance(SELLER, "USDT").available, 200.into());
+    }
+
+    #[rstest]
+    fn settle_trade_with_fees(
+        mut accounts: Accounts,
+        mut orderbook: Orderbook,
+        ask_010_at_020: Order,
+        bid_020_at_025: Order,
+    ) {
+        // the seller is the maker with a 1% rebate and the buyer the taker paying 2%
+        let rates = FeeRates::new(Decimal::new(-1, 2), Decimal::new(2, 2));
+        let fees = FeeSchedule::new(rates);
+
+        assert!(accounts.reserve(&ask_010_at_020, &orderbook).is_ok());
+        assert!(orderbook.handle_create(ask_010_at_020).is_ok());
+        assert!(accounts.reserve(&bid_020_at_025, &orderbook).is_ok());
+        assert!(orderbook.handle_create(bid_020_at_025).is_ok());
+
+        let trade = orderbook.trades().iter().next().unwrap();
+        let (taker_fee, maker_fee) = accounts.settle(trade, &fees);
+
+        // the buyer pays in ETH and the seller gets the rebate in USDT
+        let expected = Fee {
+            asset: "ETH".into(),
+            amount: Decimal::new(2, 1),
+        };
+        assert_eq!(taker_fee, expected);
+        assert_eq!(accounts.balance(BUYER, "ETH").available, Decimal::new(98, 1));
+        let expected = Fee {
+            asset: "USDT".into(),
+            amount: (-2).into(),
+        };
+        assert_eq!(maker_fee, expected);
+        assert_eq!(accounts.balance(SELLER, "USDT").available, 202.into());
+
+        // the venue keeps the fees net of rebates
+        assert_eq!(accounts.fees_collected("ETH"), Decimal::new(2, 1));
+        assert_eq!(accounts.fees_collected("USDT"), (-2).into());
+    }
+}
@@ -0,0 +1,209 @@
+use std::cmp::{Ordering, Reverse};
+
+use serde::{Deserialize, Serialize};
+
+use crate::order::{OrderPrice, OrderQuantity, OrderSide};
+
+// outcome of a call auction (indicative during the call period), the surplus is the quantity left
+// unmatched at the clearing price on the imbalanced side
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AuctionResult {
+    pub price: OrderPrice,
+    pub volume: OrderQuantity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imbalance: Option<OrderSide>,
+    pub surplus: OrderQuantity,
+}
+
+// the clearing price is the one that maximizes the executable volume, ties are broken by the lowest surplus,
+// then by the market pressure (the highest price if there's a buy surplus at all of them, the lowest if there's
+// a sell surplus), then by the closest price to the reference price (if any) and finally by the lowest price
+// (levels are given as price and total quantity, bids and asks both from the best price)
+pub fn clearing_price(
+    bids: &[(OrderPrice, OrderQuantity)],
+    asks: &[(OrderPrice, OrderQuantity)],
+    reference_price: Option<OrderPrice>,
+) -> Option<AuctionResult> {
+    // summed wide, a side with more than the fixed-point range at a price saturates instead of overflowing
+    let total = |quantity: i128| OrderQuantity::from_raw(quantity.min(i128::from(i64::MAX)) as i64);
+
+    // one pass up the prices of both sides (the bids from the lowest), the supply at a price is what's asked
+    // at or below it and the demand what's bid at or above it
+    let mut demand: i128 = bids.iter().map(|(_, quantity)| i128::from(quantity.raw())).sum();
+    let mut supply: i128 = 0;
+    let (mut bids, mut asks) = (bids.iter().rev().peekable(), asks.iter().peekable());
+    let mut tied: Option<Tied> = None;
+    loop {
+        let price = match (bids.peek(), asks.peek()) {
+            (Some((bid, _)), Some((ask, _))) => *bid.min(ask),
+            (Some((bid, _)), None) => *bid,
+            (None, Some((ask, _))) => *ask,
+            (None, None) => break,
+        };
+        let demand_at = total(demand);
+        while let Some((_, quantity)) = bids.next_if(|(bid, _)| *bid == price) {
+            demand -= i128::from(quantity.raw());
+        }
+        while let Some((_, quantity)) = asks.next_if(|(ask, _)| *ask == price) {
+            supply += i128::from(quantity.raw());
+        }
+        let supply_at = total(supply);
+
+        let volume = demand_at.min(supply_at);
+        if volume.is_zero() {
+            continue;
+        }
+        let imbalance = match demand_at.cmp(&supply_at) {
+            Ordering::Greater => Some(OrderSide::Bid),
+            Ordering::Less => Some(OrderSide::Ask),
+            Ordering::Equal => None,
+        };
+        let auction = AuctionResult {
+            price,
+            volume,
+            imbalance,
+            surplus: demand_at.saturating_sub(supply_at).abs(),
+        };
+        match tied.as_mut() {
+            Some(tied) if tied.key() == auction.key() => tied.add(auction, reference_price),
+            Some(tied) if tied.key() < auction.key() => {}
+            _ => tied = Some(Tied::new(auction)),
+        }
+    }
+
+    tied.map(|tied| match tied.pressure {
+        Some(OrderSide::Bid) => tied.highest,
+        Some(OrderSide::Ask) => tied.lowest,
+        None => tied.closest,
+    })
+}
+
+impl AuctionResult {
+    // the highest volume and then the lowest surplus first
+    #[inline]
+    fn key(&self) -> (Reverse<OrderQuantity>, OrderQuantity) {
+        (Reverse(self.volume), self.surplus)
+    }
+}
+
+// what breaks the ties between the best candidates, seen from the lowest price
+struct Tied {
+    lowest: AuctionResult,
+    highest: AuctionResult,
+    closest: AuctionResult,      // to the reference price, the lowest of the closest ones
+    pressure: Option<OrderSide>, // the same surplus on the same side at every price pushes the price that way
+}
+
+impl Tied {
+    #[inline]
+    fn new(auction: AuctionResult) -> Self {
+        Self {
+            lowest: auction,
+            highest: auction,
+            closest: auction,
+            pressure: auction.imbalance,
+        }
+    }
+
+    #[inline]
+    fn key(&self) -> (Reverse<OrderQuantity>, OrderQuantity) {
+        self.lowest.key()
+    }
+
+    #[inline]
+    fn add(&mut self, auction: AuctionResult, reference_price: Option<OrderPrice>) {
+        let distance =
+            |price: OrderPrice| reference_price.map(|reference_price| price.saturating_sub(reference_price).abs());
+        self.highest = auction;
+        if distance(auction.price) < distance(self.closest.price) {
+            self.closest = auction;
+        }
+        if auction.imbalance != self.pressure {
+            self.pressure = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    fn maximize_volume() {
+        // at 10 only 10 can trade, at 11 the volume is 25 and at 12 it's 15
+        let bids = [(12.into(), 15.into()), (11.into(), 10.into()), (10.into(), 20.into())];
+        let asks = [(10.into(), 10.into()), (11.into(), 20.into()), (13.into(), 5.into())];
+        assert_eq!(
+            clearing_price(&bids, &asks, None),
+            Some(AuctionResult {
+                price: 11.into(),
+                volume: 25.into(),
+                imbalance: Some(OrderSide::Ask),
+                surplus: 5.into()
+            })
+        );
+
+        // nothing to trade if the book is not crossed
+        assert_eq!(clearing_price(&bids[2..], &asks[2..], None), None);
+    }
+
+    #[rstest]
+    fn tie_breakers() {
+        // the same volume and surplus at 10 and 11, the reference price decides
+        let bids = [(11.into(), 10.into())];
+        let asks = [(10.into(), 10.into())];
+        assert_eq!(
+            clearing_price(&bids, &asks, None).map(|auction| auction.price),
+            Some(10.into())
+        );
+        assert_eq!(
+            clearing_price(&bids, &asks, Some(12.into())).map(|auction| auction.price),
+            Some(11.into())
+        );
+
+        // the same volume at 10 and 11 but a lower surplus at 11
+        let bids = [(11.into(), 10.into()), (10.into(), 5.into())];
+        let auction = clearing_price(&bids, &asks, None).unwrap();
+        assert_eq!(auction.price, 11.into());
+        assert_eq!(auction.imbalance, None);
+
+        // the same volume and surplus at 10 and 11 with more to buy at both, the highest price wins
+        // whatever the reference price, and the lowest with more to sell
+        let bids = [(11.into(), 15.into())];
+        let asks = [(10.into(), 10.into())];
+        let auction = clearing_price(&bids, &asks, Some(10.into())).unwrap();
+        assert_eq!(auction.price, 11.into());
+        assert_eq!(auction.imbalance, Some(OrderSide::Bid));
+        let bids = [(11.into(), 10.into())];
+        let asks = [(10.into(), 15.into())];
+        let auction = clearing_price(&bids, &asks, Some(11.into())).unwrap();
+        assert_eq!(auction.price, 10.into());
+        assert_eq!(auction.imbalance, Some(OrderSide::Ask));
+    }
+
+    #[rstest]
+    fn interleaved_levels() {
+        // 30 can trade against 30 from 10 (only asked) to 11 (only bid), above it the demand drops and below it
+        // the supply
+        let bids = [(14.into(), 5.into()), (11.into(), 25.into()), (9.into(), 40.into())];
+        let asks = [(8.into(), 10.into()), (10.into(), 20.into()), (12.into(), 30.into())];
+        assert_eq!(
+            clearing_price(&bids, &asks, Some(13.into())),
+            Some(AuctionResult {
+                price: 11.into(),
+                volume: 30.into(),
+                imbalance: None,
+                surplus: OrderQuantity::ZERO
+            })
+        );
+
+        // a side summing past the fixed-point range saturates
+        let max = OrderQuantity::from_raw(i64::MAX);
+        let bids = [(11.into(), max), (10.into(), max)];
+        let asks = [(10.into(), 10.into())];
+        let auction = clearing_price(&bids, &asks, None).unwrap();
+        assert_eq!((auction.price, auction.volume), (11.into(), 10.into()));
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::VecDeque;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{order::OrderPrice, phase::TradingPhase, throttle::Timestamp};
+
+// what the instrument does once a taker reaches a price band
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum BreachAction {
+    #[default]
+    #[serde(rename = "VOLATILITY_AUCTION")]
+    VolatilityAuction,
+    Halt,
+}
+
+// bands as the max distance (e.g. 5 for 5%) from the static reference price (the configured one or the last
+// auction price) and from the dynamic one (the last trade or the oldest trade within the window if any),
+// after a breach the instrument resumes continuous trading once the duration (in nanoseconds) has elapsed
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PriceBands {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub static_percent: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dynamic_percent: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<Timestamp>,
+    #[serde(default)]
+    pub action: BreachAction,
+    #[serde(default)]
+    pub duration: Timestamp,
+}
+
+// prices a taker can trade at (both included)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PriceBand {
+    pub min: OrderPrice,
+    pub max: OrderPrice,
+}
+
+impl PriceBand {
+    #[inline]
+    fn around(reference_price: OrderPrice, percent: Decimal) -> Self {
+        let width = reference_price.percent(percent);
+        Self {
+            min: reference_price.saturating_sub(width),
+            max: reference_price.saturating_add(width),
+        }
+    }
+
+    // bands that don't overlap leave an empty band (min above max), no price is within both of them
+    #[inline]
+    fn intersect(self, other: Self) -> Self {
+        Self {
+            min: self.min.max(other.min),
+            max: self.max.min(other.max),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.min > self.max
+    }
+
+    #[inline]
+    pub fn contains(&self, price: OrderPrice) -> bool {
+        !self.is_empty() && self.min <= price && price <= self.max
+    }
+}
+
+#[derive(Default)]
+pub struct CircuitBreaker {
+    bands: PriceBands,
+    static_reference: Option<OrderPrice>,
+    recent_trades: VecDeque<(Timestamp, OrderPrice)>,
+    resume_at: Option<Timestamp>,
+}
+
+impl CircuitBreaker {
+    #[inline]
+    pub fn new(bands: PriceBands) -> Self {
+        Self {
+            bands,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn set_bands(&mut self, bands: PriceBands) {
+        self.bands = bands;
+    }
+
+    #[inline]
+    pub fn set_static_reference(&mut self, reference_price: OrderPrice) {
+        self.static_reference = Some(reference_price);
+    }
+
+    // keep the trades within the window (or just the last one)
+    #[inline]
+    pub fn record(&mut self, now: Timestamp, price: OrderPrice) {
+        self.recent_trades.push_back((now, price));
+        let window = self.bands.window.unwrap_or_default();
+        while self.recent_trades.len() > 1
+            && self
+                .recent_trades
+                .front()
+                .is_some_and(|(at, _)| at.saturating_add(window) < now)
+        {
+            self.recent_trades.pop_front();
+        }
+    }
+
+    #[inline]
+    fn dynamic_reference(&self, now: Timestamp) -> Option<OrderPrice> {
+        match self.bands.window {
+            Some(window) => self
+                .recent_trades
+                .iter()
+                .find(|(at, _)| at.saturating_add(window) >= now)
+                .or(self.recent_trades.back()),
+            None => self.recent_trades.back(),
+        }
+        .map(|(_, price)| *price)
+    }
+
+    // the band for takers right now (none if there's nothing to compare with)
+    pub fn band(&self, now: Timestamp) -> Option<PriceBand> {
+        let static_band = self
+            .bands
+            .static_percent
+            .zip(self.static_reference)
+            .map(|(percent, reference_price)| PriceBand::around(reference_price, percent));
+        let dynamic_band = self
+            .bands
+            .dynamic_percent
+            .zip(self.dynamic_reference(now))
+            .map(|(percent, reference_price)| PriceBand::around(reference_price, percent));
+
+        match (static_band, dynamic_band) {
+            (Some(static_band), Some(dynamic_band)) => Some(static_band.intersect(dynamic_band)),
+            (band, None) | (None, band) => band,
+        }
+    }
+
+    // the phase the instrument moves into after a breach
+    #[inline]
+    pub fn trip(&mut self, now: Timestamp) -> TradingPhase {
+        self.resume_at = Some(now.saturating_add(self.bands.duration));
+        match self.bands.action {
+            BreachAction::VolatilityAuction => TradingPhase::VolatilityAuction,
+            BreachAction::Halt => TradingPhase::Halted,
+        }
+    }
+
+    // whether the instrument should resume continuous trading
+    #[inline]
+    pub fn resume(&mut self, now: Timestamp) -> bool {
+        if self.resume_at.is_some_and(|resume_at| resume_at <= now) {
+            self.resume_at = None;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    fn static_and_dynamic_bands() {
+        let bands = PriceBands {
+            static_percent: Some(10.into()),
+            dynamic_percent: Some(5.into()),
+            window: Some(100),
+            ..Default::default()
+        };
+        let mut breaker = CircuitBreaker::new(bands);
+        assert_eq!(breaker.band(0), None);
+
+        breaker.set_static_reference(100.into());
+        assert_eq!(
+            breaker.band(0),
+            Some(PriceBand {
+                min: 90.into(),
+                max: 110.into()
+            })
+        );
+
+        // the dynamic band follows the oldest trade within the window
+        breaker.record(0, 106.into());
+        breaker.record(50, 108.into());
+        let band = breaker.band(50).unwrap();
+        assert_eq!(
+            (band.min, band.max),
+            (OrderPrice::try_from(Decimal::new(1007, 1)).unwrap(), 110.into())
+        );
+        breaker.record(120, 108.into());
+        let band = breaker.band(120).unwrap();
+        assert_eq!(
+            (band.min, band.max),
+            (OrderPrice::try_from(Decimal::new(1026, 1)).unwrap(), 110.into())
+        );
+        assert!(band.contains(110.into()));
+        assert!(!band.contains(102.into()));
+
+        // once the dynamic reference moves past the static band nothing is within both
+        breaker.record(300, 120.into());
+        let band = breaker.band(300).unwrap();
+        assert!(band.is_empty());
+        for price in [90, 100, 110, 114, 120] {
+            assert!(!band.contains(price.into()));
+        }
+    }
+
+    #[rstest]
+    fn trip_and_resume() {
+        let bands = PriceBands {
+            action: BreachAction::Halt,
+            duration: 10,
+            ..Default::default()
+        };
+        let mut breaker = CircuitBreaker::new(bands);
+        assert!(!breaker.resume(0));
+        assert_eq!(breaker.trip(5), TradingPhase::Halted);
+        assert!(!breaker.resume(14));
+        assert!(breaker.resume(15));
+        assert!(!breaker.resume(20));
+    }
+}
@@ -0,0 +1,211 @@
+use std::thread::{self, Scope, ScopedJoinHandle};
+
+use anyhow::{Result, anyhow};
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use thiserror::Error;
+
+use crate::{
+    engine::{Engine, EngineError},
+    event::Event,
+    order::OrderRequest,
+    pipeline::DEFAULT_RING_CAPACITY,
+    ring::{Consumer, Producer, ring},
+};
+
+// outcome of a request, numbered in the order the requests came in
+#[derive(Debug)]
+pub struct Sequenced {
+    pub sequence: u64,
+    pub pair: CompactString,
+    pub result: Result<Vec<Event>, DispatcherError>,
+}
+
+// spreads the engines of many pairs over single-threaded workers, each owning the engines of the pairs hashed onto
+// it, every request goes to the engine of its pair and the outcomes are merged back in the order of the requests,
+// so the output is the same whatever the number of workers (and each engine sees what it would see on its own),
+// it's for applications embedding the engines of many pairs (the binary runs a single pair through the pipeline)
+#[derive(Clone, Debug)]
+pub struct Dispatcher {
+    workers: usize,
+    capacity: usize,
+    admin: bool, // whether the input comes from the operator
+}
+
+impl Dispatcher {
+    #[inline]
+    pub fn new(workers: usize) -> Self {
+        Self {
+            workers: workers.max(1),
+            capacity: DEFAULT_RING_CAPACITY,
+            admin: false,
+        }
+    }
+
+    // slots of the rings to and from each worker
+    #[inline]
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    // the input comes from the operator, admin requests go through the engines too (see Pipeline::with_admin)
+    #[inline]
+    pub fn with_admin(mut self, admin: bool) -> Self {
+        self.admin = admin;
+        self
+    }
+
+    // the worker owning the engine of the pair (a stable hash, the same on every run)
+    #[inline]
+    pub fn worker(&self, pair: &str) -> usize {
+        (fnv1a(pair.as_bytes()) % self.workers as u64) as usize
+    }
+
+    // process every request with the engine of its pair (the first of each request), the output gets the outcomes
+    // in order on the calling thread, returns the engines once the input is exhausted
+    pub fn run(
+        &self,
+        engines: Vec<Engine>,
+        input: impl IntoIterator<Item = (CompactString, OrderRequest)> + Send,
+        mut output: impl FnMut(Sequenced),
+    ) -> Result<Vec<Engine>> {
+        let mut shards: Vec<IndexMap<CompactString, (usize, Engine)>> =
+            (0..self.workers).map(|_| IndexMap::new()).collect();
+        for (idx, engine) in engines.into_iter().enumerate() {
+            let pair = CompactString::new(engine.pair());
+            let shard = &mut shards[self.worker(&pair)];
+            if shard.contains_key(&pair) {
+                return Err(DispatcherError::DuplicatePair(pair).into());
+            }
+            shard.insert(pair, (idx, engine));
+        }
+
+        let (mut requests_tx, mut requests_rx) = (vec![], vec![]);
+        let (mut outcomes_tx, mut outcomes_rx) = (vec![], vec![]);
+        for _ in 0..self.workers {
+            let (tx, rx) = ring(self.capacity);
+            requests_tx.push(tx);
+            requests_rx.push(rx);
+            let (tx, rx) = ring(self.capacity);
+            outcomes_tx.push(tx);
+            outcomes_rx.push(rx);
+        }
+        // the worker of every request in order, for the merge (it can be as far behind as all the rings together)
+        let (assignments_tx, assignments_rx) = ring(self.capacity * self.workers);
+
+        thread::scope(|scope| {
+            // owned by the scope so the rings are closed on every way out (a worker or the output panicking
+            // included), otherwise the workers left would wait forever on their full rings
+            let mut outcomes_rx = outcomes_rx;
+            let dispatcher = thread::Builder::new()
+                .name("dispatcher".to_string())
+                .spawn_scoped(scope, || self.dispatch(input, requests_tx, assignments_tx))?;
+            let workers = shards
+                .into_iter()
+                .zip(requests_rx)
+                .zip(outcomes_tx)
+                .enumerate()
+                .map(|(worker, ((engines, rx), tx))| spawn(scope, worker, self.admin, engines, rx, tx))
+                .collect::<Result<Vec<_>>>()?;
+
+            // each worker handles its requests in order, so the next outcome of the worker of the next request
+            // is the outcome of that request
+            for worker in assignments_rx {
+                let sequenced = outcomes_rx[worker]
+                    .pop()
+                    .ok_or_else(|| anyhow!("worker {worker} stopped before the end of the input"))?;
+                output(sequenced);
+            }
+
+            dispatcher.join().map_err(|_| anyhow!("dispatcher panicked"))?;
+            let mut engines = vec![];
+            for (worker, handle) in workers.into_iter().enumerate() {
+                engines.extend(handle.join().map_err(|_| anyhow!("worker {worker} panicked"))?);
+            }
+            engines.sort_by_key(|(idx, _)| *idx);
+            Ok(engines.into_iter().map(|(_, engine)| engine).collect())
+        })
+    }
+
+    fn dispatch(
+        &self,
+        input: impl IntoIterator<Item = (CompactString, OrderRequest)>,
+        mut requests: Vec<Producer<(u64, CompactString, OrderRequest)>>,
+        mut assignments: Producer<usize>,
+    ) {
+        for (sequence, (pair, order_request)) in (0..).zip(input) {
+            let worker = self.worker(&pair);
+            // the request goes first, the merge never waits for a request that isn't on its way
+            if requests[worker].push((sequence, pair, order_request)).is_err() || assignments.push(worker).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+// a worker owns the engines of its pairs, the index of each one is where it goes back in the list of engines
+fn spawn<'scope>(
+    scope: &'scope Scope<'scope, '_>,
+    worker: usize,
+    admin: bool,
+    mut engines: IndexMap<CompactString, (usize, Engine)>,
+    requests: Consumer<(u64, CompactString, OrderRequest)>,
+    mut outcomes: Producer<Sequenced>,
+) -> Result<ScopedJoinHandle<'scope, Vec<(usize, Engine)>>> {
+    let handle = thread::Builder::new()
+        .name(format!("worker-{worker}"))
+        .spawn_scoped(scope, move || {
+            for (sequence, pair, order_request) in requests {
+                let result = match engines.get_mut(&pair) {
+                    Some((_, engine)) if admin => engine.process_admin(order_request).map_err(DispatcherError::from),
+                    Some((_, engine)) => engine.process(order_request).map_err(DispatcherError::from),
+                    None => Err(DispatcherError::UnknownPair(pair.clone())),
+                };
+                if outcomes.push(Sequenced { sequence, pair, result }).is_err() {
+                    break;
+                }
+            }
+            engines.into_values().collect()
+        })?;
+    Ok(handle)
+}
+
+// FNV-1a, unlike the std hasher it doesn't change between runs
+#[inline]
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[derive(Debug, Error)]
+pub enum DispatcherError {
+    #[error("no engine for the pair! {0}")]
+    UnknownPair(CompactString),
+    #[error("more than one engine for the pair! {0}")]
+    DuplicatePair(CompactString),
+    #[error("engine error: {0}")]
+    EngineError(#[from] EngineError),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    // the rest goes through the public api, see tests/dispatcher.rs
+
+    #[rstest]
+    fn stable_hash() {
+        let dispatcher = Dispatcher::new(4);
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert!(
+            ["ETH/USDT", "BTC/USDT", "SOL/USDT"]
+                .iter()
+                .all(|pair| dispatcher.worker(pair) < 4)
+        );
+    }
+}
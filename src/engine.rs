@@ -1,59 +1,862 @@
 use anyhow::Result;
 use compact_str::CompactString;
+use indexmap::{IndexMap, IndexSet};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::{
-    order::{Order, OrderRequest},
-    orderbook::Orderbook,
+    account::{AccountError, Accounts, Balance},
+    breaker::{CircuitBreaker, PriceBands},
+    event::Event,
+    fee::FeeSchedule,
+    instrument::{InstrumentError, InstrumentSpec},
+    order::{AccountId, Order, OrderId, OrderPrice, OrderRequest},
+    orderbook::{MassCancelFilter, Orderbook, OrderbookError},
+    phase::{PhaseSchedule, TradingPhase},
+    position::{CostMethod, PositionReport, Positions},
+    rfq::{self, RfqError, Rfqs},
+    risk::{Risk, RiskError, RiskLimits},
+    summary,
+    throttle::{Throttle, ThrottleError, ThrottleLimits, Timestamp},
+    trade::Trade,
 };
 
 pub struct Engine {
-    _pair: CompactString,
+    pair: CompactString,
+    instrument: InstrumentSpec,
     orderbook: Orderbook,
+    account_ids: IndexMap<CompactString, AccountId>,
+    accounts: Accounts,
+    fees: FeeSchedule,
+    risk: Risk,
+    positions: Positions,
+    throttle: Throttle,
+    breaker: CircuitBreaker,
+    rfqs: Rfqs,
+    trades: Vec<Trade>,  // reused to settle the trades drained from the book
+    dropped: Vec<Order>, // reused to cancel the triggered stops dropped by the book
+    done: Vec<OrderId>,  // reused to close the orders settled by every request
+    client_orders: IndexMap<AccountId, IndexMap<CompactString, OrderId>>,
+    client_order_ids: IndexMap<OrderId, (AccountId, CompactString)>, // of the live orders, to prune the above
+    next_order_id: u64,
+    blocked: bool,
+    blocked_accounts: IndexSet<AccountId>,
+    now: Timestamp,
+    phase: TradingPhase,
+    schedule: PhaseSchedule,
 }
 
 impl Engine {
     #[inline]
     pub fn new(pair: &str) -> Self {
         Self {
-            _pair: CompactString::new_inline(pair),
+            pair: CompactString::new_inline(pair),
+            instrument: InstrumentSpec::default(),
             orderbook: Orderbook::default(),
+            account_ids: IndexMap::new(),
+            accounts: Accounts::new(pair),
+            fees: FeeSchedule::default(),
+            risk: Risk::default(),
+            positions: Positions::default(),
+            throttle: Throttle::default(),
+            breaker: CircuitBreaker::default(),
+            rfqs: Rfqs::default(),
+            trades: vec![],
+            dropped: vec![],
+            done: vec![],
+            client_orders: IndexMap::new(),
+            client_order_ids: IndexMap::new(),
+            next_order_id: 1,
+            blocked: false,
+            blocked_accounts: IndexSet::new(),
+            now: 0,
+            phase: TradingPhase::default(),
+            schedule: PhaseSchedule::default(),
         }
     }
 
     #[inline]
-    pub fn process(&mut self, order_request: OrderRequest) -> Result<(), EngineError> {
+    pub fn with_instrument(mut self, instrument: InstrumentSpec) -> Self {
+        self.instrument = instrument;
+        if let Some(range) = instrument.ladder_range() {
+            self.orderbook = std::mem::take(&mut self.orderbook).with_price_range(range);
+        }
+        self
+    }
+
+    #[inline]
+    pub fn with_order_capacity(mut self, capacity: usize) -> Self {
+        self.orderbook = std::mem::take(&mut self.orderbook).with_capacity(capacity);
+        self
+    }
+
+    #[inline]
+    pub fn with_fee_schedule(mut self, fees: FeeSchedule) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    #[inline]
+    pub fn with_risk_limits(mut self, limits: RiskLimits) -> Self {
+        self.risk = Risk::new(limits);
+        self
+    }
+
+    #[inline]
+    pub fn with_cost_method(mut self, method: CostMethod) -> Self {
+        self.positions = Positions::new(method);
+        self
+    }
+
+    #[inline]
+    pub fn with_throttle_limits(mut self, limits: ThrottleLimits) -> Self {
+        self.throttle = Throttle::new(limits);
+        self
+    }
+
+    #[inline]
+    pub fn with_phase(mut self, phase: TradingPhase) -> Self {
+        self.phase = phase;
+        self
+    }
+
+    #[inline]
+    pub fn with_phase_schedule(mut self, schedule: PhaseSchedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    #[inline]
+    pub fn with_price_bands(mut self, bands: PriceBands) -> Self {
+        self.breaker = CircuitBreaker::new(bands);
+        self
+    }
+
+    // requests from clients, the admin ones (see OrderRequest::is_admin) are rejected
+    #[inline]
+    pub fn process(&mut self, order_request: OrderRequest) -> Result<Vec<Event>, EngineError> {
+        let mut events = vec![];
+        self.process_into(order_request, false, &mut events)?;
+        Ok(events)
+    }
+
+    // requests from the operator, admin or not
+    #[inline]
+    pub fn process_admin(&mut self, order_request: OrderRequest) -> Result<Vec<Event>, EngineError> {
+        let mut events = vec![];
+        self.process_into(order_request, true, &mut events)?;
+        Ok(events)
+    }
+
+    // processes the requests in order exactly as one at a time, appending their events to one buffer and the
+    // errors to another (by the index of the request in the batch), as with process a request that fails leaves
+    // none of its events behind but whatever it changed in the engine before failing stays changed
+    #[inline]
+    pub fn process_batch(
+        &mut self,
+        order_requests: impl IntoIterator<Item = OrderRequest>,
+        events: &mut Vec<Event>,
+        errors: &mut Vec<(usize, EngineError)>,
+    ) {
+        self.process_all(order_requests, false, events, errors);
+    }
+
+    // the same for requests from the operator
+    #[inline]
+    pub fn process_admin_batch(
+        &mut self,
+        order_requests: impl IntoIterator<Item = OrderRequest>,
+        events: &mut Vec<Event>,
+        errors: &mut Vec<(usize, EngineError)>,
+    ) {
+        self.process_all(order_requests, true, events, errors);
+    }
+
+    #[inline]
+    fn process_all(
+        &mut self,
+        order_requests: impl IntoIterator<Item = OrderRequest>,
+        admin: bool,
+        events: &mut Vec<Event>,
+        errors: &mut Vec<(usize, EngineError)>,
+    ) {
+        for (idx, order_request) in order_requests.into_iter().enumerate() {
+            let len = events.len();
+            if let Err(error) = self.process_into(order_request, admin, events) {
+                events.truncate(len);
+                errors.push((idx, error));
+            }
+        }
+    }
+
+    // appends the events of the request, some of them may be there already if it fails
+    #[inline]
+    fn process_into(
+        &mut self,
+        order_request: OrderRequest,
+        admin: bool,
+        events: &mut Vec<Event>,
+    ) -> Result<(), EngineError> {
+        if !admin && order_request.is_admin() {
+            return Err(EngineError::AdminOnly(order_request.to_string()));
+        }
+        let handled = self.handle(order_request, events);
+        // a taker stopped at the price band trips the circuit breaker, whatever matched it (an order, the stops it
+        // triggered or the ones triggered by a phase change) and even if the request failed afterwards
+        let tripped = self.trip_on_band_breach(events);
+        handled.and(tripped)
+    }
+
+    #[inline]
+    fn handle(&mut self, order_request: OrderRequest, events: &mut Vec<Event>) -> Result<(), EngineError> {
         //info!("{order_request}");
         match order_request {
             OrderRequest::Create {
-                account_id: _,
-                order_id,
-                pair: _,
+                account_id,
+                client_order_id,
+                pair,
                 side,
                 limit_price,
                 quantity,
+                time_in_force,
+                trigger,
+                hidden,
+                session_id,
+                persistent,
             } => {
-                let order = if let Some(limit_price) = limit_price {
-                    Order::limit_order(order_id.into(), side, limit_price, quantity)
+                // decimals are converted to the fixed-point prices and quantities of the instrument
+                // (before touching any state, same as the validator)
+                check_pair(&self.pair, &pair)?;
+                let limit_price = limit_price.map(|price| self.instrument.price(price)).transpose()?;
+                let quantity = self.instrument.quantity(quantity)?;
+                if let Some(limit_price) = limit_price {
+                    self.instrument.check_notional(limit_price, quantity)?;
+                }
+                if let Some(trigger) = &trigger {
+                    self.instrument.check_trigger(trigger)?;
+                }
+
+                let account = self.account(&account_id);
+                self.check_order_entry(account, &account_id)?;
+                self.throttle.order(account, self.now)?;
+
+                // a client order id can be reused once the order it was mapped to is no longer live
+                let client_orders = self.client_orders.entry(account).or_default();
+                if let Some(order_id) = client_orders.get(&client_order_id)
+                    && self.orderbook.contains(*order_id)
+                {
+                    return Err(EngineError::ClientOrderIdDuplicated {
+                        account_id,
+                        client_order_id,
+                    });
+                }
+
+                let order_id = OrderId::new(self.next_order_id);
+                let mut order = if let Some(limit_price) = limit_price {
+                    Order::limit_order(order_id, side, quantity, limit_price)
                 } else {
-                    Order::market_order(order_id.into(), side, quantity)
+                    Order::market_order(order_id, side, quantity)
+                }
+                .with_account(account)
+                .with_hidden(hidden);
+                if let Some(time_in_force) = time_in_force {
+                    order = order.with_time_in_force(time_in_force);
+                }
+                if let Some(trigger) = trigger {
+                    order = order.with_trigger(trigger);
+                }
+                if let Some(session_id) = session_id {
+                    order = order.with_session(session_id.into(), persistent);
+                }
+
+                // orders breaching the risk limits or without enough funds are rejected before reaching the book
+                self.risk.check(&order, self.orderbook.last_trade_price())?;
+                self.accounts.reserve(&order, &self.orderbook)?;
+                self.next_order_id += 1;
+                client_orders.insert(client_order_id.clone(), order_id);
+                self.client_order_ids
+                    .insert(order_id, (account, client_order_id.clone()));
+                events.push(Event::Accepted {
+                    order_id,
+                    client_order_id,
+                });
+
+                let handled = if self.phase.is_matching() {
+                    self.orderbook.set_price_band(self.breaker.band(self.now));
+                    self.orderbook.handle_create(order).map(|_| ())
+                } else {
+                    self.orderbook.handle_rest(order)
                 };
-                let _ = self.orderbook.handle_create(order);
+                // the book rejects an order that can't rest before it trades (and drops the stops it triggers
+                // that can't, see settle_trades), whatever traded before any other error stands
+                self.settle_trades(events);
+                self.done.push(order_id);
+                if let Err(error) = handled {
+                    self.close_done();
+                    return Err(error.into());
+                }
+
+                if let Some(order) = self.orderbook.order(order_id) {
+                    self.risk.open(order);
+                }
+                self.close_done();
+                self.publish_indicative(events);
             }
-            OrderRequest::Cancel { order_id } => {
-                let _ = self.orderbook.handle_cancel(order_id.into());
+            OrderRequest::Cancel {
+                account_id,
+                client_order_id,
+            } => {
+                if !self.phase.accepts_cancels() {
+                    return Err(EngineError::NotAllowedInPhase(self.phase));
+                }
+                if let Some(account) = self.account_ids.get(&account_id) {
+                    self.throttle.cancel(*account, self.now)?;
+                }
+                let order_id = self.order_id(&account_id, &client_order_id)?;
+                let order = self.orderbook.handle_cancel(order_id)?;
+                self.close([order_id]);
+                events.push(Event::Cancelled(order));
+                self.publish_indicative(events);
+            }
+            OrderRequest::MassCancel {
+                account_id,
+                side,
+                min_price,
+                max_price,
+            } => {
+                if !self.phase.accepts_cancels() {
+                    return Err(EngineError::NotAllowedInPhase(self.phase));
+                }
+                let min_price = min_price.map(|price| self.instrument.price(price)).transpose()?;
+                let max_price = max_price.map(|price| self.instrument.price(price)).transpose()?;
+                let account = match account_id {
+                    Some(account_id) => match self.account_ids.get(&account_id) {
+                        Some(account) => {
+                            self.throttle.cancel(*account, self.now)?;
+                            Some(*account)
+                        }
+                        None => return Ok(()), // an unknown account has no orders
+                    },
+                    None => None,
+                };
+                let filter = MassCancelFilter {
+                    account,
+                    side,
+                    min_price,
+                    max_price,
+                };
+                let orders = self.orderbook.handle_mass_cancel(&filter)?;
+                self.close(orders.iter().map(Order::id));
+                events.extend(orders.into_iter().map(Event::Cancelled));
+                self.publish_indicative(events);
+            }
+            OrderRequest::SessionClosed { session_id } => {
+                let orders = self.orderbook.handle_session_closed(session_id.into())?;
+                self.close(orders.iter().map(Order::id));
+                events.extend(orders.into_iter().map(Event::Cancelled));
+            }
+            OrderRequest::Block {
+                account_id,
+                cancel_orders,
+            } => {
+                let account = match account_id {
+                    Some(account_id) => {
+                        let account = self.account(&account_id);
+                        self.blocked_accounts.insert(account);
+                        Some(account)
+                    }
+                    None => {
+                        self.blocked = true;
+                        None
+                    }
+                };
+                if cancel_orders {
+                    let filter = MassCancelFilter {
+                        account,
+                        ..Default::default()
+                    };
+                    let orders = self.orderbook.handle_mass_cancel(&filter)?;
+                    self.close(orders.iter().map(Order::id));
+                    events.extend(orders.into_iter().map(Event::Cancelled));
+                }
+            }
+            // lifting the instrument block doesn't lift the blocks of the accounts
+            OrderRequest::Unblock { account_id } => match account_id {
+                Some(account_id) => {
+                    let account = self.account(&account_id);
+                    self.blocked_accounts.swap_remove(&account);
+                }
+                None => self.blocked = false,
+            },
+            OrderRequest::SetThrottleLimits { account_id, limits } => match account_id {
+                Some(account_id) => {
+                    let account = self.account(&account_id);
+                    self.throttle.set_account_limits(account, limits);
+                }
+                None => self.throttle.set_default_limits(limits),
+            },
+            // the clock never goes backwards and moves the phases as scheduled
+            OrderRequest::Clock { timestamp } => {
+                self.now = self.now.max(timestamp);
+                let due: Vec<TradingPhase> = self.schedule.due(self.now).collect();
+                for phase in due {
+                    self.set_phase(phase, events)?;
+                }
+                if self.breaker.resume(self.now)
+                    && matches!(self.phase, TradingPhase::Halted | TradingPhase::VolatilityAuction)
+                {
+                    self.set_phase(TradingPhase::Continuous, events)?;
+                }
+            }
+            OrderRequest::SetPhase { phase } => self.set_phase(phase, events)?,
+            OrderRequest::SetPriceBands { bands } => self.breaker.set_bands(bands),
+            OrderRequest::RequestQuote {
+                account_id,
+                rfq_id,
+                side,
+                quantity,
+            } => {
+                let quantity = self.instrument.quantity(quantity)?;
+                let account = self.account(&account_id);
+                self.check_order_entry(account, &account_id)?;
+                self.rfqs.request(rfq_id.clone(), account, side, quantity)?;
+                events.push(Event::QuoteRequested { rfq_id, side, quantity });
+            }
+            // quotes are firm, the funds of the maker are reserved until the request is accepted
+            OrderRequest::Quote {
+                account_id,
+                rfq_id,
+                quote_id,
+                price,
+            } => {
+                let price = self.instrument.price(price)?;
+                let account = self.account(&account_id);
+                self.check_order_entry(account, &account_id)?;
+                let request = self
+                    .rfqs
+                    .get(&rfq_id)
+                    .ok_or_else(|| RfqError::RfqNotFound(rfq_id.clone()))?;
+                self.instrument.check_notional(price, request.quantity())?;
+                let order_id = OrderId::new(self.next_order_id);
+                let maker =
+                    Order::limit_order(order_id, !request.side(), request.quantity(), price).with_account(account);
+                self.rfqs.validate_quote(&rfq_id, &quote_id, &maker)?;
+                self.accounts.reserve(&maker, &self.orderbook)?;
+                self.next_order_id += 1;
+                self.rfqs.quote(&rfq_id, quote_id.clone(), maker);
+                events.push(Event::Quoted {
+                    rfq_id,
+                    quote_id,
+                    price,
+                });
+            }
+            // the block trade prints to the tape without touching the book, the other quotes are released
+            OrderRequest::AcceptQuote {
+                account_id,
+                rfq_id,
+                quote_id,
+            } => {
+                let account = self.account(&account_id);
+                self.check_order_entry(account, &account_id)?;
+                let request = self
+                    .rfqs
+                    .get(&rfq_id)
+                    .filter(|request| request.requester() == account)
+                    .ok_or_else(|| RfqError::RfqNotFound(rfq_id.clone()))?;
+                let maker = request.quote(&quote_id).ok_or_else(|| RfqError::QuoteNotFound {
+                    rfq_id: rfq_id.clone(),
+                    quote_id: quote_id.clone(),
+                })?;
+                let price = maker.limit_price().expect("quotes are limit orders");
+                let summary = summary::compute(&self.orderbook);
+                rfq::check_band(price, summary.best_bid, summary.best_ask)?;
+
+                // the trade is built before the taker locks anything or the request goes away, so a failure
+                // leaves both untouched
+                let order_id = OrderId::new(self.next_order_id);
+                let taker =
+                    Order::limit_order(order_id, request.side(), request.quantity(), price).with_account(account);
+                let (mut filled_taker, mut filled_maker) = (taker, *maker);
+                let trade_id = self.orderbook.next_trade_id();
+                let trade = Trade::with_price(
+                    trade_id,
+                    &mut filled_taker,
+                    &mut filled_maker,
+                    request.quantity(),
+                    price,
+                )
+                .map_err(OrderbookError::from)?;
+                self.accounts.reserve(&taker, &self.orderbook)?;
+                self.next_order_id += 1;
+
+                let (request, _) = self.rfqs.accept(&rfq_id, &quote_id).expect("quote checked above");
+                self.orderbook.print_trade(trade);
+                self.settle_trades(events);
+                self.done.extend(request.quotes().map(Order::id));
+                self.close_done();
+            }
+            // the funds of the quotes are released
+            OrderRequest::CancelQuoteRequest { account_id, rfq_id } => {
+                let account = self.account(&account_id);
+                let request = self.rfqs.cancel(&rfq_id, account)?;
+                self.close(request.quotes().map(Order::id));
+                events.push(Event::QuoteRequestCancelled { rfq_id });
+            }
+            OrderRequest::WithdrawQuote {
+                account_id,
+                rfq_id,
+                quote_id,
+            } => {
+                let account = self.account(&account_id);
+                let maker = self.rfqs.withdraw(&rfq_id, &quote_id, account)?;
+                self.close([maker.id()]);
+                events.push(Event::QuoteWithdrawn { rfq_id, quote_id });
+            }
+            OrderRequest::Deposit {
+                account_id,
+                asset,
+                amount,
+            } => {
+                let account = self.account(&account_id);
+                self.accounts.deposit(account, &asset, amount)?;
+            }
+            OrderRequest::Withdraw {
+                account_id,
+                asset,
+                amount,
+            } => {
+                let account = self.account(&account_id);
+                self.accounts.withdraw(account, &asset, amount)?;
+            }
+            OrderRequest::SetRiskLimits { account_id, limits } => match account_id {
+                Some(account_id) => {
+                    let account = self.account(&account_id);
+                    self.risk.set_account_limits(account, limits);
+                }
+                None => self.risk.set_instrument_limits(limits),
+            },
+            OrderRequest::SetReferencePrice { price } => {
+                let price = self.instrument.price(price)?;
+                self.risk.set_reference_price(price);
+                self.breaker.set_static_reference(price);
+            }
+            OrderRequest::SetFeeTier { account_id, tier } => {
+                if !self.fees.has_tier(&tier) {
+                    return Err(EngineError::FeeTierNotFound(tier));
+                }
+                let account = self.account(&account_id);
+                self.fees.assign_tier(account, tier);
             }
         };
 
         Ok(())
     }
 
+    // new orders (and quotes) need the phase to accept them and neither the instrument nor the account blocked
+    #[inline]
+    fn check_order_entry(&self, account: AccountId, account_id: &CompactString) -> Result<(), EngineError> {
+        if !self.phase.accepts_orders() {
+            return Err(EngineError::NotAllowedInPhase(self.phase));
+        }
+        if self.blocked {
+            return Err(EngineError::InstrumentBlocked);
+        }
+        if self.blocked_accounts.contains(&account) {
+            return Err(EngineError::AccountBlocked(account_id.clone()));
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn set_phase(&mut self, phase: TradingPhase, events: &mut Vec<Event>) -> Result<(), EngineError> {
+        if self.phase == phase {
+            return Ok(());
+        }
+        let previous = std::mem::replace(&mut self.phase, phase);
+        events.push(Event::PhaseChanged { phase });
+
+        if previous.is_uncrossing(phase)
+            && let Some(auction) = self.orderbook.uncross(self.reference_price(), phase.is_matching())?
+        {
+            self.breaker.set_static_reference(auction.price);
+            events.push(Event::Uncrossed(auction));
+            self.settle_trades(events);
+            self.close_done();
+        }
+
+        // stops triggered while the book wasn't matching (e.g. by the auction price) wait for it to match again
+        if phase.is_matching() {
+            self.orderbook.set_price_band(self.breaker.band(self.now));
+            let triggered = self.orderbook.trigger_pending_stops();
+            self.settle_trades(events);
+            self.close_done();
+            // right away, so the phases that follow (e.g. scheduled by the same clock) see the breach
+            self.trip_on_band_breach(events)?;
+            triggered?;
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn trip_on_band_breach(&mut self, events: &mut Vec<Event>) -> Result<(), EngineError> {
+        if self.orderbook.take_band_breach() {
+            let phase = self.breaker.trip(self.now);
+            self.set_phase(phase, events)?;
+        }
+        Ok(())
+    }
+
+    // settle the trades done since the last time (drained from the book) and cancel the triggered stops the book
+    // dropped, both sides of every trade and every triggered stop (e.g. cancelled at the band without trading) are
+    // left in done for the caller to close those no longer in the book
+    fn settle_trades(&mut self, events: &mut Vec<Event>) {
+        let mut trades = std::mem::take(&mut self.trades);
+        self.orderbook.drain_trades(&mut trades);
+        for trade in trades.drain(..) {
+            for order_id in [trade.taker(), trade.maker()] {
+                if let Some((account, side)) = self.accounts.owner(order_id) {
+                    self.positions.apply(account, side, trade.price(), trade.quantity());
+                    self.throttle.trade(account, self.now);
+                }
+            }
+            let (taker_fee, maker_fee) = self.accounts.settle(&trade, &self.fees);
+            self.risk.fill(&trade);
+            self.breaker.record(self.now, trade.price());
+            self.done.extend([trade.taker(), trade.maker()]);
+            events.push(Event::Trade {
+                trade,
+                taker_fee,
+                maker_fee,
+            });
+        }
+        self.trades = trades;
+
+        let mut dropped = std::mem::take(&mut self.dropped);
+        self.orderbook.drain_dropped(&mut dropped);
+        for order in dropped.drain(..) {
+            self.done.push(order.id());
+            events.push(Event::Cancelled(order));
+        }
+        self.dropped = dropped;
+        self.orderbook.drain_matched_stops(&mut self.done);
+    }
+
+    // close the orders settled by the request (see close)
+    #[inline]
+    fn close_done(&mut self) {
+        let mut done = std::mem::take(&mut self.done);
+        self.close(done.drain(..));
+        self.done = done;
+    }
+
+    // indicative price and volume during the call period of an auction
+    #[inline]
+    fn publish_indicative(&mut self, events: &mut Vec<Event>) {
+        if self.phase.is_auction()
+            && let reference_price = self.reference_price()
+            && let Some(auction) = self.orderbook.indicative_auction(reference_price)
+        {
+            events.push(Event::Indicative(auction));
+        }
+    }
+
+    #[inline]
+    fn reference_price(&self) -> Option<OrderPrice> {
+        self.orderbook.last_trade_price().or(self.risk.reference_price())
+    }
+
+    // release the funds still locked, the risk exposure and the client order id of the orders that are no longer
+    // live
+    #[inline]
+    fn close(&mut self, order_ids: impl IntoIterator<Item = OrderId>) {
+        for order_id in order_ids {
+            if !self.orderbook.contains(order_id) {
+                self.accounts.release(order_id);
+                self.risk.close(order_id);
+                if let Some((account, client_order_id)) = self.client_order_ids.swap_remove(&order_id)
+                    && let Some(client_orders) = self.client_orders.get_mut(&account)
+                {
+                    client_orders.swap_remove(&client_order_id);
+                }
+            }
+        }
+    }
+
+    // position of the account marked to the mid price of the book
+    #[inline]
+    pub fn position(&self, account_id: &str) -> PositionReport {
+        self.account_ids
+            .get(account_id)
+            .and_then(|account| self.positions.position(*account))
+            .map(|position| position.report(self.mid_price()))
+            .unwrap_or_default()
+    }
+
+    #[inline]
+    fn mid_price(&self) -> Option<OrderPrice> {
+        summary::compute(&self.orderbook).mid()
+    }
+
+    #[inline]
+    pub fn snapshot(&self) -> Snapshot {
+        let mid_price = self.mid_price();
+        let positions = self
+            .account_ids
+            .iter()
+            .filter_map(|(account_id, account)| {
+                let position = self.positions.position(*account)?;
+                Some((account_id.clone(), position.report(mid_price)))
+            })
+            .collect();
+        let blocked_accounts = self
+            .account_ids
+            .iter()
+            .filter(|(_, account)| self.blocked_accounts.contains(*account))
+            .map(|(account_id, _)| account_id.clone())
+            .collect();
+        Snapshot {
+            pair: self.pair.clone(),
+            phase: self.phase,
+            mid_price,
+            positions,
+            blocked: self.blocked,
+            blocked_accounts,
+        }
+    }
+
+    #[inline]
+    pub fn balance(&self, account_id: &str, asset: &str) -> Balance {
+        self.account_ids
+            .get(account_id)
+            .map(|account| self.accounts.balance(*account, asset))
+            .unwrap_or_default()
+    }
+
+    // engine order id of a client order id (as long as the order is live)
+    #[inline]
+    fn order_id(&self, account_id: &CompactString, client_order_id: &CompactString) -> Result<OrderId, EngineError> {
+        self.account_ids
+            .get(account_id)
+            .and_then(|account| self.client_orders.get(account))
+            .and_then(|client_orders| client_orders.get(client_order_id))
+            .copied()
+            .ok_or_else(|| EngineError::ClientOrderIdNotFound {
+                account_id: account_id.clone(),
+                client_order_id: client_order_id.clone(),
+            })
+    }
+
+    // accounts are interned so orders can keep a copyable id
+    #[inline]
+    fn account(&mut self, account_id: &CompactString) -> AccountId {
+        let next = AccountId::new(self.account_ids.len() as u64);
+        *self.account_ids.entry(account_id.clone()).or_insert(next)
+    }
+
+    // the checks that don't depend on the state of the engine, to run them ahead of it
+    #[inline]
+    pub fn validator(&self) -> Validator {
+        Validator {
+            pair: self.pair.clone(),
+            instrument: self.instrument,
+        }
+    }
+
+    #[inline]
+    pub fn pair(&self) -> &str {
+        &self.pair
+    }
+
+    #[inline]
+    pub fn phase(&self) -> TradingPhase {
+        self.phase
+    }
+
+    #[inline]
+    pub fn now(&self) -> Timestamp {
+        self.now
+    }
+
     #[inline]
     pub fn orderbook(&self) -> &Orderbook {
         &self.orderbook
     }
 }
 
+// checks of the requests that don't need the state of the engine, so they can run ahead of it (e.g. on another
+// thread), the engine runs the same checks before touching any state so the outcome is the same either way
+#[derive(Clone, Debug)]
+pub struct Validator {
+    pair: CompactString,
+    instrument: InstrumentSpec,
+}
+
+impl Validator {
+    #[inline]
+    pub fn validate(&self, order_request: &OrderRequest) -> Result<(), EngineError> {
+        match order_request {
+            OrderRequest::Create {
+                pair,
+                limit_price,
+                quantity,
+                trigger,
+                ..
+            } => {
+                check_pair(&self.pair, pair)?;
+                let limit_price = limit_price.map(|price| self.instrument.price(price)).transpose()?;
+                let quantity = self.instrument.quantity(*quantity)?;
+                if let Some(limit_price) = limit_price {
+                    self.instrument.check_notional(limit_price, quantity)?;
+                }
+                if let Some(trigger) = trigger {
+                    self.instrument.check_trigger(trigger)?;
+                }
+            }
+            OrderRequest::RequestQuote { quantity, .. } => {
+                self.instrument.quantity(*quantity)?;
+            }
+            OrderRequest::Quote { price, .. } | OrderRequest::SetReferencePrice { price } => {
+                self.instrument.price(*price)?;
+            }
+            OrderRequest::MassCancel {
+                min_price, max_price, ..
+            } => {
+                for price in [min_price, max_price].into_iter().flatten() {
+                    self.instrument.price(*price)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[inline]
+fn check_pair(expected: &CompactString, found: &CompactString) -> Result<(), EngineError> {
+    if found != expected {
+        return Err(EngineError::InvalidPair {
+            expected: expected.clone(),
+            found: found.clone(),
+        });
+    }
+    Ok(())
+}
+
+// state of the engine at a point in time
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Snapshot {
+    pub pair: CompactString,
+    pub phase: TradingPhase,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mid_price: Option<OrderPrice>,
+    pub positions: IndexMap<CompactString, PositionReport>,
+    #[serde(default)]
+    pub blocked: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocked_accounts: Vec<CompactString>,
+}
+
 #[derive(Debug, Error)]
 pub enum EngineError {
     #[error("invalid pair (expected={}, found={})", .expected, .found)]
@@ -61,4 +864,1079 @@ pub enum EngineError {
         expected: CompactString,
         found: CompactString,
     },
+    #[error("client order id already in use by a live order (account_id={}, client_order_id={})", .account_id, .client_order_id)]
+    ClientOrderIdDuplicated {
+        account_id: CompactString,
+        client_order_id: CompactString,
+    },
+    #[error("client order id not found (account_id={}, client_order_id={})", .account_id, .client_order_id)]
+    ClientOrderIdNotFound {
+        account_id: CompactString,
+        client_order_id: CompactString,
+    },
+    #[error("request not allowed in the trading phase! {0}")]
+    NotAllowedInPhase(TradingPhase),
+    #[error("instrument blocked, no new orders accepted")]
+    InstrumentBlocked,
+    #[error("account blocked, no new orders accepted! {0}")]
+    AccountBlocked(CompactString),
+    #[error("admin request not allowed from clients! {0}")]
+    AdminOnly(String),
+    #[error("fee tier not found! {0}")]
+    FeeTierNotFound(CompactString),
+    #[error("instrument error: {0}")]
+    InstrumentError(#[from] InstrumentError),
+    #[error("throttled: {0}")]
+    ThrottleError(#[from] ThrottleError),
+    #[error("rfq error: {0}")]
+    RfqError(#[from] RfqError),
+    #[error("risk error: {0}")]
+    RiskError(#[from] RiskError),
+    #[error("account error: {0}")]
+    AccountError(#[from] AccountError),
+    #[error("orderbook error: {0}")]
+    OrderbookError(#[from] OrderbookError),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::{
+        fee::FeeRates,
+        order::{
+            OrderQuantity, OrderSide, StopTrigger, TimeInForce,
+            util::{DEFAULT_PAIR, generate},
+        },
+    };
+
+    #[fixture]
+    fn engine() -> Engine {
+        let mut engine = Engine::new(DEFAULT_PAIR);
+        for account_id in ["alice", "bob"] {
+            for (asset, amount) in [("ETH", 100), ("USDT", 10_000)] {
+                engine.process_admin(deposit(account_id, asset, amount)).unwrap();
+            }
+        }
+        engine
+    }
+
+    fn deposit(account_id: &str, asset: &str, amount: u64) -> OrderRequest {
+        OrderRequest::Deposit {
+            account_id: account_id.into(),
+            asset: asset.into(),
+            amount: amount.into(),
+        }
+    }
+
+    fn create(account_id: &str, client_order_id: &str, side: OrderSide, price: u64) -> OrderRequest {
+        OrderRequest::Create {
+            account_id: account_id.into(),
+            client_order_id: client_order_id.into(),
+            pair: DEFAULT_PAIR.into(),
+            side,
+            limit_price: Some(price.into()),
+            quantity: 10.into(),
+            time_in_force: None,
+            trigger: None,
+            hidden: false,
+            session_id: None,
+            persistent: false,
+        }
+    }
+
+    fn with_quantity(mut order_request: OrderRequest, size: u64) -> OrderRequest {
+        if let OrderRequest::Create { quantity, .. } = &mut order_request {
+            *quantity = size.into();
+        }
+        order_request
+    }
+
+    fn with_stop(mut order_request: OrderRequest, stop_trigger: StopTrigger) -> OrderRequest {
+        if let OrderRequest::Create { trigger, .. } = &mut order_request {
+            *trigger = Some(stop_trigger);
+        }
+        order_request
+    }
+
+    fn market(mut order_request: OrderRequest) -> OrderRequest {
+        if let OrderRequest::Create { limit_price, .. } = &mut order_request {
+            *limit_price = None;
+        }
+        order_request
+    }
+
+    fn cancel(account_id: &str, client_order_id: &str) -> OrderRequest {
+        OrderRequest::Cancel {
+            account_id: account_id.into(),
+            client_order_id: client_order_id.into(),
+        }
+    }
+
+    fn accepted(events: &[Event]) -> OrderId {
+        match events.first() {
+            Some(Event::Accepted { order_id, .. }) => *order_id,
+            _ => panic!("order not accepted"),
+        }
+    }
+
+    #[rstest]
+    fn same_client_order_id_across_accounts(mut engine: Engine) {
+        // both accounts use the same client order id but get different engine order ids
+        let first = accepted(&engine.process(create("alice", "1", OrderSide::Bid, 10)).unwrap());
+        let second = accepted(&engine.process(create("bob", "1", OrderSide::Bid, 11)).unwrap());
+        assert_ne!(first, second);
+
+        // the cancel is addressed by client order id to the right order
+        match engine.process(cancel("alice", "1")).unwrap().as_slice() {
+            [Event::Cancelled(order)] => assert_eq!(order.id(), first),
+            events => panic!("unexpected events {events:?}"),
+        }
+        assert_eq!(
+            engine.orderbook().peek_top(&OrderSide::Bid).map(Order::id),
+            Some(second)
+        );
+    }
+
+    #[rstest]
+    fn reject_insufficient_funds(mut engine: Engine) {
+        // 10@1001 needs more USDT than alice has
+        assert!(matches!(
+            engine.process(create("alice", "1", OrderSide::Bid, 1001)),
+            Err(EngineError::AccountError(AccountError::InsufficientFunds { .. }))
+        ));
+        assert_eq!(engine.orderbook().peek_top(&OrderSide::Bid), None);
+        assert_eq!(engine.balance("alice", "USDT").available, 10_000.into());
+    }
+
+    #[rstest]
+    fn settle_balances(mut engine: Engine) {
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 10)).is_ok());
+        assert_eq!(engine.balance("alice", "USDT").locked, 100.into());
+
+        // bob sells 10 ETH at 10 to alice
+        assert!(engine.process(create("bob", "1", OrderSide::Ask, 10)).is_ok());
+        assert_eq!(engine.balance("alice", "USDT").locked, 0.into());
+        assert_eq!(engine.balance("alice", "USDT").available, 9_900.into());
+        assert_eq!(engine.balance("alice", "ETH").available, 110.into());
+        assert_eq!(engine.balance("bob", "USDT").available, 10_100.into());
+        assert_eq!(engine.balance("bob", "ETH").available, 90.into());
+
+        // the funds are released on cancel
+        assert!(engine.process(create("bob", "2", OrderSide::Ask, 20)).is_ok());
+        assert_eq!(engine.balance("bob", "ETH").locked, 10.into());
+        assert!(engine.process(cancel("bob", "2")).is_ok());
+        assert_eq!(engine.balance("bob", "ETH").locked, 0.into());
+        assert_eq!(engine.balance("bob", "ETH").available, 90.into());
+    }
+
+    #[rstest]
+    fn post_only_maker_fee(engine: Engine) {
+        let rates = FeeRates::new(Decimal::new(1, 3), Decimal::new(2, 3));
+        let vip = FeeRates::new(Decimal::new(-5, 4), Decimal::new(1, 3));
+        let mut engine = engine.with_fee_schedule(FeeSchedule::new(rates).with_tier("vip", vip));
+        let set_fee_tier = |tier: &str| OrderRequest::SetFeeTier {
+            account_id: "alice".into(),
+            tier: tier.into(),
+        };
+        assert!(matches!(
+            engine.process_admin(set_fee_tier("unknown")),
+            Err(EngineError::FeeTierNotFound(_))
+        ));
+        assert!(engine.process_admin(set_fee_tier("vip")).is_ok());
+
+        // alice rests a post only bid and bob takes it
+        let mut post_only = create("alice", "1", OrderSide::Bid, 10);
+        if let OrderRequest::Create { time_in_force, .. } = &mut post_only {
+            *time_in_force = Some(TimeInForce::GoodTilCancel { post_only: true });
+        }
+        assert!(engine.process(post_only).is_ok());
+        let events = engine.process(create("bob", "1", OrderSide::Ask, 10)).unwrap();
+
+        // alice gets the vip maker rebate in ETH and bob pays the default taker fee in USDT
+        match events.as_slice() {
+            [
+                Event::Accepted { .. },
+                Event::Trade {
+                    taker_fee, maker_fee, ..
+                },
+            ] => {
+                assert_eq!(maker_fee.asset, "ETH");
+                assert_eq!(maker_fee.amount, Decimal::new(-5, 3));
+                assert_eq!(taker_fee.asset, "USDT");
+                assert_eq!(taker_fee.amount, Decimal::new(2, 1));
+            }
+            events => panic!("unexpected events {events:?}"),
+        }
+        assert_eq!(engine.balance("alice", "ETH").available, Decimal::new(110_005, 3));
+        assert_eq!(engine.balance("bob", "USDT").available, Decimal::new(100_998, 1));
+    }
+
+    #[rstest]
+    fn risk_limits(mut engine: Engine) {
+        let limits = RiskLimits {
+            max_open_orders: Some(1),
+            price_collar: Some(10.into()),
+            ..Default::default()
+        };
+        let set_risk_limits = OrderRequest::SetRiskLimits {
+            account_id: Some("alice".into()),
+            limits,
+        };
+        assert!(engine.process_admin(set_risk_limits).is_ok());
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 10)).is_ok());
+        assert!(matches!(
+            engine.process(create("alice", "2", OrderSide::Bid, 10)),
+            Err(EngineError::RiskError(RiskError::MaxOpenOrders { .. }))
+        ));
+
+        // once filled the order no longer counts towards the open orders
+        assert!(engine.process(create("bob", "1", OrderSide::Ask, 10)).is_ok());
+        assert!(engine.process(create("alice", "2", OrderSide::Bid, 10)).is_ok());
+        assert!(engine.process(cancel("alice", "2")).is_ok());
+
+        // the last trade price is the reference for the price collar
+        assert!(matches!(
+            engine.process(create("alice", "3", OrderSide::Bid, 12)),
+            Err(EngineError::RiskError(RiskError::PriceCollar { .. }))
+        ));
+        assert!(engine.process(create("bob", "2", OrderSide::Bid, 12)).is_ok());
+    }
+
+    #[rstest]
+    fn positions_and_pnl(mut engine: Engine) {
+        // alice buys 10@10 from bob and the book is left at 11/13
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 10)).is_ok());
+        assert!(engine.process(create("bob", "1", OrderSide::Ask, 10)).is_ok());
+        assert!(engine.process(create("alice", "2", OrderSide::Bid, 11)).is_ok());
+        assert!(engine.process(create("bob", "2", OrderSide::Ask, 13)).is_ok());
+
+        let alice = engine.position("alice");
+        assert_eq!(alice.quantity, 10.into());
+        assert_eq!(alice.average_price, Some(10.into()));
+        assert_eq!(alice.unrealized_pnl, 20.into());
+        assert_eq!(engine.position("bob").quantity, (-10).into());
+
+        // alice sells 10@11 to herself and bob's position is marked to the new mid
+        assert!(engine.process(create("alice", "3", OrderSide::Ask, 11)).is_ok());
+        let snapshot = engine.snapshot();
+        assert_eq!(snapshot.mid_price, None);
+        assert_eq!(snapshot.positions["alice"].quantity, 10.into());
+        assert_eq!(snapshot.positions["alice"].realized_pnl, 10.into());
+        assert_eq!(snapshot.positions["bob"].unrealized_pnl, 0.into());
+        assert_eq!(engine.position("carol"), PositionReport::default());
+    }
+
+    #[rstest]
+    fn kill_switch(mut engine: Engine) {
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 10)).is_ok());
+        assert!(engine.process(create("bob", "1", OrderSide::Bid, 11)).is_ok());
+
+        // blocking alice cancels her resting orders but not bob's
+        let block = |account_id: Option<&str>| OrderRequest::Block {
+            account_id: account_id.map(Into::into),
+            cancel_orders: true,
+        };
+        let unblock = |account_id: Option<&str>| OrderRequest::Unblock {
+            account_id: account_id.map(Into::into),
+        };
+        match engine.process_admin(block(Some("alice"))).unwrap().as_slice() {
+            [Event::Cancelled(order)] => assert_eq!(order.limit_price(), Some(10.into())),
+            events => panic!("unexpected events {events:?}"),
+        }
+        assert!(matches!(
+            engine.process(create("alice", "2", OrderSide::Bid, 10)),
+            Err(EngineError::AccountBlocked(_))
+        ));
+        assert!(engine.process(create("bob", "2", OrderSide::Bid, 10)).is_ok());
+        assert_eq!(engine.snapshot().blocked_accounts, vec!["alice"]);
+
+        // blocking the instrument cancels everything left
+        assert_eq!(engine.process_admin(block(None)).unwrap().len(), 2);
+        assert!(matches!(
+            engine.process(create("bob", "3", OrderSide::Bid, 10)),
+            Err(EngineError::InstrumentBlocked)
+        ));
+        assert!(engine.snapshot().blocked);
+
+        // only the operator can lift the blocks, a client sending the same request is rejected
+        for request in [unblock(None), unblock(Some("alice"))] {
+            assert!(matches!(engine.process(request), Err(EngineError::AdminOnly(_))));
+        }
+        assert!(engine.snapshot().blocked);
+
+        // unblocking the instrument keeps alice blocked
+        assert!(engine.process_admin(unblock(None)).is_ok());
+        assert!(engine.process(create("bob", "3", OrderSide::Bid, 10)).is_ok());
+        assert!(engine.process(create("alice", "2", OrderSide::Bid, 10)).is_err());
+        assert!(engine.process_admin(unblock(Some("alice"))).is_ok());
+        assert!(engine.process(create("alice", "2", OrderSide::Bid, 10)).is_ok());
+        assert!(engine.snapshot().blocked_accounts.is_empty());
+    }
+
+    #[rstest]
+    fn throttle_orders(engine: Engine) {
+        let limits = ThrottleLimits {
+            orders_per_second: Some(1),
+            ..Default::default()
+        };
+        let mut engine = engine.with_throttle_limits(limits);
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 10)).is_ok());
+        assert!(matches!(
+            engine.process(create("alice", "2", OrderSide::Bid, 10)),
+            Err(EngineError::ThrottleError(ThrottleError::OrderRate { .. }))
+        ));
+        assert!(engine.process(create("bob", "1", OrderSide::Bid, 10)).is_ok());
+
+        // the throttled order never reached the book and there's room again a second later
+        assert_eq!(
+            engine.orderbook().depth(&OrderSide::Bid, 1),
+            vec![(10.into(), 20.into())]
+        );
+        assert!(
+            engine
+                .process_admin(OrderRequest::Clock {
+                    timestamp: 1_000_000_000
+                })
+                .is_ok()
+        );
+        assert!(engine.process(create("alice", "2", OrderSide::Bid, 10)).is_ok());
+        assert_eq!(engine.now(), 1_000_000_000);
+    }
+
+    #[rstest]
+    fn trading_phases(engine: Engine) {
+        let schedule = PhaseSchedule::default()
+            .with_transition(10, TradingPhase::PreOpen)
+            .with_transition(20, TradingPhase::Continuous)
+            .with_transition(30, TradingPhase::Halted);
+        let mut engine = engine.with_phase(TradingPhase::Closed).with_phase_schedule(schedule);
+        let clock = |timestamp| OrderRequest::Clock { timestamp };
+        assert!(matches!(
+            engine.process(create("alice", "1", OrderSide::Bid, 10)),
+            Err(EngineError::NotAllowedInPhase(TradingPhase::Closed))
+        ));
+
+        // during the pre-open crossing orders rest in the book without matching
+        assert!(matches!(
+            engine.process_admin(clock(10)).unwrap().as_slice(),
+            [Event::PhaseChanged {
+                phase: TradingPhase::PreOpen
+            }]
+        ));
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 11)).is_ok());
+        assert!(matches!(
+            engine
+                .process(create("bob", "1", OrderSide::Ask, 10))
+                .unwrap()
+                .as_slice(),
+            [Event::Accepted { .. }, Event::Indicative(_)]
+        ));
+        assert_eq!(engine.process(cancel("bob", "1")).unwrap().len(), 1);
+
+        // once in continuous trading orders match again
+        assert_eq!(engine.process_admin(clock(20)).unwrap().len(), 1);
+        assert_eq!(engine.process(create("bob", "1", OrderSide::Ask, 10)).unwrap().len(), 2);
+
+        // only cancels are accepted while halted
+        assert!(engine.process(create("alice", "2", OrderSide::Bid, 9)).is_ok());
+        assert!(engine.process_admin(clock(30)).is_ok());
+        assert!(engine.process(create("alice", "3", OrderSide::Bid, 9)).is_err());
+        assert!(engine.process(cancel("alice", "2")).is_ok());
+        assert_eq!(engine.snapshot().phase, TradingPhase::Halted);
+
+        // admin requests move the phase too
+        assert!(
+            engine
+                .process_admin(OrderRequest::SetPhase {
+                    phase: TradingPhase::Continuous
+                })
+                .is_ok()
+        );
+        assert!(engine.process(create("alice", "3", OrderSide::Bid, 9)).is_ok());
+    }
+
+    #[rstest]
+    fn closing_auction(mut engine: Engine) {
+        let set_phase = |phase| OrderRequest::SetPhase { phase };
+        assert!(engine.process_admin(set_phase(TradingPhase::ClosingAuction)).is_ok());
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 12)).is_ok());
+        assert!(engine.process(create("alice", "2", OrderSide::Bid, 11)).is_ok());
+        assert!(engine.process(create("bob", "1", OrderSide::Ask, 10)).is_ok());
+
+        // the indicative price is published on every change during the call period
+        match engine
+            .process(create("bob", "2", OrderSide::Ask, 11))
+            .unwrap()
+            .as_slice()
+        {
+            [Event::Accepted { .. }, Event::Indicative(auction)] => {
+                assert_eq!(auction.price, 11.into());
+                assert_eq!(auction.volume, 20.into());
+            }
+            events => panic!("unexpected events {events:?}"),
+        }
+
+        // all the crossing orders trade at the clearing price on the uncross
+        match engine
+            .process_admin(set_phase(TradingPhase::PostClose))
+            .unwrap()
+            .as_slice()
+        {
+            [
+                Event::PhaseChanged { .. },
+                Event::Uncrossed(auction),
+                Event::Trade { trade: first, .. },
+                Event::Trade { trade: second, .. },
+            ] => {
+                assert_eq!(auction.surplus, 0.into());
+                assert_eq!(first.price(), 11.into());
+                assert_eq!(second.price(), 11.into());
+            }
+            events => panic!("unexpected events {events:?}"),
+        }
+        assert_eq!(engine.orderbook().last_trade_price(), Some(11.into()));
+        assert_eq!(engine.balance("alice", "USDT").available, 9_780.into());
+        assert_eq!(engine.balance("alice", "USDT").locked, 0.into());
+        assert_eq!(engine.balance("bob", "ETH").available, 80.into());
+    }
+
+    #[rstest]
+    fn stops_wait_for_matching(mut engine: Engine) {
+        let set_phase = |phase| OrderRequest::SetPhase { phase };
+        assert!(engine.process_admin(set_phase(TradingPhase::ClosingAuction)).is_ok());
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 10)).is_ok());
+        assert!(engine.process(create("bob", "1", OrderSide::Ask, 10)).is_ok());
+        assert!(engine.process(create("bob", "2", OrderSide::Ask, 12)).is_ok());
+        let stop = with_stop(
+            create("alice", "2", OrderSide::Bid, 13),
+            StopTrigger::Fixed { stop_price: 10.into() },
+        );
+        let stop_id = accepted(&engine.process(stop).unwrap());
+
+        // the auction price triggers the stop but nothing matches after the close
+        match engine
+            .process_admin(set_phase(TradingPhase::PostClose))
+            .unwrap()
+            .as_slice()
+        {
+            [
+                Event::PhaseChanged { .. },
+                Event::Uncrossed(_),
+                Event::Trade { trade, .. },
+            ] => assert_eq!(trade.price(), 10.into()),
+            events => panic!("unexpected events {events:?}"),
+        }
+        assert_eq!(engine.orderbook().next_trade_id(), 2.into());
+
+        // it's still there to be canceled, or matched once the book matches again
+        assert!(engine.orderbook().order(stop_id).is_some());
+        match engine
+            .process_admin(set_phase(TradingPhase::Continuous))
+            .unwrap()
+            .as_slice()
+        {
+            [Event::PhaseChanged { .. }, Event::Trade { trade, .. }] => assert_eq!(trade.price(), 12.into()),
+            events => panic!("unexpected events {events:?}"),
+        }
+    }
+
+    #[rstest]
+    fn stop_bid_gapping_past_its_stop(mut engine: Engine) {
+        let trigger = StopTrigger::Fixed { stop_price: 10.into() };
+        assert!(
+            engine
+                .process(with_quantity(create("bob", "1", OrderSide::Ask, 10), 1))
+                .is_ok()
+        );
+        assert!(engine.process(create("bob", "2", OrderSide::Ask, 500)).is_ok());
+        assert!(engine.process_admin(deposit("carol", "USDT", 100)).is_ok());
+
+        // once triggered a market stop bid would pay whatever the book asks, nothing bounds the funds to lock
+        assert!(matches!(
+            engine.process(with_stop(market(create("alice", "1", OrderSide::Bid, 0)), trigger)),
+            Err(EngineError::AccountError(AccountError::UnpricedOrder(_)))
+        ));
+        assert_eq!(engine.balance("alice", "USDT").locked, 0.into());
+        let stop_id = accepted(
+            &engine
+                .process(with_stop(create("alice", "1", OrderSide::Bid, 11), trigger))
+                .unwrap(),
+        );
+        assert_eq!(engine.balance("alice", "USDT").locked, 110.into());
+
+        // the trade at 10 triggers the stop, which doesn't follow the price up to 500 but rests at its limit
+        match engine
+            .process(with_quantity(create("carol", "1", OrderSide::Bid, 10), 1))
+            .unwrap()
+            .as_slice()
+        {
+            [Event::Accepted { .. }, Event::Trade { trade, .. }] => assert_eq!(trade.price(), 10.into()),
+            events => panic!("unexpected events {events:?}"),
+        }
+        assert!(engine.orderbook().order(stop_id).is_some_and(|order| !order.is_stop()));
+        assert_eq!(
+            engine.balance("alice", "USDT"),
+            Balance {
+                available: 9_890.into(),
+                locked: 110.into()
+            }
+        );
+        assert_eq!(
+            engine.orderbook().depth(&OrderSide::Ask, 1),
+            vec![(500.into(), 10.into())]
+        );
+    }
+
+    #[rstest]
+    fn trailing_stop_bids_need_a_limit(mut engine: Engine) {
+        let trigger = StopTrigger::TrailingAmount {
+            stop_price: 12.into(),
+            offset: 2.into(),
+        };
+
+        // like any other buy stop, a trailing one needs a limit to bound the funds it locks (the sell side doesn't)
+        assert!(matches!(
+            engine.process(with_stop(market(create("alice", "1", OrderSide::Bid, 0)), trigger)),
+            Err(EngineError::AccountError(AccountError::UnpricedOrder(_)))
+        ));
+        assert_eq!(engine.balance("alice", "USDT").locked, 0.into());
+        assert!(
+            engine
+                .process(with_stop(create("alice", "1", OrderSide::Bid, 13), trigger))
+                .is_ok()
+        );
+        assert_eq!(engine.balance("alice", "USDT").locked, 130.into());
+        assert!(
+            engine
+                .process(with_stop(market(create("bob", "1", OrderSide::Ask, 0)), trigger))
+                .is_ok()
+        );
+        assert_eq!(engine.balance("bob", "ETH").locked, 10.into());
+    }
+
+    #[rstest]
+    fn circuit_breaker(engine: Engine) {
+        let bands = PriceBands {
+            dynamic_percent: Some(10.into()),
+            duration: 100,
+            ..Default::default()
+        };
+        let mut engine = engine.with_price_bands(bands);
+        let clock = |timestamp| OrderRequest::Clock { timestamp };
+        for (client_order_id, price) in [("1", 10), ("2", 9), ("3", 8)] {
+            assert!(
+                engine
+                    .process(create("alice", client_order_id, OrderSide::Bid, price))
+                    .is_ok()
+            );
+        }
+
+        // the first trade sets the reference, the sweep stops at 9 and the rest of the ask is cancelled
+        assert!(engine.process(create("bob", "1", OrderSide::Ask, 10)).is_ok());
+        match engine
+            .process(with_quantity(create("bob", "2", OrderSide::Ask, 5), 20))
+            .unwrap()
+            .as_slice()
+        {
+            [
+                Event::Accepted { .. },
+                Event::Trade { trade, .. },
+                Event::PhaseChanged {
+                    phase: TradingPhase::VolatilityAuction,
+                },
+            ] => assert_eq!(trade.price(), 9.into()),
+            events => panic!("unexpected events {events:?}"),
+        }
+        assert_eq!(engine.balance("bob", "ETH").locked, 0.into());
+        assert_eq!(engine.orderbook().depth(&OrderSide::Ask, 1), vec![]);
+        assert!(engine.process(create("bob", "3", OrderSide::Ask, 5)).is_ok());
+
+        // once the auction is over the book is uncrossed (the same volume from 5 to 8, the closest to the last trade)
+        assert_eq!(engine.process_admin(clock(50)).unwrap().len(), 0);
+        match engine.process_admin(clock(100)).unwrap().as_slice() {
+            [Event::PhaseChanged { .. }, Event::Uncrossed(auction), ..] => assert_eq!(auction.price, 8.into()),
+            events => panic!("unexpected events {events:?}"),
+        }
+        assert_eq!(engine.phase(), TradingPhase::Continuous);
+    }
+
+    #[rstest]
+    fn stop_at_band_on_phase_change(engine: Engine) {
+        let bands = PriceBands {
+            dynamic_percent: Some(10.into()),
+            ..Default::default()
+        };
+        let mut engine = engine.with_price_bands(bands);
+        let set_phase = |phase| OrderRequest::SetPhase { phase };
+        assert!(engine.process_admin(set_phase(TradingPhase::ClosingAuction)).is_ok());
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 10)).is_ok());
+        assert!(engine.process(create("bob", "1", OrderSide::Ask, 10)).is_ok());
+        assert!(engine.process(create("bob", "2", OrderSide::Ask, 12)).is_ok());
+        let stop = with_stop(
+            create("alice", "2", OrderSide::Bid, 13),
+            StopTrigger::Fixed { stop_price: 10.into() },
+        );
+        let stop_id = accepted(&engine.process(stop).unwrap());
+        assert!(engine.process_admin(set_phase(TradingPhase::PostClose)).is_ok());
+
+        // the stop waiting for the book to match is stopped at the band (9 to 11) and trips the breaker right away
+        match engine
+            .process_admin(set_phase(TradingPhase::Continuous))
+            .unwrap()
+            .as_slice()
+        {
+            [
+                Event::PhaseChanged {
+                    phase: TradingPhase::Continuous,
+                },
+                Event::PhaseChanged {
+                    phase: TradingPhase::VolatilityAuction,
+                },
+            ] => (),
+            events => panic!("unexpected events {events:?}"),
+        }
+        assert!(engine.orderbook().order(stop_id).is_none());
+        assert_eq!(engine.balance("alice", "USDT").locked, 0.into());
+
+        // the next order doesn't find the breach still there
+        match engine
+            .process(create("alice", "3", OrderSide::Bid, 5))
+            .unwrap()
+            .as_slice()
+        {
+            [Event::Accepted { .. }] => (),
+            events => panic!("unexpected events {events:?}"),
+        }
+        assert_eq!(engine.phase(), TradingPhase::VolatilityAuction);
+    }
+
+    #[rstest]
+    fn block_trade(mut engine: Engine) {
+        let request_quote = OrderRequest::RequestQuote {
+            account_id: "alice".into(),
+            rfq_id: "rfq".into(),
+            side: OrderSide::Bid,
+            quantity: 50.into(),
+        };
+        let quote = |account_id: &str, quote_id: &str, price: u64| OrderRequest::Quote {
+            account_id: account_id.into(),
+            rfq_id: "rfq".into(),
+            quote_id: quote_id.into(),
+            price: price.into(),
+        };
+        let accept_quote = |account_id: &str, quote_id: &str| OrderRequest::AcceptQuote {
+            account_id: account_id.into(),
+            rfq_id: "rfq".into(),
+            quote_id: quote_id.into(),
+        };
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 9)).is_ok());
+        assert!(engine.process(create("bob", "1", OrderSide::Ask, 12)).is_ok());
+        assert!(engine.process_admin(deposit("carol", "ETH", 50)).is_ok());
+
+        // bob and carol quote the requested 50 ETH
+        assert!(engine.process(request_quote).is_ok());
+        assert!(engine.process(quote("bob", "q1", 13)).is_ok());
+        assert!(engine.process(quote("carol", "q2", 11)).is_ok());
+        assert_eq!(engine.balance("bob", "ETH").locked, 60.into());
+        assert!(matches!(
+            engine.process(quote("alice", "q3", 10)),
+            Err(EngineError::RfqError(RfqError::OwnRfq(_)))
+        ));
+
+        // only alice can accept and bob's quote is above the best ask
+        assert!(matches!(
+            engine.process(accept_quote("bob", "q2")),
+            Err(EngineError::RfqError(RfqError::RfqNotFound(_)))
+        ));
+        assert!(matches!(
+            engine.process(accept_quote("alice", "q1")),
+            Err(EngineError::RfqError(RfqError::OutsideBand { .. }))
+        ));
+
+        // nor can she without the funds, the quotes are still there once she has them
+        let withdraw = OrderRequest::Withdraw {
+            account_id: "alice".into(),
+            asset: "USDT".into(),
+            amount: 9_500.into(),
+        };
+        assert!(engine.process(withdraw).is_ok());
+        assert!(matches!(
+            engine.process(accept_quote("alice", "q2")),
+            Err(EngineError::AccountError(AccountError::InsufficientFunds { .. }))
+        ));
+        assert_eq!(engine.balance("bob", "ETH").locked, 60.into());
+        assert!(engine.process_admin(deposit("alice", "USDT", 9_500)).is_ok());
+
+        // the block trade settles without touching the book and releases the other quotes
+        match engine.process(accept_quote("alice", "q2")).unwrap().as_slice() {
+            [Event::Trade { trade, .. }] => {
+                assert_eq!(trade.price(), 11.into());
+                assert_eq!(trade.quantity(), 50.into());
+            }
+            events => panic!("unexpected events {events:?}"),
+        }
+        assert_eq!(engine.balance("alice", "ETH").available, 150.into());
+        assert_eq!(engine.balance("alice", "USDT").available, 9_360.into());
+        assert_eq!(engine.balance("carol", "USDT").available, 550.into());
+        assert_eq!(engine.balance("bob", "ETH").locked, 10.into());
+        assert_eq!(engine.position("carol").quantity, (-50).into());
+        assert_eq!(
+            engine.orderbook().depth(&OrderSide::Ask, 1),
+            vec![(12.into(), 10.into())]
+        );
+        assert_eq!(engine.orderbook().last_trade_price(), None);
+        assert!(engine.process(accept_quote("alice", "q2")).is_err());
+    }
+
+    #[rstest]
+    fn withdraw_quotes(mut engine: Engine) {
+        let request_quote = OrderRequest::RequestQuote {
+            account_id: "alice".into(),
+            rfq_id: "rfq".into(),
+            side: OrderSide::Bid,
+            quantity: 50.into(),
+        };
+        let quote = |account_id: &str, quote_id: &str| OrderRequest::Quote {
+            account_id: account_id.into(),
+            rfq_id: "rfq".into(),
+            quote_id: quote_id.into(),
+            price: 11.into(),
+        };
+        let withdraw_quote = |account_id: &str, quote_id: &str| OrderRequest::WithdrawQuote {
+            account_id: account_id.into(),
+            rfq_id: "rfq".into(),
+            quote_id: quote_id.into(),
+        };
+        let cancel_request = |account_id: &str| OrderRequest::CancelQuoteRequest {
+            account_id: account_id.into(),
+            rfq_id: "rfq".into(),
+        };
+        assert!(engine.process_admin(deposit("carol", "ETH", 50)).is_ok());
+        assert!(engine.process(request_quote).is_ok());
+        assert!(engine.process(quote("bob", "q1")).is_ok());
+        assert!(engine.process(quote("carol", "q2")).is_ok());
+        assert_eq!(engine.balance("bob", "ETH").locked, 50.into());
+
+        // only bob can pull his quote, which releases his funds
+        assert!(matches!(
+            engine.process(withdraw_quote("carol", "q1")),
+            Err(EngineError::RfqError(RfqError::QuoteNotFound { .. }))
+        ));
+        assert!(matches!(
+            engine.process(withdraw_quote("bob", "q1")).unwrap().as_slice(),
+            [Event::QuoteWithdrawn { .. }]
+        ));
+        assert_eq!(engine.balance("bob", "ETH").locked, 0.into());
+        assert!(engine.process(withdraw_quote("bob", "q1")).is_err());
+
+        // only alice can cancel the request, which releases the quotes still there
+        assert!(matches!(
+            engine.process(cancel_request("carol")),
+            Err(EngineError::RfqError(RfqError::RfqNotFound(_)))
+        ));
+        assert!(matches!(
+            engine.process(cancel_request("alice")).unwrap().as_slice(),
+            [Event::QuoteRequestCancelled { .. }]
+        ));
+        assert_eq!(engine.balance("carol", "ETH").locked, 0.into());
+        assert_eq!(engine.balance("carol", "ETH").available, 50.into());
+        assert!(engine.process(quote("bob", "q3")).is_err());
+    }
+
+    #[rstest]
+    fn duplicated_client_order_id(mut engine: Engine) {
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 10)).is_ok());
+        assert!(matches!(
+            engine.process(create("alice", "1", OrderSide::Bid, 10)),
+            Err(EngineError::ClientOrderIdDuplicated { .. })
+        ));
+
+        // once the order is no longer live the client order id can be reused
+        assert!(engine.process(cancel("alice", "1")).is_ok());
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 10)).is_ok());
+        assert!(matches!(
+            engine.process(cancel("bob", "1")),
+            Err(EngineError::ClientOrderIdNotFound { .. })
+        ));
+    }
+
+    #[rstest]
+    fn closed_client_order_id_pruned(mut engine: Engine) {
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 10)).is_ok());
+        assert!(engine.process(create("alice", "2", OrderSide::Bid, 9)).is_ok());
+        assert!(engine.process(create("bob", "1", OrderSide::Ask, 10)).is_ok());
+        assert!(engine.process(cancel("alice", "2")).is_ok());
+
+        // neither the filled orders nor the canceled one keep their client order id around
+        assert!(engine.client_orders.values().all(IndexMap::is_empty));
+        assert!(engine.client_order_ids.is_empty());
+        assert!(matches!(
+            engine.process(cancel("alice", "1")),
+            Err(EngineError::ClientOrderIdNotFound { .. })
+        ));
+    }
+
+    #[rstest]
+    fn tick_and_lot_sizes(engine: Engine) {
+        let instrument = InstrumentSpec::new(5.into(), 2.into()).unwrap();
+        let mut engine = engine.with_instrument(instrument);
+        assert!(matches!(
+            engine.process(create("alice", "1", OrderSide::Bid, 12)),
+            Err(EngineError::InstrumentError(InstrumentError::OffTick { .. }))
+        ));
+
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 10)).is_ok());
+
+        // 3 is not a whole number of lots
+        let request = OrderRequest::RequestQuote {
+            account_id: "alice".into(),
+            rfq_id: "rfq".into(),
+            side: OrderSide::Bid,
+            quantity: 3.into(),
+        };
+        assert!(matches!(
+            engine.process(request),
+            Err(EngineError::InstrumentError(InstrumentError::OffLot { .. }))
+        ));
+
+        // prices off the tick grid are rejected whatever the request (ahead of the engine too)
+        let validator = engine.validator();
+        let mass_cancel = OrderRequest::MassCancel {
+            account_id: Some("alice".into()),
+            side: None,
+            min_price: Some(12.into()),
+            max_price: None,
+        };
+        let reference_price = OrderRequest::SetReferencePrice { price: 12.into() };
+        for (order_request, admin) in [(mass_cancel, false), (reference_price, true)] {
+            assert!(matches!(
+                validator.validate(&order_request),
+                Err(EngineError::InstrumentError(InstrumentError::OffTick { .. }))
+            ));
+            let processed = match admin {
+                true => engine.process_admin(order_request),
+                false => engine.process(order_request),
+            };
+            assert!(matches!(
+                processed,
+                Err(EngineError::InstrumentError(InstrumentError::OffTick { .. }))
+            ));
+        }
+        assert_eq!(
+            engine.orderbook().depth(&OrderSide::Bid, 1),
+            vec![(10.into(), 10.into())]
+        );
+    }
+
+    #[rstest]
+    fn bounded_price_range(engine: Engine) {
+        let instrument = InstrumentSpec::new(1.into(), 1.into())
+            .and_then(|instrument| instrument.with_price_range(1.into(), 100.into()))
+            .unwrap();
+        let mut engine = engine.with_instrument(instrument);
+        assert!(matches!(
+            engine.process(create("alice", "1", OrderSide::Bid, 101)),
+            Err(EngineError::InstrumentError(InstrumentError::OutOfRange { .. }))
+        ));
+
+        // the book is laid out on the range and matches as usual
+        assert!(engine.process(create("alice", "1", OrderSide::Bid, 100)).is_ok());
+        assert!(engine.process(create("bob", "1", OrderSide::Ask, 1)).is_ok());
+        assert_eq!(engine.orderbook().next_trade_id(), 2.into());
+    }
+
+    #[rstest]
+    fn invalid_stop_triggers(engine: Engine) {
+        let instrument = InstrumentSpec::new(1.into(), 1.into())
+            .and_then(|instrument| instrument.with_price_range(1.into(), 100.into()))
+            .unwrap();
+        let mut engine = engine.with_instrument(instrument);
+        let validator = engine.validator();
+        let stop =
+            |client_order_id: &str, trigger| with_stop(create("alice", client_order_id, OrderSide::Ask, 9), trigger);
+        let half: OrderPrice = Decimal::new(5, 1).try_into().unwrap();
+
+        // rejected the same ahead of the engine and by the engine itself
+        for (client_order_id, trigger) in [
+            (
+                "1",
+                StopTrigger::Fixed {
+                    stop_price: OrderPrice::ZERO,
+                },
+            ),
+            ("2", StopTrigger::Fixed { stop_price: half }),
+            ("3", StopTrigger::Fixed { stop_price: 101.into() }),
+            (
+                "4",
+                StopTrigger::TrailingAmount {
+                    stop_price: 10.into(),
+                    offset: OrderPrice::ZERO,
+                },
+            ),
+            (
+                "5",
+                StopTrigger::TrailingAmount {
+                    stop_price: 10.into(),
+                    offset: half,
+                },
+            ),
+            (
+                "6",
+                StopTrigger::TrailingPercent {
+                    stop_price: 10.into(),
+                    percent: Decimal::ZERO,
+                },
+            ),
+            (
+                "7",
+                StopTrigger::TrailingPercent {
+                    stop_price: 10.into(),
+                    percent: Decimal::ONE_HUNDRED,
+                },
+            ),
+        ] {
+            let expected = instrument.check_trigger(&trigger).unwrap_err();
+            let order_request = stop(client_order_id, trigger);
+            assert!(
+                matches!(validator.validate(&order_request), Err(EngineError::InstrumentError(error)) if error == expected),
+                "{trigger:?}"
+            );
+            assert!(
+                matches!(engine.process(order_request), Err(EngineError::InstrumentError(error)) if error == expected),
+                "{trigger:?}"
+            );
+        }
+        assert_eq!(engine.balance("alice", "ETH").locked, 0.into());
+
+        let trigger = StopTrigger::TrailingPercent {
+            stop_price: 10.into(),
+            percent: 5.into(),
+        };
+        assert!(validator.validate(&stop("8", trigger)).is_ok());
+        assert!(engine.process(stop("8", trigger)).is_ok());
+    }
+
+    #[rstest]
+    fn quantity_overflow(mut engine: Engine) {
+        engine
+            .process_admin(deposit("alice", "USDT", 1_000_000_000_000))
+            .unwrap();
+        let bid = |client_order_id: &str, price| {
+            with_quantity(create("alice", client_order_id, OrderSide::Bid, price), 50_000_000_000)
+        };
+        assert!(engine.process(bid("1", 1)).is_ok());
+
+        // the level can't hold both, the second one is rejected and its funds released
+        assert!(matches!(
+            engine.process(bid("2", 1)),
+            Err(EngineError::OrderbookError(OrderbookError::QuantityOverflow(_)))
+        ));
+        assert_eq!(
+            engine.orderbook().depth(&OrderSide::Bid, 5),
+            [(1.into(), OrderQuantity::try_from(50_000_000_000_i64).unwrap())]
+        );
+        assert_eq!(
+            engine.balance("alice", "USDT").locked,
+            Decimal::from(50_000_000_000_u64)
+        );
+
+        // nor can a notional out of the fixed-point range enter at all
+        assert!(matches!(
+            engine.process(bid("3", 2)),
+            Err(EngineError::InstrumentError(InstrumentError::NotionalOverflow { .. }))
+        ));
+    }
+
+    #[rstest]
+    fn quantity_overflow_after_trading(mut engine: Engine) {
+        engine
+            .process_admin(deposit("alice", "USDT", 1_000_000_000_000))
+            .unwrap();
+        assert!(
+            engine
+                .process(with_quantity(create("alice", "1", OrderSide::Bid, 1), 50_000_000_000))
+                .is_ok()
+        );
+        assert!(engine.process(create("bob", "1", OrderSide::Ask, 10)).is_ok());
+        let stop = with_stop(
+            with_quantity(create("alice", "2", OrderSide::Bid, 1), 50_000_000_000),
+            StopTrigger::Fixed { stop_price: 10.into() },
+        );
+        let stop_id = accepted(&engine.process(stop).unwrap());
+
+        // the bid trades and rests before the stop it triggers can't fit in its level, only the stop is dropped
+        // and the trade is published along with it
+        match engine
+            .process(with_quantity(create("alice", "3", OrderSide::Bid, 10), 15))
+            .unwrap()
+            .as_slice()
+        {
+            [
+                Event::Accepted { .. },
+                Event::Trade { trade, .. },
+                Event::Cancelled(order),
+            ] => {
+                assert_eq!((trade.price(), trade.quantity()), (10.into(), 10.into()));
+                assert_eq!(order.id(), stop_id);
+            }
+            events => panic!("unexpected events {events:?}"),
+        }
+        assert!(!engine.orderbook().contains(stop_id));
+        assert_eq!(engine.orderbook().last_trade_price(), Some(10.into()));
+        assert_eq!(
+            engine.orderbook().depth(&OrderSide::Bid, 5),
+            [
+                (10.into(), 5.into()),
+                (1.into(), OrderQuantity::try_from(50_000_000_000_i64).unwrap())
+            ]
+        );
+        assert_eq!(
+            engine.balance("alice", "USDT").locked,
+            Decimal::from(50_000_000_050_u64)
+        );
+        assert_eq!(engine.balance("alice", "ETH").available, 110.into());
+        assert_eq!(engine.balance("bob", "USDT").available, 10_100.into());
+    }
+
+    #[rstest]
+    fn batch_same_as_sequential(mut engine: Engine) {
+        let mut order_requests: Vec<OrderRequest> = generate(1..=300).collect();
+        order_requests.extend([
+            create("alice", "1", OrderSide::Bid, 10),
+            create("alice", "1", OrderSide::Bid, 10), // client order id in use
+            create("bob", "1", OrderSide::Ask, 10),
+            cancel("alice", "2"), // unknown client order id
+        ]);
+
+        let mut sequential = self::engine();
+        let (mut expected, mut expected_errors) = (vec![], vec![]);
+        for (idx, order_request) in order_requests.iter().enumerate() {
+            match sequential.process_admin(order_request.clone()) {
+                Ok(events) => expected.extend(events),
+                Err(error) => expected_errors.push((idx, error.to_string())),
+            }
+        }
+
+        // whatever the size of the batches
+        let (mut events, mut errors, mut failed) = (vec![], vec![], vec![]);
+        for (batch, order_requests) in order_requests.chunks(7).enumerate() {
+            engine.process_admin_batch(order_requests.to_vec(), &mut events, &mut failed);
+            errors.extend(
+                failed
+                    .drain(..)
+                    .map(|(idx, error)| (batch * 7 + idx, error.to_string())),
+            );
+        }
+        assert!(errors.len() >= 2);
+        assert_eq!(errors, expected_errors);
+        assert_eq!(
+            serde_json::to_string(&events).unwrap(),
+            serde_json::to_string(&expected).unwrap()
+        );
+        assert_eq!(engine.snapshot(), sequential.snapshot());
+    }
 }
@@ -0,0 +1,90 @@
+use std::fmt::Display;
+
+use compact_str::CompactString;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auction::AuctionResult,
+    fee::Fee,
+    order::{Order, OrderId, OrderPrice, OrderQuantity, OrderSide},
+    phase::TradingPhase,
+    trade::Trade,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE", tag = "event")]
+pub enum Event {
+    // the engine order id assigned to the client order id
+    Accepted {
+        order_id: OrderId,
+        client_order_id: CompactString,
+    },
+    Trade {
+        trade: Trade,
+        taker_fee: Fee,
+        maker_fee: Fee,
+    },
+    Cancelled(Order),
+    #[serde(rename = "PHASE_CHANGED")]
+    PhaseChanged {
+        phase: TradingPhase,
+    },
+    Indicative(AuctionResult),
+    Uncrossed(AuctionResult),
+    #[serde(rename = "QUOTE_REQUESTED")]
+    QuoteRequested {
+        rfq_id: CompactString,
+        side: OrderSide,
+        quantity: OrderQuantity,
+    },
+    Quoted {
+        rfq_id: CompactString,
+        quote_id: CompactString,
+        price: OrderPrice,
+    },
+    #[serde(rename = "QUOTE_REQUEST_CANCELLED")]
+    QuoteRequestCancelled {
+        rfq_id: CompactString,
+    },
+    #[serde(rename = "QUOTE_WITHDRAWN")]
+    QuoteWithdrawn {
+        rfq_id: CompactString,
+        quote_id: CompactString,
+    },
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::Accepted {
+                order_id,
+                client_order_id,
+            } => write!(f, "ACCEPTED client_order_id:{client_order_id} {order_id}"),
+            Event::Trade {
+                trade,
+                taker_fee,
+                maker_fee,
+            } => write!(
+                f,
+                "{trade} fees:[taker:{} {}|maker:{} {}]",
+                taker_fee.amount, taker_fee.asset, maker_fee.amount, maker_fee.asset
+            ),
+            Event::Cancelled(order) => write!(f, "CANCELLED {order}"),
+            Event::PhaseChanged { phase } => write!(f, "PHASE_CHANGED {phase}"),
+            Event::Indicative(auction) => write!(f, "INDICATIVE {}@{}", auction.volume, auction.price),
+            Event::Uncrossed(auction) => write!(f, "UNCROSSED {}@{}", auction.volume, auction.price),
+            Event::QuoteRequested { rfq_id, side, quantity } => {
+                write!(f, "QUOTE_REQUESTED rfq_id:{rfq_id} {side} {quantity}")
+            }
+            Event::Quoted {
+                rfq_id,
+                quote_id,
+                price,
+            } => write!(f, "QUOTED rfq_id:{rfq_id} quote_id:{quote_id} @{price}"),
+            Event::QuoteRequestCancelled { rfq_id } => write!(f, "QUOTE_REQUEST_CANCELLED rfq_id:{rfq_id}"),
+            Event::QuoteWithdrawn { rfq_id, quote_id } => {
+                write!(f, "QUOTE_WITHDRAWN rfq_id:{rfq_id} quote_id:{quote_id}")
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{account::Asset, order::AccountId};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+// rates as a fraction of the amount received (e.g. 0.001 for 10 bps), a negative maker rate is a rebate
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FeeRates {
+    pub maker: Decimal,
+    pub taker: Decimal,
+}
+
+impl FeeRates {
+    #[inline]
+    pub fn new(maker: Decimal, taker: Decimal) -> Self {
+        Self { maker, taker }
+    }
+
+    #[inline]
+    pub fn rate(&self, liquidity: Liquidity) -> Decimal {
+        match liquidity {
+            Liquidity::Maker => self.maker,
+            Liquidity::Taker => self.taker,
+        }
+    }
+}
+
+// fee charged (or rebate paid if negative) on one side of a trade
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Fee {
+    pub asset: Asset,
+    pub amount: Decimal,
+}
+
+// fee rates of an instrument, accounts without a tier pay the default rates
+#[derive(Clone, Debug, Default)]
+pub struct FeeSchedule {
+    default_rates: FeeRates,
+    tiers: IndexMap<CompactString, FeeRates>,
+    account_tiers: IndexMap<AccountId, CompactString>,
+}
+
+impl FeeSchedule {
+    #[inline]
+    pub fn new(default_rates: FeeRates) -> Self {
+        Self {
+            default_rates,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn with_tier(mut self, tier: &str, rates: FeeRates) -> Self {
+        self.tiers.insert(tier.into(), rates);
+        self
+    }
+
+    #[inline]
+    pub fn has_tier(&self, tier: &str) -> bool {
+        self.tiers.contains_key(tier)
+    }
+
+    #[inline]
+    pub fn assign_tier(&mut self, account: AccountId, tier: CompactString) {
+        self.account_tiers.insert(account, tier);
+    }
+
+    #[inline]
+    pub fn rates(&self, account: AccountId) -> FeeRates {
+        self.account_tiers
+            .get(&account)
+            .and_then(|tier| self.tiers.get(tier))
+            .copied()
+            .unwrap_or(self.default_rates)
+    }
+}
@@ -0,0 +1,333 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    order::{FixedPointError, OrderPrice, OrderQuantity, StopTrigger},
+    orderbook::{MAX_LADDER_TICKS, PriceRange},
+};
+
+// tick and lot sizes of an instrument, prices and quantities entering the engine are converted from decimals
+// and must be whole multiples of them (by default the finest steps the fixed-point scale allows)
+// instruments with bounded prices (min and max included) keep their book in arrays indexed by tick
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InstrumentSpec {
+    pub tick_size: OrderPrice,
+    pub lot_size: OrderQuantity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_range: Option<(OrderPrice, OrderPrice)>,
+}
+
+impl Default for InstrumentSpec {
+    fn default() -> Self {
+        Self {
+            tick_size: OrderPrice::from_raw(1),
+            lot_size: OrderQuantity::from_raw(1),
+            price_range: None,
+        }
+    }
+}
+
+impl InstrumentSpec {
+    #[inline]
+    pub fn new(tick_size: Decimal, lot_size: Decimal) -> Result<Self, InstrumentError> {
+        if tick_size <= Decimal::ZERO {
+            return Err(InstrumentError::InvalidTickSize(tick_size));
+        }
+        if lot_size <= Decimal::ZERO {
+            return Err(InstrumentError::InvalidLotSize(lot_size));
+        }
+        Ok(Self {
+            tick_size: tick_size.try_into()?,
+            lot_size: lot_size.try_into()?,
+            price_range: None,
+        })
+    }
+
+    // at most MAX_LADDER_TICKS ticks, one level of each side is preallocated per tick
+    #[inline]
+    pub fn with_price_range(mut self, min_price: Decimal, max_price: Decimal) -> Result<Self, InstrumentError> {
+        let (min, max) = (self.price(min_price)?, self.price(max_price)?);
+        if min > max || min <= OrderPrice::ZERO {
+            return Err(InstrumentError::InvalidPriceRange { min_price, max_price });
+        }
+        self.price_range = Some((min, max));
+        if self.ladder_range().is_none() {
+            return Err(InstrumentError::TooManyTicks { min_price, max_price });
+        }
+        Ok(self)
+    }
+
+    // the range the book is laid out on, if the prices are bounded (and the range can be laid out, the fields of
+    // a deserialized instrument haven't been checked)
+    #[inline]
+    pub fn ladder_range(&self) -> Option<PriceRange> {
+        let (min_price, max_price) = self.price_range?;
+        let range = PriceRange {
+            min_price,
+            max_price,
+            tick_size: self.tick_size,
+        };
+        range.ticks().map(|_| range)
+    }
+
+    #[inline]
+    pub fn price(&self, price: Decimal) -> Result<OrderPrice, InstrumentError> {
+        let fixed = OrderPrice::try_from(price)?;
+        if !fixed.is_multiple_of(self.tick_size) {
+            return Err(InstrumentError::OffTick {
+                price,
+                tick_size: self.tick_size,
+            });
+        }
+        if let Some((min, max)) = self.price_range
+            && !(min..=max).contains(&fixed)
+        {
+            return Err(InstrumentError::OutOfRange { price, min, max });
+        }
+        Ok(fixed)
+    }
+
+    #[inline]
+    pub fn quantity(&self, quantity: Decimal) -> Result<OrderQuantity, InstrumentError> {
+        let fixed = OrderQuantity::try_from(quantity)?;
+        if !fixed.is_multiple_of(self.lot_size) {
+            return Err(InstrumentError::OffLot {
+                quantity,
+                lot_size: self.lot_size,
+            });
+        }
+        Ok(fixed)
+    }
+
+    // stop prices are checked as any other price (and must be positive), trailing offsets must be a whole number
+    // of ticks and trailing percents within (0, 100), otherwise the stop would trigger right away or never
+    #[inline]
+    pub fn check_trigger(&self, trigger: &StopTrigger) -> Result<(), InstrumentError> {
+        let stop_price = trigger.stop_price();
+        if stop_price <= OrderPrice::ZERO {
+            return Err(InstrumentError::InvalidStopPrice(stop_price));
+        }
+        self.price(stop_price.to_decimal())?;
+        match *trigger {
+            StopTrigger::Fixed { .. } => (),
+            StopTrigger::TrailingAmount { offset, .. } => {
+                if offset <= OrderPrice::ZERO || !offset.is_multiple_of(self.tick_size) {
+                    return Err(InstrumentError::InvalidStopOffset {
+                        offset,
+                        tick_size: self.tick_size,
+                    });
+                }
+            }
+            StopTrigger::TrailingPercent { percent, .. } => {
+                if percent <= Decimal::ZERO || percent >= Decimal::ONE_HUNDRED {
+                    return Err(InstrumentError::InvalidStopPercent(percent));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // the notional of an order (price times quantity) must be in the fixed-point range, the book sums quantities
+    // and costs of orders without checking them one by one
+    #[inline]
+    pub fn check_notional(&self, price: OrderPrice, quantity: OrderQuantity) -> Result<(), InstrumentError> {
+        let notional = i128::from(price.raw()) * i128::from(quantity.raw()) / i128::from(OrderPrice::ONE.raw());
+        if notional > i128::from(i64::MAX) {
+            return Err(InstrumentError::NotionalOverflow { price, quantity });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum InstrumentError {
+    #[error("price not a multiple of the tick size (price={}, tick_size={})", .price, .tick_size)]
+    OffTick { price: Decimal, tick_size: OrderPrice },
+    #[error("quantity not a multiple of the lot size (quantity={}, lot_size={})", .quantity, .lot_size)]
+    OffLot { quantity: Decimal, lot_size: OrderQuantity },
+    #[error("price out of the range of the instrument (price={}, min={}, max={})", .price, .min, .max)]
+    OutOfRange {
+        price: Decimal,
+        min: OrderPrice,
+        max: OrderPrice,
+    },
+    #[error("notional out of the fixed-point range (price={}, quantity={})", .price, .quantity)]
+    NotionalOverflow { price: OrderPrice, quantity: OrderQuantity },
+    #[error("invalid price range (min={}, max={})", .min_price, .max_price)]
+    InvalidPriceRange { min_price: Decimal, max_price: Decimal },
+    #[error("price range with more than {} ticks (min={}, max={})", MAX_LADDER_TICKS, .min_price, .max_price)]
+    TooManyTicks { min_price: Decimal, max_price: Decimal },
+    #[error("stop price must be positive! {0}")]
+    InvalidStopPrice(OrderPrice),
+    #[error("trailing offset must be a positive multiple of the tick size (offset={}, tick_size={})", .offset, .tick_size)]
+    InvalidStopOffset { offset: OrderPrice, tick_size: OrderPrice },
+    #[error("trailing percent must be above 0 and below 100! {0}")]
+    InvalidStopPercent(Decimal),
+    #[error("tick size must be positive! {0}")]
+    InvalidTickSize(Decimal),
+    #[error("lot size must be positive! {0}")]
+    InvalidLotSize(Decimal),
+    #[error("fixed-point error: {0}")]
+    FixedPointError(#[from] FixedPointError),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    fn ticks_and_lots() {
+        let spec = InstrumentSpec::new(Decimal::new(5, 2), Decimal::new(1, 1)).unwrap();
+        assert_eq!(
+            spec.price(Decimal::new(1015, 2)),
+            Ok(OrderPrice::from_raw(1_015_000_000))
+        );
+        assert!(matches!(
+            spec.price(Decimal::new(1012, 2)),
+            Err(InstrumentError::OffTick { .. })
+        ));
+        assert_eq!(
+            spec.quantity(Decimal::new(25, 1)),
+            Ok(OrderQuantity::from_raw(250_000_000))
+        );
+        assert!(matches!(
+            spec.quantity(Decimal::new(25, 2)),
+            Err(InstrumentError::OffLot { .. })
+        ));
+
+        // finer than the fixed-point scale or out of its range
+        assert_eq!(
+            InstrumentSpec::default().price(Decimal::new(1, 9)),
+            Err(FixedPointError::Precision(Decimal::new(1, 9)).into())
+        );
+        assert_eq!(
+            InstrumentSpec::default().quantity(Decimal::from(i64::MAX)),
+            Err(FixedPointError::Overflow(Decimal::from(i64::MAX)).into())
+        );
+    }
+
+    #[rstest]
+    fn price_range() {
+        let spec = InstrumentSpec::new(Decimal::new(5, 1), Decimal::ONE)
+            .unwrap()
+            .with_price_range(10.into(), 20.into())
+            .unwrap();
+        assert!(spec.price(Decimal::new(205, 1)).is_err());
+        assert!(matches!(
+            spec.price(Decimal::new(95, 1)),
+            Err(InstrumentError::OutOfRange { .. })
+        ));
+        assert_eq!(spec.price(20.into()), Ok(OrderPrice::from(20)));
+        assert_eq!(
+            spec.ladder_range(),
+            Some(PriceRange {
+                min_price: 10.into(),
+                max_price: 20.into(),
+                tick_size: spec.tick_size,
+            })
+        );
+
+        // the bounds must be on the tick grid and in order
+        assert!(matches!(
+            spec.with_price_range(20.into(), 10.into()),
+            Err(InstrumentError::InvalidPriceRange { .. })
+        ));
+        assert!(matches!(
+            spec.with_price_range(Decimal::new(101, 1), 20.into()),
+            Err(InstrumentError::OffTick { .. })
+        ));
+        assert!(InstrumentSpec::default().ladder_range().is_none());
+
+        // one level per tick is preallocated, too many ticks are rejected
+        assert!(matches!(
+            InstrumentSpec::default().with_price_range(1.into(), 1_000.into()),
+            Err(InstrumentError::TooManyTicks { .. })
+        ));
+        assert!(matches!(
+            InstrumentSpec::new(Decimal::ZERO, Decimal::ONE),
+            Err(InstrumentError::InvalidTickSize(_))
+        ));
+        assert!(matches!(
+            InstrumentSpec::new(Decimal::ONE, Decimal::NEGATIVE_ONE),
+            Err(InstrumentError::InvalidLotSize(_))
+        ));
+
+        // nor are the fields of a deserialized instrument trusted
+        let spec: InstrumentSpec =
+            serde_json::from_str(r#"{"tick_size": 0, "lot_size": 1, "price_range": [1, 10]}"#).unwrap();
+        assert!(spec.ladder_range().is_none());
+    }
+
+    #[rstest]
+    fn notional() {
+        let spec = InstrumentSpec::default();
+        let max = OrderQuantity::from_raw(i64::MAX);
+        assert_eq!(spec.check_notional(OrderPrice::ONE, max), Ok(()));
+        assert_eq!(
+            spec.check_notional(2.into(), max),
+            Err(InstrumentError::NotionalOverflow {
+                price: 2.into(),
+                quantity: max,
+            })
+        );
+        assert_eq!(spec.check_notional(Decimal::new(5, 1).try_into().unwrap(), max), Ok(()));
+    }
+
+    #[rstest]
+    fn stop_triggers() {
+        let spec = InstrumentSpec::new(Decimal::new(5, 1), Decimal::ONE)
+            .unwrap()
+            .with_price_range(10.into(), 20.into())
+            .unwrap();
+        let fixed = |stop_price: OrderPrice| StopTrigger::Fixed { stop_price };
+        let trailing_amount = |offset: OrderPrice| StopTrigger::TrailingAmount {
+            stop_price: 15.into(),
+            offset,
+        };
+        let trailing_percent = |percent: Decimal| StopTrigger::TrailingPercent {
+            stop_price: 15.into(),
+            percent,
+        };
+        assert_eq!(spec.check_trigger(&fixed(15.into())), Ok(()));
+        assert_eq!(
+            spec.check_trigger(&trailing_amount(Decimal::new(15, 1).try_into().unwrap())),
+            Ok(())
+        );
+        assert_eq!(spec.check_trigger(&trailing_percent(Decimal::new(5, 1))), Ok(()));
+
+        // the stop price is a price like any other
+        assert_eq!(
+            spec.check_trigger(&fixed(OrderPrice::ZERO)),
+            Err(InstrumentError::InvalidStopPrice(OrderPrice::ZERO))
+        );
+        assert!(matches!(
+            spec.check_trigger(&fixed(Decimal::new(152, 1).try_into().unwrap())),
+            Err(InstrumentError::OffTick { .. })
+        ));
+        assert!(matches!(
+            spec.check_trigger(&fixed(21.into())),
+            Err(InstrumentError::OutOfRange { .. })
+        ));
+
+        // trailing by whole ticks or by a percent that neither triggers right away nor never
+        for offset in [
+            OrderPrice::ZERO,
+            OrderPrice::ZERO.saturating_sub(1.into()),
+            Decimal::new(2, 1).try_into().unwrap(),
+        ] {
+            assert!(matches!(
+                spec.check_trigger(&trailing_amount(offset)),
+                Err(InstrumentError::InvalidStopOffset { .. })
+            ));
+        }
+        for percent in [Decimal::ZERO, Decimal::NEGATIVE_ONE, Decimal::ONE_HUNDRED] {
+            assert_eq!(
+                spec.check_trigger(&trailing_percent(percent)),
+                Err(InstrumentError::InvalidStopPercent(percent))
+            );
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use hdrhistogram::Histogram;
+use tracing::info;
+
+// highest latency tracked (in nanoseconds), anything slower is recorded as this
+const MAX_LATENCY: u64 = 60_000_000_000;
+// precision of the histograms
+const SIGNIFICANT_DIGITS: u8 = 3;
+
+// what is measured for every request going through the pipeline
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Measure {
+    Decode,  // parsing the json line
+    Queue,   // from decoded to picked by the engine (the validation and the rings in between)
+    Match,   // the engine itself
+    Publish, // writing the events out, each request takes the time of its batch (their events are written together)
+}
+
+impl Measure {
+    pub const ALL: [Measure; 4] = [Measure::Decode, Measure::Queue, Measure::Match, Measure::Publish];
+}
+
+impl Display for Measure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Measure::Decode => "decode",
+            Measure::Queue => "queue",
+            Measure::Match => "match",
+            Measure::Publish => "publish",
+        };
+        write!(f, "{name}")
+    }
+}
+
+// one histogram of latencies (in nanoseconds) per measure
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histograms([Histogram<u64>; 4]);
+
+impl Default for Histograms {
+    fn default() -> Self {
+        Self(std::array::from_fn(|_| {
+            Histogram::new_with_bounds(1, MAX_LATENCY, SIGNIFICANT_DIGITS).expect("valid histogram bounds")
+        }))
+    }
+}
+
+impl Histograms {
+    #[inline]
+    pub fn record(&mut self, measure: Measure, latency: Duration) {
+        self.record_n(measure, latency, 1);
+    }
+
+    // the same latency for a number of requests
+    #[inline]
+    pub fn record_n(&mut self, measure: Measure, latency: Duration, count: u64) {
+        self.0[measure as usize].saturating_record_n(latency.as_nanos().try_into().unwrap_or(u64::MAX), count);
+    }
+
+    // all the latencies of the other histograms
+    #[inline]
+    pub fn merge(&mut self, other: &Histograms) {
+        for (histogram, other) in self.0.iter_mut().zip(&other.0) {
+            histogram.add(other).expect("histograms with the same bounds");
+        }
+    }
+
+    #[inline]
+    pub fn reset(&mut self) {
+        self.0.iter_mut().for_each(Histogram::reset);
+    }
+
+    #[inline]
+    pub fn percentiles(&self, measure: Measure) -> Percentiles {
+        let histogram = &self.0[measure as usize];
+        Percentiles {
+            count: histogram.len(),
+            p50: histogram.value_at_quantile(0.5),
+            p99: histogram.value_at_quantile(0.99),
+            p999: histogram.value_at_quantile(0.999),
+            max: histogram.max(),
+        }
+    }
+
+    // logs the percentiles of every measure with latencies
+    pub fn report(&self) {
+        for measure in Measure::ALL {
+            let percentiles = self.percentiles(measure);
+            if percentiles.count > 0 {
+                info!("Latency of {measure}: {percentiles}");
+            }
+        }
+    }
+}
+
+// latencies in nanoseconds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Percentiles {
+    pub count: u64,
+    pub p50: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+impl Display for Percentiles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "count={} p50={}ns p99={}ns p99.9={}ns max={}ns",
+            self.count, self.p50, self.p99, self.p999, self.max
+        )
+    }
+}
+
+// the latencies of all the threads together, each one adds what it has recorded every once in a while so the
+// periodic reports cover every measure
+#[derive(Debug)]
+pub struct Reporter {
+    every: Duration,
+    merged: Mutex<(Histograms, Instant)>, // with the time of the last report
+}
+
+impl Reporter {
+    #[inline]
+    pub fn new(every: Duration) -> Self {
+        Self {
+            every,
+            merged: Mutex::new((Histograms::default(), Instant::now())),
+        }
+    }
+
+    // logs the merged percentiles if a report is due
+    fn add(&self, histograms: &Histograms, now: Instant) {
+        let mut merged = self.merged.lock().expect("reporter not poisoned");
+        let (merged, last_report) = &mut *merged;
+        merged.merge(histograms);
+        if now.saturating_duration_since(*last_report) >= self.every {
+            *last_report = now;
+            merged.report();
+        }
+    }
+
+    #[inline]
+    pub fn histograms(&self) -> Histograms {
+        self.merged.lock().expect("reporter not poisoned").0.clone()
+    }
+}
+
+// histograms owned by a single thread (nothing shared on the hot path), adding what it has recorded to the
+// reporter every once in a while if there's one
+#[derive(Debug)]
+pub struct Recorder {
+    histograms: Histograms, // added to the reporter already
+    recent: Histograms,
+    reporter: Option<Arc<Reporter>>,
+    last_added: Instant,
+}
+
+impl Recorder {
+    #[inline]
+    pub fn new(reporter: Option<Arc<Reporter>>) -> Self {
+        Self {
+            histograms: Histograms::default(),
+            recent: Histograms::default(),
+            reporter,
+            last_added: Instant::now(),
+        }
+    }
+
+    // records the time from `since` to `now`, `now` is reused to check whether it's time to add to the reporter
+    #[inline]
+    pub fn record(&mut self, measure: Measure, since: Instant, now: Instant) {
+        self.record_n(measure, since, now, 1);
+    }
+
+    // the same for a number of requests
+    #[inline]
+    pub fn record_n(&mut self, measure: Measure, since: Instant, now: Instant, count: u64) {
+        self.recent
+            .record_n(measure, now.saturating_duration_since(since), count);
+        if let Some(reporter) = &self.reporter
+            && now.saturating_duration_since(self.last_added) >= reporter.every
+        {
+            self.last_added = now;
+            reporter.add(&self.recent, now);
+            self.histograms.merge(&self.recent);
+            self.recent.reset();
+        }
+    }
+
+    #[inline]
+    pub fn into_histograms(mut self) -> Histograms {
+        self.histograms.merge(&self.recent);
+        self.histograms
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    fn percentiles() {
+        let mut histograms = Histograms::default();
+        for micros in 1..=1_000 {
+            histograms.record(Measure::Match, Duration::from_micros(micros));
+        }
+        let percentiles = histograms.percentiles(Measure::Match);
+        assert_eq!(percentiles.count, 1_000);
+        // within the precision of the histogram
+        for (value, expected) in [
+            (percentiles.p50, 500_000),
+            (percentiles.p99, 990_000),
+            (percentiles.p999, 999_000),
+            (percentiles.max, 1_000_000),
+        ] {
+            assert!(value.abs_diff(expected) <= expected / 1_000, "{value} vs {expected}");
+        }
+        assert_eq!(histograms.percentiles(Measure::Decode), Percentiles::default());
+    }
+
+    #[rstest]
+    fn merge() {
+        let reporter = Arc::new(Reporter::new(Duration::from_nanos(150)));
+        let (mut decode, mut publish) = (Recorder::new(Some(reporter.clone())), Recorder::new(None));
+        let start = decode.last_added;
+        decode.record(Measure::Decode, start, start + Duration::from_nanos(100));
+        publish.record(Measure::Publish, start, start + Duration::from_nanos(100));
+        assert_eq!(decode.last_added, start);
+        assert_eq!(reporter.histograms().percentiles(Measure::Decode).count, 0);
+        // time to add to the reporter
+        decode.record(Measure::Decode, start, start + Duration::from_nanos(200));
+        assert_eq!(decode.last_added, start + Duration::from_nanos(200));
+        assert_eq!(reporter.histograms().percentiles(Measure::Decode).count, 2);
+        decode.record(Measure::Decode, start, start + Duration::from_nanos(250));
+        // a clock going backwards counts as no time at all
+        publish.record(Measure::Publish, start + Duration::from_nanos(10), start);
+
+        let mut histograms = decode.into_histograms();
+        histograms.merge(&publish.into_histograms());
+        assert_eq!(histograms.percentiles(Measure::Decode).count, 3);
+        assert_eq!(histograms.percentiles(Measure::Decode).max, 250);
+        assert_eq!(histograms.percentiles(Measure::Publish).count, 2);
+        assert_eq!(histograms.percentiles(Measure::Publish).max, 100);
+    }
+}
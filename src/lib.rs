@@ -1,6 +1,24 @@
+pub mod account;
+pub mod auction;
+pub mod breaker;
+pub mod dispatcher;
 pub mod engine;
+pub mod event;
+pub mod fee;
+pub mod instrument;
+pub mod latency;
+pub mod matcher;
 pub mod order;
 pub mod orderbook;
+pub mod phase;
+pub mod pipeline;
 //pub mod policy;
+pub mod position;
+pub mod queue;
+pub mod rfq;
+pub mod ring;
+pub mod risk;
+pub mod stops;
 pub mod summary;
+pub mod throttle;
 pub mod trade;
@@ -1,20 +1,21 @@
 use std::{
-    io::{BufRead, BufReader},
+    fs::File,
+    io::{BufRead, BufReader, Write},
     path::PathBuf,
     str::FromStr,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use anyhow::Result;
 use clap::Parser;
 use compact_str::CompactString;
-use crossbeam_channel::unbounded;
 use matchina::{
     engine::Engine,
-    order::{OrderRequest, util::DEFAULT_PAIR},
+    order::util::DEFAULT_PAIR,
+    pipeline::{DEFAULT_RING_CAPACITY, Pipeline, Stage},
     summary::compute,
 };
-use tracing::{error, info};
+use tracing::info;
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_log::LogTracer;
 use tracing_subscriber::{EnvFilter, Layer, Registry, fmt, prelude::*};
@@ -28,6 +29,36 @@ struct Args {
     input: Option<Input>,
     #[clap(short, long, value_parser = clap::value_parser!(Output), help = "Target of Order Book events")]
     output: Option<Output>,
+    #[clap(long, default_value_t = DEFAULT_RING_CAPACITY, help = "Capacity of the ring buffers between the pipeline stages")]
+    capacity: usize,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "Cores to pin the decode, validate, match and publish stages to (in that order)"
+    )]
+    cores: Vec<usize>,
+    #[clap(
+        long,
+        help = "Measure the latency of each stage (reported as percentiles at the end)"
+    )]
+    latency: bool,
+    #[clap(
+        long,
+        value_name = "SECONDS",
+        requires = "latency",
+        help = "Also report the latencies measured so far every this many seconds"
+    )]
+    latency_every: Option<u64>,
+    #[clap(
+        long,
+        help = "Journal of the requests that reach the engine (JSON lines, to replay them)"
+    )]
+    journal: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "The input comes from the operator, admin requests (deposits, phases, limits, kill switch...) are processed too"
+    )]
+    admin: bool,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -68,37 +99,45 @@ fn main() -> Result<()> {
     // Parse command line arguments
     let args = Args::parse();
 
-    let (tx, rx) = unbounded();
-
-    // Start reading orders in a separate thread
-    let reader = read(args.input.unwrap_or_default(), tx);
-    reader.join().expect("order reader thread panicked")?;
-
     // Create the matching engine
     let mut engine = Engine::new(&args.pair);
 
-    // Process all the order requests
-    let start = Instant::now();
-    while let Ok(order_request) = rx.recv() {
-        if let Err(error) = engine.process(order_request) {
-            error!("Error processing order request: {}", error);
-        }
+    // Stages of the pipeline (decode -> validate/journal -> match -> publish) on their own threads
+    let mut pipeline = Pipeline::default().with_capacity(args.capacity).with_admin(args.admin);
+    for (stage, core) in Stage::ALL.into_iter().zip(args.cores) {
+        pipeline = pipeline.with_core(stage, core);
     }
+    if args.latency {
+        pipeline = pipeline.with_latency(args.latency_every.map(Duration::from_secs));
+    }
+
+    let input: Box<dyn BufRead + Send> = match args.input.unwrap_or_default() {
+        Input::File(path) => Box::new(BufReader::new(File::open(path)?)),
+        Input::Stdin => Box::new(BufReader::new(std::io::stdin())),
+    };
+    let output: Box<dyn Write + Send> = match args.output.unwrap_or_default() {
+        Output::File(path) => Box::new(File::create(path)?),
+        Output::Stdout => Box::new(std::io::stdout()),
+    };
+
+    // Process all the order requests, publishing the events as they come
+    let start = Instant::now();
+    let stats = match args.journal {
+        Some(path) => pipeline.run_journaled(&mut engine, input, output, File::create(path)?)?,
+        None => pipeline.run(&mut engine, input, output)?,
+    };
     let elapsed = (Instant::now() - start).as_millis();
-    info!("Matching Engine finished in {elapsed} milliseconds");
+    info!(
+        "Matching Engine finished in {elapsed} milliseconds (decoded={}, malformed={}, rejected={}, failed={}, events={})",
+        stats.decoded, stats.malformed, stats.rejected, stats.failed, stats.events
+    );
+    if let Some(latency) = &stats.latency {
+        latency.report();
+    }
 
     // Report summary
-    let orderbook = engine.orderbook();
-    match args.output.unwrap_or_default() {
-        Output::Stdout => {
-            let summary = compute(orderbook);
-            info!("{summary}");
-        }
-        Output::File(path) => {
-            info!("{:?}", path);
-            unimplemented!()
-        }
-    }
+    let summary = compute(engine.orderbook());
+    info!("{summary}");
 
     Ok(())
 }
@@ -106,47 +145,16 @@ fn main() -> Result<()> {
 fn init_logs() -> WorkerGuard {
     LogTracer::init().expect("Unable to set up log tracer");
 
-    let (non_blocking_writer, guard) = tracing_appender::non_blocking(std::io::stdout());
-    let stdout_layer = fmt::layer()
+    // stdout is where the events go by default, the logs never get in their way
+    let (non_blocking_writer, guard) = tracing_appender::non_blocking(std::io::stderr());
+    let stderr_layer = fmt::layer()
         .json()
         .with_thread_names(true)
         .with_writer(non_blocking_writer)
         .with_filter(EnvFilter::from_default_env());
 
-    let subscriber = Registry::default().with(stdout_layer);
+    let subscriber = Registry::default().with(stderr_layer);
     tracing::subscriber::set_global_default(subscriber).expect("Failed to set global subscriber");
 
     guard
 }
-
-fn read(input_source: Input, tx: crossbeam_channel::Sender<OrderRequest>) -> std::thread::JoinHandle<Result<()>> {
-    std::thread::spawn(move || -> Result<()> {
-        let mut buf_read: Box<dyn BufRead> = match &input_source {
-            Input::File(path) => {
-                let file = std::fs::File::open(path)?;
-                Box::new(BufReader::new(file))
-            }
-            Input::Stdin => {
-                let stdin = std::io::stdin();
-                Box::new(BufReader::new(stdin))
-            }
-        };
-
-        let mut buf = String::with_capacity(4096);
-        while buf_read.read_line(&mut buf).is_ok() {
-            let order = serde_json::from_str(&buf);
-            buf.clear();
-            match order {
-                Err(error) => {
-                    if error.is_eof() {
-                        break;
-                    }
-                    error!("Error processing source of orders: {}", error);
-                }
-                Ok(order) => tx.send(order)?,
-            }
-        }
-
-        Ok(())
-    })
-}
@@ -0,0 +1,329 @@
+use std::cmp::Reverse;
+
+use indexmap::IndexMap;
+
+use crate::{
+    breaker::PriceBand,
+    order::{Order, OrderFeatures, OrderId, OrderPrice, OrderQuantity, OrderSide},
+    orderbook::{HiddenPriority, Ladder, OrderbookError},
+    queue::{Handle, OrderArena},
+    trade::{Trade, TradeTape},
+};
+
+// one side of the book, ladders are generic over it so the ordering of their levels is written once
+pub trait Side {
+    const SIDE: OrderSide;
+
+    // ordering of the levels with the best price first
+    type Key: Ord + Copy;
+
+    type Opposite: Side<Opposite = Self>;
+
+    fn key(price: OrderPrice) -> Self::Key;
+
+    // whether `price` is better than `than` for the resting orders of this side
+    fn is_better(price: OrderPrice, than: OrderPrice) -> bool;
+}
+
+// lowest price first
+pub struct Asks;
+
+// highest price first
+pub struct Bids;
+
+impl Side for Asks {
+    const SIDE: OrderSide = OrderSide::Ask;
+    type Key = OrderPrice;
+    type Opposite = Bids;
+
+    #[inline]
+    fn key(price: OrderPrice) -> Self::Key {
+        price
+    }
+
+    #[inline]
+    fn is_better(price: OrderPrice, than: OrderPrice) -> bool {
+        price < than
+    }
+}
+
+impl Side for Bids {
+    const SIDE: OrderSide = OrderSide::Bid;
+    type Key = Reverse<OrderPrice>;
+    type Opposite = Asks;
+
+    #[inline]
+    fn key(price: OrderPrice) -> Self::Key {
+        Reverse(price)
+    }
+
+    #[inline]
+    fn is_better(price: OrderPrice, than: OrderPrice) -> bool {
+        price > than
+    }
+}
+
+// matches incoming orders against the opposite ladder and rests what's left on their own, whatever the ladders
+// are backed by, borrowing the parts of the book it needs
+pub(crate) struct Matcher<'a> {
+    pub(crate) orders: &'a mut OrderArena,
+    pub(crate) handles: &'a mut IndexMap<OrderId, Handle>,
+    pub(crate) filled: &'a mut Vec<OrderId>, // makers closed by the sweep, taken out of the index once it's done
+    pub(crate) trades: &'a mut TradeTape,
+    pub(crate) hidden_priority: HiddenPriority,
+    pub(crate) price_band: Option<PriceBand>,
+    pub(crate) band_breached: bool, // whether a taker was stopped at the price band
+}
+
+impl Matcher<'_> {
+    // whether the incoming order traded
+    pub(crate) fn match_order<S: Side>(
+        &mut self,
+        mut incoming: Order,
+        order_ladder: &mut impl Ladder<S>,
+        opposite_ladder: &mut impl Ladder<S::Opposite>,
+    ) -> Result<bool, OrderbookError> {
+        debug_assert_eq!(incoming.side(), S::SIDE);
+
+        // rejected before trading if what would be left of it couldn't rest (it only gets smaller by trading)
+        if incoming.is_bookable()
+            && !incoming.is_immediate_or_cancel()
+            && !incoming.is_fill_or_kill()
+            && !order_ladder.can_rest(&incoming)
+        {
+            return Err(OrderbookError::QuantityOverflow(incoming.id()));
+        }
+
+        // PostOnly orders should go directly to the book; otherwise, if they can be matched inmediately, then they should be canceled
+        if incoming.is_post_only()
+            && opposite_ladder
+                .peek_top(self.orders)
+                .is_some_and(|top_order| incoming.matches(top_order))
+        {
+            incoming.cancel();
+            return Ok(false);
+        }
+
+        // FOK orders should be canceled if they cannot be fill completely
+        if incoming.is_fill_or_kill() && !self.can_fill(&incoming, opposite_ladder) {
+            incoming.cancel();
+            return Ok(false);
+        }
+
+        let matched = self.take(&mut incoming, opposite_ladder);
+        for order_id in self.filled.drain(..) {
+            self.handles.swap_remove(&order_id);
+        }
+        let matched = matched?;
+
+        // IOC orders should be closed at the end of the matching phase (this is, no insertion in the book)
+        if incoming.is_immediate_or_cancel() {
+            incoming.cancel();
+            return Ok(matched);
+        }
+
+        // insert limit order in the book
+        if !incoming.is_closed() && incoming.is_bookable() {
+            let handle = order_ladder.insert(self.orders, incoming, self.hidden_priority)?;
+            self.handles.insert(incoming.id(), handle);
+        }
+
+        Ok(matched)
+    }
+
+    // whether the liquidity within the limit price (and the band) is enough to fill the whole order
+    fn can_fill<O: Side>(&self, incoming: &Order, opposite_ladder: &impl Ladder<O>) -> bool {
+        let mut remaining = incoming.remaining();
+        for price_level in opposite_ladder.levels() {
+            if remaining <= OrderQuantity::ZERO || !price_level.matches(incoming) {
+                break;
+            }
+            if self.price_band.is_some_and(|band| !band.contains(price_level.price)) {
+                break;
+            }
+            // hidden liquidity counts too
+            remaining = remaining.saturating_sub(price_level.total_quantity());
+        }
+        remaining <= OrderQuantity::ZERO
+    }
+
+    // trade the incoming order against the best levels in price-time order, the trades go straight to the tape
+    fn take<O: Side>(
+        &mut self,
+        incoming: &mut Order,
+        opposite_ladder: &mut impl Ladder<O>,
+    ) -> Result<bool, OrderbookError> {
+        let mut matched = false;
+
+        while let Some(price_level) = opposite_ladder.best_mut() {
+            if incoming.is_closed() || !price_level.matches(incoming) {
+                break;
+            }
+
+            // takers stop at the price band, whatever is left is cancelled (resting at its limit would cross the
+            // levels beyond the band)
+            if self.price_band.is_some_and(|band| !band.contains(price_level.price)) {
+                self.band_breached = true;
+                incoming.cancel();
+                break;
+            }
+
+            let mut cursor = price_level.front();
+            while let Some(handle) = cursor {
+                if incoming.is_closed() {
+                    break;
+                }
+
+                cursor = self.orders.next(handle);
+                let maker = &mut self.orders[handle];
+                let traded = incoming.can_trade(maker);
+
+                let trade = Trade::new(self.trades.next_id(), incoming, maker, traded)?;
+                self.trades.push(trade);
+
+                matched = true;
+
+                price_level.fill(maker, traded);
+                if maker.is_closed() {
+                    let maker = price_level.unlink(self.orders, handle);
+                    self.filled.push(maker.id());
+                }
+            }
+
+            if !price_level.is_closed() {
+                break;
+            }
+            opposite_ladder.pop_best();
+        }
+
+        Ok(matched)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::orderbook::{ArrayLadder, PriceRange, TreeLadder};
+
+    // convention for order ids: 3-digit side (bid = 900, ask = 901), 3-digit quantity, 3-digit price (for market orders always 999)
+
+    #[derive(Default)]
+    struct Book {
+        orders: OrderArena,
+        handles: IndexMap<OrderId, Handle>,
+        filled: Vec<OrderId>,
+        trades: TradeTape,
+    }
+
+    impl Book {
+        fn matcher(&mut self, price_band: Option<PriceBand>) -> Matcher<'_> {
+            Matcher {
+                orders: &mut self.orders,
+                handles: &mut self.handles,
+                filled: &mut self.filled,
+                trades: &mut self.trades,
+                hidden_priority: HiddenPriority::default(),
+                price_band,
+                band_breached: false,
+            }
+        }
+
+        fn trades(&self) -> Vec<(OrderId, OrderPrice, OrderQuantity)> {
+            self.trades
+                .iter()
+                .map(|trade| (trade.maker(), trade.price(), trade.quantity()))
+                .collect()
+        }
+    }
+
+    #[fixture]
+    fn book() -> Book {
+        Book::default()
+    }
+
+    fn order(order_id: u64, side: OrderSide, quantity: i32, price: i32) -> Order {
+        Order::limit_order(OrderId::new(order_id), side, quantity.into(), price.into())
+    }
+
+    // rests three asks over two levels and sweeps them with a bid
+    fn sweep<A: Ladder<Asks>, B: Ladder<Bids>>(book: &mut Book, asks: &mut A, bids: &mut B) {
+        let mut matcher = book.matcher(None);
+        for ask in [
+            order(901_010_015, OrderSide::Ask, 10, 15),
+            order(901_020_014, OrderSide::Ask, 20, 14),
+            order(901_005_015, OrderSide::Ask, 5, 15),
+        ] {
+            assert_eq!(matcher.match_order::<Asks>(ask, asks, bids), Ok(false));
+        }
+
+        let bid = order(900_040_015, OrderSide::Bid, 40, 15);
+        assert_eq!(matcher.match_order::<Bids>(bid, bids, asks), Ok(true));
+
+        // best price first, then time priority, and the rest of the bid rests
+        assert_eq!(
+            book.trades(),
+            [
+                (901_020_014.into(), 14.into(), 20.into()),
+                (901_010_015.into(), 15.into(), 10.into()),
+                (901_005_015.into(), 15.into(), 5.into()),
+            ]
+        );
+        assert!(asks.levels().next().is_none());
+        assert_eq!(bids.depth(5), [(15.into(), 5.into())]);
+        assert_eq!(book.handles.keys().collect::<Vec<_>>(), [&OrderId::new(900_040_015)]);
+    }
+
+    #[rstest]
+    fn sweep_tree_ladders(mut book: Book) {
+        sweep(&mut book, &mut TreeLadder::default(), &mut TreeLadder::default());
+    }
+
+    #[rstest]
+    fn sweep_array_ladders(mut book: Book) {
+        let range = PriceRange {
+            min_price: 10.into(),
+            max_price: 20.into(),
+            tick_size: 1.into(),
+        };
+        sweep(&mut book, &mut ArrayLadder::new(&range), &mut ArrayLadder::new(&range));
+    }
+
+    #[rstest]
+    fn stop_at_price_band(mut book: Book) {
+        let (mut asks, mut bids) = (TreeLadder::<Asks>::default(), TreeLadder::<Bids>::default());
+        let mut matcher = book.matcher(None);
+        for ask in [
+            order(901_010_015, OrderSide::Ask, 10, 15),
+            order(901_010_017, OrderSide::Ask, 10, 17),
+        ] {
+            assert_eq!(matcher.match_order::<Asks>(ask, &mut asks, &mut bids), Ok(false));
+        }
+
+        // the taker stops at the first level out of the band and the rest of it is cancelled, the book isn't crossed
+        let band = PriceBand {
+            min: 10.into(),
+            max: 16.into(),
+        };
+        let mut matcher = book.matcher(Some(band));
+        let bid = order(900_020_020, OrderSide::Bid, 20, 20);
+        assert_eq!(matcher.match_order::<Bids>(bid, &mut bids, &mut asks), Ok(true));
+        assert!(matcher.band_breached);
+        assert_eq!(book.trades(), [(901_010_015.into(), 15.into(), 10.into())]);
+        assert_eq!(asks.depth(5), [(17.into(), 10.into())]);
+        assert!(bids.depth(5).is_empty());
+
+        // nothing trades within an empty band
+        let band = PriceBand {
+            min: 18.into(),
+            max: 16.into(),
+        };
+        let mut matcher = book.matcher(Some(band));
+        let bid = order(900_010_017, OrderSide::Bid, 10, 17);
+        assert_eq!(matcher.match_order::<Bids>(bid, &mut bids, &mut asks), Ok(false));
+        assert!(matcher.band_breached);
+        assert_eq!(asks.depth(5), [(17.into(), 10.into())]);
+        assert!(bids.depth(5).is_empty());
+    }
+}
@@ -1,10 +1,17 @@
-use std::{cmp::Ordering, fmt::Display};
+use std::{cmp::Ordering, fmt::Display, ops::Mul};
 
 use compact_str::CompactString;
-use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
 use thiserror::Error;
 
+use crate::{
+    breaker::PriceBands,
+    phase::TradingPhase,
+    risk::RiskLimits,
+    throttle::{ThrottleLimits, Timestamp},
+};
+
 #[derive(Clone, Copy, Debug, Hash, Serialize, Deserialize, PartialEq, Eq)]
 pub struct OrderId(u64);
 
@@ -27,41 +34,480 @@ impl Display for OrderId {
     }
 }
 
-// TODO use struct to give behavior (see OrderId)
-pub type OrderPrice = Decimal;
-pub type OrderQuantity = Decimal;
+#[derive(Clone, Copy, Debug, Default, Hash, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AccountId(u64);
+
+impl AccountId {
+    #[inline]
+    pub const fn new(account_id: u64) -> Self {
+        Self(account_id)
+    }
+}
+
+impl From<u64> for AccountId {
+    fn from(value: u64) -> AccountId {
+        AccountId::new(value)
+    }
+}
+
+impl Display for AccountId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "account_id:{}", self.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SessionId(u64);
+
+impl SessionId {
+    #[inline]
+    pub fn new(session_id: u64) -> Self {
+        Self(session_id)
+    }
+}
+
+impl From<u64> for SessionId {
+    fn from(value: u64) -> SessionId {
+        SessionId::new(value)
+    }
+}
+
+impl Display for SessionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "session_id:{}", self.0)
+    }
+}
+
+// prices and quantities are integers scaled by 10^SCALE, decimals are only used at the boundaries (requests,
+// balances, serde) so the book compares and adds plain integers, the order path only uses the checked and
+// saturating methods (orders are bounded when they enter the engine), there are no operators that could panic
+// the scale is the same for every instrument, the tick and lot sizes are only checked as multiples of it
+pub const SCALE: u32 = 8;
+const UNIT: i64 = 10_i64.pow(SCALE);
+
+macro_rules! fixed_point {
+    ($name:ident) => {
+        #[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+        pub struct $name(i64);
+
+        impl $name {
+            pub const ZERO: Self = Self(0);
+            pub const ONE: Self = Self(UNIT);
+
+            #[inline]
+            pub const fn from_raw(raw: i64) -> Self {
+                Self(raw)
+            }
+
+            #[inline]
+            pub const fn raw(&self) -> i64 {
+                self.0
+            }
+
+            #[inline]
+            pub const fn is_zero(&self) -> bool {
+                self.0 == 0
+            }
+
+            #[inline]
+            pub const fn abs(&self) -> Self {
+                Self(self.0.abs())
+            }
+
+            #[inline]
+            pub const fn checked_add(self, rhs: Self) -> Option<Self> {
+                match self.0.checked_add(rhs.0) {
+                    Some(raw) => Some(Self(raw)),
+                    None => None,
+                }
+            }
+
+            #[inline]
+            pub const fn checked_sub(self, rhs: Self) -> Option<Self> {
+                match self.0.checked_sub(rhs.0) {
+                    Some(raw) => Some(Self(raw)),
+                    None => None,
+                }
+            }
+
+            #[inline]
+            pub const fn saturating_add(self, rhs: Self) -> Self {
+                Self(self.0.saturating_add(rhs.0))
+            }
+
+            #[inline]
+            pub const fn saturating_sub(self, rhs: Self) -> Self {
+                Self(self.0.saturating_sub(rhs.0))
+            }
+
+            // whether it's a whole number of steps (e.g. ticks or lots)
+            #[inline]
+            pub const fn is_multiple_of(&self, step: Self) -> bool {
+                step.0 > 0 && self.0 % step.0 == 0
+            }
+
+            // a percentage of it (e.g. 5 for 5%), rounded to the scale and saturating at the fixed-point range
+            #[inline]
+            pub fn percent(&self, percent: Decimal) -> Self {
+                let value = self
+                    .to_decimal()
+                    .checked_mul(percent)
+                    .map(|value| (value / Decimal::ONE_HUNDRED).round_dp(SCALE));
+                match value.map(Self::try_from) {
+                    Some(Ok(value)) => value,
+                    _ if self.0.is_negative() != percent.is_sign_negative() => Self(i64::MIN),
+                    _ => Self(i64::MAX),
+                }
+            }
+
+            #[inline]
+            pub fn to_decimal(&self) -> Decimal {
+                Decimal::new(self.0, SCALE)
+            }
+        }
+
+        impl TryFrom<Decimal> for $name {
+            type Error = FixedPointError;
+
+            fn try_from(value: Decimal) -> Result<Self, Self::Error> {
+                let scaled = value
+                    .checked_mul(Decimal::from(UNIT))
+                    .ok_or(FixedPointError::Overflow(value))?;
+                if !scaled.fract().is_zero() {
+                    return Err(FixedPointError::Precision(value));
+                }
+                scaled.to_i64().map(Self).ok_or(FixedPointError::Overflow(value))
+            }
+        }
+
+        impl From<$name> for Decimal {
+            #[inline]
+            fn from(value: $name) -> Decimal {
+                value.to_decimal()
+            }
+        }
+
+        impl From<i32> for $name {
+            #[inline]
+            fn from(value: i32) -> Self {
+                // any i32 fits once scaled
+                Self(value as i64 * UNIT)
+            }
+        }
+
+        impl TryFrom<i64> for $name {
+            type Error = FixedPointError;
+
+            #[inline]
+            fn try_from(value: i64) -> Result<Self, Self::Error> {
+                value
+                    .checked_mul(UNIT)
+                    .map(Self)
+                    .ok_or(FixedPointError::Overflow(Decimal::from(value)))
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", self.to_decimal().normalize())
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                Serialize::serialize(&self.to_decimal().normalize(), serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let value = <Decimal as Deserialize>::deserialize(deserializer)?;
+                Self::try_from(value).map_err(de::Error::custom)
+            }
+        }
+    };
+}
+
+fixed_point!(OrderPrice);
+fixed_point!(OrderQuantity);
+
+impl OrderPrice {
+    // the midpoint, rounded down to the scale
+    #[inline]
+    pub const fn midpoint(self, other: Self) -> Self {
+        Self(self.0.midpoint(other.0))
+    }
+}
+
+// the notional of a price and a quantity
+impl Mul<OrderQuantity> for OrderPrice {
+    type Output = Decimal;
+
+    #[inline]
+    fn mul(self, rhs: OrderQuantity) -> Decimal {
+        self.to_decimal() * rhs.to_decimal()
+    }
+}
+
+impl Mul<OrderPrice> for OrderQuantity {
+    type Output = Decimal;
+
+    #[inline]
+    fn mul(self, rhs: OrderPrice) -> Decimal {
+        rhs * self
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum FixedPointError {
+    #[error("more decimals than the fixed-point scale! {0}")]
+    Precision(Decimal),
+    #[error("out of the fixed-point range! {0}")]
+    Overflow(Decimal),
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "UPPERCASE", tag = "order_request")]
 pub enum OrderRequest {
     Create {
         account_id: CompactString,
-        order_id: u64,
+        client_order_id: CompactString, // unique per account while the order is live
         pair: CompactString,
         side: OrderSide,
         limit_price: Option<Decimal>, // for market orders use None
         quantity: Decimal,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        time_in_force: Option<TimeInForce>, // for limit orders, GTC by default
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        trigger: Option<StopTrigger>, // for stop orders, held until triggered
+        #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+        hidden: bool, // for limit orders matchable but not displayed in the book
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session_id: Option<u64>, // gateway session that entered the order
+        #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+        persistent: bool, // for orders that survive the session being closed
     },
     Cancel {
-        order_id: u64,
+        account_id: CompactString,
+        client_order_id: CompactString,
+    },
+    // cancel all the resting orders matching every given filter (no filters cancels the whole instrument)
+    #[serde(rename = "MASS_CANCEL")]
+    MassCancel {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<CompactString>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        side: Option<OrderSide>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min_price: Option<Decimal>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_price: Option<Decimal>,
+    },
+    Deposit {
+        account_id: CompactString,
+        asset: CompactString,
+        amount: Decimal,
+    },
+    Withdraw {
+        account_id: CompactString,
+        asset: CompactString,
+        amount: Decimal,
+    },
+    // limits for the account or for the whole instrument (if no account is given)
+    #[serde(rename = "SET_RISK_LIMITS")]
+    SetRiskLimits {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<CompactString>,
+        limits: RiskLimits,
+    },
+    // reference for the price collars until there's a last trade price
+    #[serde(rename = "SET_REFERENCE_PRICE")]
+    SetReferencePrice {
+        price: Decimal,
+    },
+    #[serde(rename = "SET_FEE_TIER")]
+    SetFeeTier {
+        account_id: CompactString,
+        tier: CompactString,
+    },
+    // cancel all the orders of a gateway session except the persistent ones
+    #[serde(rename = "SESSION_CLOSED")]
+    SessionClosed {
+        session_id: u64,
+    },
+    // kill switch: no new orders for the account or for all the accounts (if no account is given)
+    Block {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<CompactString>,
+        #[serde(default)]
+        cancel_orders: bool,
+    },
+    Unblock {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<CompactString>,
+    },
+    // limits for the account or the default ones for all accounts (if no account is given)
+    #[serde(rename = "SET_THROTTLE_LIMITS")]
+    SetThrottleLimits {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account_id: Option<CompactString>,
+        limits: ThrottleLimits,
+    },
+    // move the engine clock forward (in nanoseconds)
+    Clock {
+        timestamp: Timestamp,
     },
+    #[serde(rename = "SET_PHASE")]
+    SetPhase {
+        phase: TradingPhase,
+    },
+    #[serde(rename = "SET_PRICE_BANDS")]
+    SetPriceBands {
+        bands: PriceBands,
+    },
+    // off-book block trades: the requester broadcasts the side and quantity, makers answer with firm quotes
+    // and the requester accepts one of them
+    #[serde(rename = "REQUEST_QUOTE")]
+    RequestQuote {
+        account_id: CompactString,
+        rfq_id: CompactString,
+        side: OrderSide,
+        quantity: Decimal,
+    },
+    Quote {
+        account_id: CompactString,
+        rfq_id: CompactString,
+        quote_id: CompactString,
+        price: Decimal,
+    },
+    #[serde(rename = "ACCEPT_QUOTE")]
+    AcceptQuote {
+        account_id: CompactString,
+        rfq_id: CompactString,
+        quote_id: CompactString,
+    },
+    // the requester gives up on the request, or a maker pulls its quote before it's accepted
+    #[serde(rename = "CANCEL_QUOTE_REQUEST")]
+    CancelQuoteRequest {
+        account_id: CompactString,
+        rfq_id: CompactString,
+    },
+    #[serde(rename = "WITHDRAW_QUOTE")]
+    WithdrawQuote {
+        account_id: CompactString,
+        rfq_id: CompactString,
+        quote_id: CompactString,
+    },
+}
+
+impl OrderRequest {
+    // commands of the operator (money coming in, the state of the instrument and the limits of the accounts),
+    // clients can't send them, the engine only takes them through its admin entry point
+    #[inline]
+    pub fn is_admin(&self) -> bool {
+        matches!(
+            self,
+            OrderRequest::MassCancel { account_id: None, .. }
+                | OrderRequest::Deposit { .. }
+                | OrderRequest::SetRiskLimits { .. }
+                | OrderRequest::SetReferencePrice { .. }
+                | OrderRequest::SetFeeTier { .. }
+                | OrderRequest::SessionClosed { .. }
+                | OrderRequest::Block { .. }
+                | OrderRequest::Unblock { .. }
+                | OrderRequest::SetThrottleLimits { .. }
+                | OrderRequest::Clock { .. }
+                | OrderRequest::SetPhase { .. }
+                | OrderRequest::SetPriceBands { .. }
+        )
+    }
 }
 
 impl Display for OrderRequest {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             OrderRequest::Create {
-                account_id: _,
-                order_id,
+                account_id,
+                client_order_id,
                 pair: _,
                 side,
                 limit_price,
                 quantity,
+                ..
             } => match limit_price {
-                Some(limit_price) => write!(f, "ORDER[{order_id}] {side} {quantity}@{limit_price}"),
-                None => write!(f, "ORDER[{order_id}] {side} {quantity}@MARKET"),
+                Some(limit_price) => write!(
+                    f,
+                    "ORDER[{account_id}/{client_order_id}] {side} {quantity}@{limit_price}"
+                ),
+                None => write!(f, "ORDER[{account_id}/{client_order_id}] {side} {quantity}@MARKET"),
             },
-            OrderRequest::Cancel { order_id } => write!(f, "[CANCEL] order_id: {order_id}"),
+            OrderRequest::Cancel {
+                account_id,
+                client_order_id,
+            } => write!(f, "[CANCEL] client_order_id: {account_id}/{client_order_id}"),
+            OrderRequest::MassCancel {
+                account_id,
+                side,
+                min_price,
+                max_price,
+            } => write!(
+                f,
+                "[MASS_CANCEL] account_id: {account_id:?} side: {side:?} price: {min_price:?}..{max_price:?}"
+            ),
+            OrderRequest::Deposit {
+                account_id,
+                asset,
+                amount,
+            } => write!(f, "[DEPOSIT] account_id: {account_id} {amount} {asset}"),
+            OrderRequest::Withdraw {
+                account_id,
+                asset,
+                amount,
+            } => write!(f, "[WITHDRAW] account_id: {account_id} {amount} {asset}"),
+            OrderRequest::SetRiskLimits { account_id, limits } => {
+                write!(f, "[SET_RISK_LIMITS] account_id: {account_id:?} limits: {limits:?}")
+            }
+            OrderRequest::SetReferencePrice { price } => write!(f, "[SET_REFERENCE_PRICE] price: {price}"),
+            OrderRequest::SetFeeTier { account_id, tier } => {
+                write!(f, "[SET_FEE_TIER] account_id: {account_id} tier: {tier}")
+            }
+            OrderRequest::SessionClosed { session_id } => write!(f, "[SESSION_CLOSED] session_id: {session_id}"),
+            OrderRequest::Block {
+                account_id,
+                cancel_orders,
+            } => write!(f, "[BLOCK] account_id: {account_id:?} cancel_orders: {cancel_orders}"),
+            OrderRequest::Unblock { account_id } => write!(f, "[UNBLOCK] account_id: {account_id:?}"),
+            OrderRequest::SetThrottleLimits { account_id, limits } => {
+                write!(f, "[SET_THROTTLE_LIMITS] account_id: {account_id:?} limits: {limits:?}")
+            }
+            OrderRequest::Clock { timestamp } => write!(f, "[CLOCK] timestamp: {timestamp}"),
+            OrderRequest::SetPhase { phase } => write!(f, "[SET_PHASE] phase: {phase}"),
+            OrderRequest::SetPriceBands { bands } => write!(f, "[SET_PRICE_BANDS] bands: {bands:?}"),
+            OrderRequest::RequestQuote {
+                account_id,
+                rfq_id,
+                side,
+                quantity,
+            } => write!(f, "[REQUEST_QUOTE] rfq_id: {account_id}/{rfq_id} {side} {quantity}"),
+            OrderRequest::Quote {
+                account_id,
+                rfq_id,
+                quote_id,
+                price,
+            } => write!(f, "[QUOTE] rfq_id: {rfq_id} quote_id: {account_id}/{quote_id} @{price}"),
+            OrderRequest::AcceptQuote {
+                account_id,
+                rfq_id,
+                quote_id,
+            } => write!(f, "[ACCEPT_QUOTE] rfq_id: {account_id}/{rfq_id} quote_id: {quote_id}"),
+            OrderRequest::CancelQuoteRequest { account_id, rfq_id } => {
+                write!(f, "[CANCEL_QUOTE_REQUEST] rfq_id: {account_id}/{rfq_id}")
+            }
+            OrderRequest::WithdrawQuote {
+                account_id,
+                rfq_id,
+                quote_id,
+            } => write!(f, "[WITHDRAW_QUOTE] rfq_id: {rfq_id} quote_id: {account_id}/{quote_id}"),
         }
     }
 }
@@ -130,6 +576,53 @@ impl Default for TimeInForce {
     }
 }
 
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "stop_type")]
+pub enum StopTrigger {
+    #[serde(rename = "STOP")]
+    Fixed { stop_price: OrderPrice },
+    // the stop price follows the last traded price at a fixed distance
+    #[serde(rename = "TRAILING_AMOUNT")]
+    TrailingAmount { stop_price: OrderPrice, offset: OrderPrice },
+    // the stop price follows the last traded price at a percentage distance (e.g. 5 for 5%)
+    #[serde(rename = "TRAILING_PERCENT")]
+    TrailingPercent { stop_price: OrderPrice, percent: Decimal },
+}
+
+impl StopTrigger {
+    #[inline]
+    pub fn stop_price(&self) -> OrderPrice {
+        match *self {
+            Self::Fixed { stop_price }
+            | Self::TrailingAmount { stop_price, .. }
+            | Self::TrailingPercent { stop_price, .. } => stop_price,
+        }
+    }
+
+    // trailing stops only move in the favourable direction: up for sell stops, down for buy stops
+    #[inline]
+    pub fn ratchet(&mut self, side: OrderSide, last_price: OrderPrice) {
+        let (stop_price, offset) = match self {
+            Self::Fixed { .. } => return,
+            Self::TrailingAmount { stop_price, offset } => (stop_price, *offset),
+            Self::TrailingPercent { stop_price, percent } => (stop_price, last_price.percent(*percent)),
+        };
+
+        match side {
+            OrderSide::Ask => *stop_price = (*stop_price).max(last_price.saturating_sub(offset)),
+            OrderSide::Bid => *stop_price = (*stop_price).min(last_price.saturating_add(offset)),
+        }
+    }
+
+    #[inline]
+    pub fn is_triggered(&self, side: OrderSide, last_price: OrderPrice) -> bool {
+        match side {
+            OrderSide::Ask => last_price <= self.stop_price(),
+            OrderSide::Bid => last_price >= self.stop_price(),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum OrderStatus {
@@ -144,9 +637,14 @@ pub enum OrderStatus {
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Order {
     id: OrderId,
+    account: AccountId,
     side: OrderSide,
     //#[serde(flatten)]
     type_: OrderType,
+    trigger: Option<StopTrigger>,
+    hidden: bool,
+    session: Option<SessionId>,
+    persistent: bool,
     order_quantity: OrderQuantity,
     //#[serde(default)]
     filled_quantity: OrderQuantity,
@@ -158,13 +656,18 @@ impl Order {
     pub fn limit_order(id: OrderId, side: OrderSide, quantity: OrderQuantity, limit_price: OrderPrice) -> Self {
         Self {
             id,
+            account: AccountId::default(),
             side,
             type_: OrderType::Limit {
                 limit_price,
                 time_in_force: Default::default(),
             },
+            trigger: None,
+            hidden: false,
+            session: None,
+            persistent: false,
             order_quantity: quantity,
-            filled_quantity: 0.into(),
+            filled_quantity: OrderQuantity::ZERO,
             status: OrderStatus::Open,
         }
     }
@@ -173,21 +676,78 @@ impl Order {
     pub fn market_order(id: OrderId, side: OrderSide, quantity: OrderQuantity) -> Self {
         Self {
             id,
+            account: AccountId::default(),
             side,
             type_: OrderType::Market {
                 fill_or_kill: Default::default(),
             },
+            trigger: None,
+            hidden: false,
+            session: None,
+            persistent: false,
             order_quantity: quantity,
-            filled_quantity: 0.into(),
+            filled_quantity: OrderQuantity::ZERO,
             status: OrderStatus::Open,
         }
     }
 
+    #[inline]
+    pub fn with_account(mut self, account: AccountId) -> Self {
+        self.account = account;
+        self
+    }
+
+    #[inline]
+    pub fn with_session(mut self, session: SessionId, persistent: bool) -> Self {
+        self.session = Some(session);
+        self.persistent = persistent;
+        self
+    }
+
+    #[inline]
+    pub fn with_trigger(mut self, trigger: StopTrigger) -> Self {
+        self.trigger = Some(trigger);
+        self
+    }
+
+    // only limit orders have a time in force, market orders are always IOC
+    #[inline]
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        if let OrderType::Limit { limit_price, .. } = self.type_ {
+            self.type_ = OrderType::Limit {
+                limit_price,
+                time_in_force,
+            };
+        }
+        self
+    }
+
+    #[inline]
+    pub fn with_hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
     #[inline]
     pub fn id(&self) -> OrderId {
         self.id
     }
 
+    #[inline]
+    pub fn account(&self) -> AccountId {
+        self.account
+    }
+
+    #[inline]
+    pub fn session(&self) -> Option<SessionId> {
+        self.session
+    }
+
+    #[inline]
+    pub fn is_persistent(&self) -> bool {
+        self.persistent
+    }
+
     #[inline]
     pub fn side(&self) -> OrderSide {
         self.side
@@ -195,7 +755,7 @@ impl Order {
 
     #[inline]
     pub fn remaining(&self) -> OrderQuantity {
-        self.order_quantity - self.filled_quantity
+        self.order_quantity.saturating_sub(self.filled_quantity)
     }
 
     #[inline]
@@ -211,6 +771,35 @@ impl Order {
         }
     }
 
+    #[inline]
+    pub fn trigger(&self) -> Option<StopTrigger> {
+        self.trigger
+    }
+
+    #[inline]
+    pub fn is_stop(&self) -> bool {
+        self.trigger.is_some()
+    }
+
+    // follow the last traded price (for trailing stops) and check whether the stop has been triggered
+    #[inline]
+    pub fn update_trigger(&mut self, last_price: OrderPrice) -> bool {
+        let side = self.side;
+        match self.trigger.as_mut() {
+            Some(trigger) => {
+                trigger.ratchet(side, last_price);
+                trigger.is_triggered(side, last_price)
+            }
+            None => false,
+        }
+    }
+
+    // once triggered the stop becomes a regular order
+    #[inline]
+    pub fn activate(&mut self) {
+        self.trigger = None;
+    }
+
     pub fn can_trade(&self, order: &Order) -> OrderQuantity {
         self.remaining().min(order.remaining())
     }
@@ -259,7 +848,7 @@ impl Order {
             });
         }
 
-        self.filled_quantity += quantity;
+        self.filled_quantity = self.filled_quantity.saturating_add(quantity);
         self.status = if self.filled_quantity == self.order_quantity {
             OrderStatus::Completed
         } else {
@@ -324,6 +913,8 @@ pub trait OrderFeatures {
     fn is_immediate_or_cancel(&self) -> bool;
 
     fn is_post_only(&self) -> bool;
+
+    fn is_hidden(&self) -> bool;
 }
 
 impl OrderFeatures for Order {
@@ -356,6 +947,10 @@ impl OrderFeatures for Order {
             } | OrderType::Market { .. }
         )
     }
+
+    fn is_hidden(&self) -> bool {
+        self.hidden && self.is_bookable()
+    }
 }
 
 #[derive(Debug, Error, PartialEq)]
@@ -375,19 +970,36 @@ pub mod util {
     use super::{OrderRequest, OrderSide};
 
     pub const DEFAULT_PAIR: &str = "ETH/USDT";
+    const ACCOUNTS: usize = 9;
+    const FUNDS: i64 = 1_000_000_000_000_000;
 
     pub fn generate(range: impl Iterator<Item = usize>) -> impl Iterator<Item = OrderRequest> {
         let mut rng = rand::thread_rng();
 
-        range.map(move |i| {
+        // the account is derived from the client order id so cancels can be addressed to previous orders
+        let account_id = |i: usize| format_compact!("{}", i % ACCOUNTS + 1);
+
+        // every account is funded upfront with both assets of the pair
+        let (base, quote) = DEFAULT_PAIR.split_once('/').unwrap_or_default();
+        let deposits = (0..ACCOUNTS).flat_map(move |i| {
+            [base, quote].map(|asset| OrderRequest::Deposit {
+                account_id: account_id(i),
+                asset: asset.into(),
+                amount: Decimal::new(FUNDS, 0),
+            })
+        });
+
+        deposits.chain(range.map(move |i| {
             if rng.gen_bool(1.0 / 1000.0) {
+                let j = rng.gen_range(1..=i);
                 OrderRequest::Cancel {
-                    order_id: rng.gen_range(1..=i as u64),
+                    account_id: account_id(j),
+                    client_order_id: format_compact!("{j}"),
                 }
             } else {
                 OrderRequest::Create {
-                    account_id: format_compact!("{}", rng.gen_range(1..10)),
-                    order_id: i as u64,
+                    account_id: account_id(i),
+                    client_order_id: format_compact!("{i}"),
                     pair: CompactString::new_inline(DEFAULT_PAIR),
                     side: if rng.gen_bool(0.5) {
                         OrderSide::Ask
@@ -400,9 +1012,14 @@ pub mod util {
                         None
                     },
                     quantity: random_decimal(&mut rng),
+                    time_in_force: None,
+                    trigger: None,
+                    hidden: false,
+                    session_id: None,
+                    persistent: false,
                 }
             }
-        })
+        }))
     }
 
     pub fn random_decimal(rng: &mut ThreadRng) -> Decimal {
@@ -544,7 +1161,7 @@ mod test {
 
             let fok = TimeInForce::ImmediateOrCancel { fill_or_kill: true };
             limit_order.type_ = OrderType::Limit {
-                limit_price: Decimal::ZERO,
+                limit_price: OrderPrice::ZERO,
                 time_in_force: fok,
             };
             assert!(limit_order.is_fill_or_kill());
@@ -563,7 +1180,7 @@ mod test {
             // change the limit order to GTC but without enforcing post_only
             let gtc = TimeInForce::GoodTilCancel { post_only: false };
             limit_order.type_ = OrderType::Limit {
-                limit_price: Decimal::ZERO,
+                limit_price: OrderPrice::ZERO,
                 time_in_force: gtc,
             };
             assert!(!limit_order.is_post_only());
@@ -571,12 +1188,43 @@ mod test {
             // change the limit order to GTC but enforcing post_only
             let gtc = TimeInForce::GoodTilCancel { post_only: true };
             limit_order.type_ = OrderType::Limit {
-                limit_price: Decimal::ZERO,
+                limit_price: OrderPrice::ZERO,
                 time_in_force: gtc,
             };
             assert!(limit_order.is_post_only());
         }
 
+        #[rstest]
+        fn trailing_stop_ratchets(ask_070_at_market: Order, bid_040_at_market: Order) {
+            // a sell trailing stop 2 below the last price only moves up
+            let trailing = StopTrigger::TrailingAmount {
+                stop_price: 10.into(),
+                offset: 2.into(),
+            };
+            let mut sell_stop = ask_070_at_market.with_trigger(trailing);
+            assert!(!sell_stop.update_trigger(15.into()));
+            assert_eq!(sell_stop.trigger().unwrap().stop_price(), 13.into());
+            assert!(!sell_stop.update_trigger(14.into()));
+            assert_eq!(sell_stop.trigger().unwrap().stop_price(), 13.into());
+            assert!(sell_stop.update_trigger(13.into()));
+
+            // a buy trailing stop 10% above the last price only moves down
+            let trailing = StopTrigger::TrailingPercent {
+                stop_price: 30.into(),
+                percent: 10.into(),
+            };
+            let mut buy_stop = bid_040_at_market.with_trigger(trailing);
+            assert!(!buy_stop.update_trigger(20.into()));
+            assert_eq!(buy_stop.trigger().unwrap().stop_price(), 22.into());
+            assert!(!buy_stop.update_trigger(21.into()));
+            assert_eq!(buy_stop.trigger().unwrap().stop_price(), 22.into());
+            assert!(buy_stop.update_trigger(22.into()));
+
+            // once activated it becomes a regular order
+            buy_stop.activate();
+            assert!(!buy_stop.is_stop());
+        }
+
         #[rstest]
         fn is_immediate_or_cancel(ask_070_at_market: Order, bid_040_at_013: Order) {
             // market orders are IOC
@@ -589,7 +1237,7 @@ mod test {
 
             let ioc = TimeInForce::ImmediateOrCancel { fill_or_kill: false };
             limit_order.type_ = OrderType::Limit {
-                limit_price: Decimal::ZERO,
+                limit_price: OrderPrice::ZERO,
                 time_in_force: ioc,
             };
             assert!(limit_order.is_immediate_or_cancel());
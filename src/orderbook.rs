@@ -1,174 +1,517 @@
-use std::{
-    cmp::Reverse,
-    collections::{BTreeMap, VecDeque, btree_map::Entry},
-    fmt::Display,
-    ops::{Deref, DerefMut},
-};
+use std::{collections::BTreeMap, fmt::Display, marker::PhantomData};
 
 use anyhow::Result;
 use indexmap::IndexMap;
-use num::Zero;
 use rust_decimal::Decimal;
 use thiserror::Error;
 
 use crate::{
-    order::{Order, OrderFeatures, OrderId, OrderPrice, OrderQuantity, OrderSide},
-    trade::{Trade, TradeError, TradeId},
+    auction::{self, AuctionResult},
+    breaker::PriceBand,
+    matcher::{Asks, Bids, Matcher, Side},
+    order::{AccountId, Order, OrderFeatures, OrderId, OrderPrice, OrderQuantity, OrderSide, SessionId},
+    queue::{Handle, OrderArena, OrderQueue},
+    stops::StopBook,
+    trade::{Trade, TradeError, TradeId, TradeTape},
 };
 
-const DEFAULT_LEVEL_SIZE: usize = 8;
+// where hidden orders queue within a price level relative to displayed ones
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HiddenPriority {
+    // displayed orders at a price are always matched ahead of hidden ones
+    #[default]
+    DisplayedFirst,
+    // hidden and displayed orders share the same time priority
+    TimePriority,
+}
 
-trait Ladder: Deref + DerefMut {
-    fn insert(&mut self, order: &Order) -> Result<&mut Self, OrderbookError>;
+// price levels of one side of the book, iterated from the best price
+pub(crate) trait Ladder<S: Side> {
+    // the level at the price, created if it doesn't exist
+    fn level_or_insert(&mut self, price: OrderPrice) -> Result<&mut PriceLevel, OrderbookError>;
 
-    fn remove(&mut self, order: &Order) -> Result<&mut Self, OrderbookError>;
-}
+    fn level(&self, price: OrderPrice) -> Option<&PriceLevel>;
 
-#[derive(Default)]
-struct LadderWrapper<T>(T);
+    fn level_mut(&mut self, price: OrderPrice) -> Option<&mut PriceLevel>;
 
-impl<T> Deref for LadderWrapper<T> {
-    type Target = T;
+    fn remove_level(&mut self, price: OrderPrice);
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
-}
+    fn best_mut(&mut self) -> Option<&mut PriceLevel>;
 
-impl<T> DerefMut for LadderWrapper<T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
-    }
-}
+    fn levels(&self) -> impl Iterator<Item = &PriceLevel>;
 
-impl<K: Ord> LadderWrapper<BTreeMap<K, PriceLevel>> {
-    fn peek_top<'a>(&'a self, orders: &'a IndexMap<OrderId, Order>) -> Option<&'a Order> {
-        self.first_key_value()
-            .map(|(_, level)| level)?
-            .front()
-            .and_then(|order_id| orders.get(order_id))
+    #[inline]
+    fn pop_best(&mut self) {
+        let best = self.levels().next().map(|level| level.price);
+        if let Some(price) = best {
+            self.remove_level(price);
+        }
     }
-}
 
-impl Ladder for LadderWrapper<BTreeMap<OrderPrice, PriceLevel>> {
-    fn insert(&mut self, order: &Order) -> Result<&mut Self, OrderbookError> {
+    fn insert(
+        &mut self,
+        orders: &mut OrderArena,
+        order: Order,
+        priority: HiddenPriority,
+    ) -> Result<Handle, OrderbookError> {
         let limit_price = order
             .limit_price()
-            .ok_or(OrderbookError::OrderToInsertWithNoLimitPrice(*order))?;
-        let price_level = self
-            .0
-            .entry(limit_price)
-            .or_insert_with(|| PriceLevel::new(limit_price));
+            .ok_or(OrderbookError::OrderToInsertWithNoLimitPrice(order.id()))?;
+        if !self.can_rest(&order) {
+            return Err(OrderbookError::QuantityOverflow(order.id()));
+        }
+        let price_level = self.level_or_insert(limit_price)?;
 
-        price_level.quantity += order.remaining();
-        price_level.push_back(order.id());
+        Ok(price_level.enqueue(orders, order, priority))
+    }
 
-        Ok(self)
+    // whether what's left of the order fits in its level without the quantity of the level overflowing
+    #[inline]
+    fn can_rest(&self, order: &Order) -> bool {
+        order.limit_price().is_none_or(|price| {
+            self.level(price)
+                .is_none_or(|level| level.total_quantity().checked_add(order.remaining()).is_some())
+        })
     }
 
-    fn remove(&mut self, order: &Order) -> Result<&mut Self, OrderbookError> {
+    fn remove(&mut self, orders: &mut OrderArena, handle: Handle) -> Result<Order, OrderbookError> {
+        let order = &orders[handle];
         let limit_price = order
             .limit_price()
-            .ok_or(OrderbookError::OrderToRemoveWithNoLimitPrice(*order))?;
-        let Entry::Occupied(mut price_level) = self.0.entry(limit_price) else {
+            .ok_or(OrderbookError::OrderToRemoveWithNoLimitPrice(order.id()))?;
+        let Some(price_level) = self.level_mut(limit_price) else {
             unreachable!();
         };
 
-        if price_level.get().len() == 1 {
-            price_level.remove();
-        } else {
-            let price_level = price_level.get_mut();
-            price_level.quantity -= order.remaining();
-            if let Some(idx) = price_level.iter().position(|&order_id| order.id() == order_id) {
-                price_level.remove(idx);
+        let order = price_level.dequeue(orders, handle);
+        if price_level.is_empty() {
+            self.remove_level(limit_price);
+        }
+
+        Ok(order)
+    }
+
+    fn peek_top<'a>(&self, orders: &'a OrderArena) -> Option<&'a Order> {
+        self.levels().next()?.front().map(|handle| &orders[handle])
+    }
+
+    // same as peek_top but skipping hidden orders (and levels with only hidden orders)
+    fn peek_top_displayed<'a>(&self, orders: &'a OrderArena) -> Option<&'a Order> {
+        self.levels()
+            .find(|level| !level.quantity.is_zero())?
+            .queue
+            .iter(orders)
+            .map(|(_, order)| order)
+            .find(|order| !order.is_hidden())
+    }
+
+    // cost of taking up to `quantity` from the best levels (hidden liquidity included)
+    fn sweep_cost(&self, mut quantity: OrderQuantity) -> Decimal {
+        let mut cost = Decimal::ZERO;
+        for level in self.levels() {
+            if quantity.is_zero() {
+                break;
             }
+            let traded = quantity.min(level.total_quantity());
+            cost += traded * level.price;
+            quantity = quantity.saturating_sub(traded);
         }
+        cost
+    }
 
-        Ok(self)
+    fn depth(&self, levels: usize) -> Vec<(OrderPrice, OrderQuantity)> {
+        self.levels()
+            .filter(|level| !level.quantity.is_zero())
+            .take(levels)
+            .map(|level| (level.price, level.quantity))
+            .collect()
     }
 }
 
-impl Ladder for LadderWrapper<BTreeMap<Reverse<OrderPrice>, PriceLevel>> {
-    fn insert(&mut self, order: &Order) -> Result<&mut Self, OrderbookError> {
-        let limit_price = order
-            .limit_price()
-            .ok_or(OrderbookError::OrderToInsertWithNoLimitPrice(*order))?;
-        let price_level = self
-            .0
-            .entry(Reverse(limit_price))
-            .or_insert_with(|| PriceLevel::new(limit_price));
+// levels in an ordered map keyed by the side, so the best price is always the first entry
+pub(crate) struct TreeLadder<S: Side>(BTreeMap<S::Key, PriceLevel>);
 
-        price_level.quantity += order.remaining();
-        price_level.push_back(order.id());
+impl<S: Side> Default for TreeLadder<S> {
+    fn default() -> Self {
+        Self(BTreeMap::new())
+    }
+}
 
-        Ok(self)
+impl<S: Side> Ladder<S> for TreeLadder<S> {
+    #[inline]
+    fn level_or_insert(&mut self, price: OrderPrice) -> Result<&mut PriceLevel, OrderbookError> {
+        Ok(self.0.entry(S::key(price)).or_insert_with(|| PriceLevel::new(price)))
     }
 
-    fn remove(&mut self, order: &Order) -> Result<&mut Self, OrderbookError> {
-        let limit_price = order
-            .limit_price()
-            .ok_or(OrderbookError::OrderToRemoveWithNoLimitPrice(*order))?;
-        let Entry::Occupied(mut price_level) = self.0.entry(Reverse(limit_price)) else {
-            unreachable!();
-        };
+    #[inline]
+    fn level(&self, price: OrderPrice) -> Option<&PriceLevel> {
+        self.0.get(&S::key(price))
+    }
 
-        if price_level.get().len() == 1 {
-            price_level.remove();
-        } else {
-            let price_level = price_level.get_mut();
-            price_level.quantity -= order.remaining();
-            if let Some(idx) = price_level.iter().position(|&order_id| order.id() == order_id) {
-                price_level.remove(idx);
+    #[inline]
+    fn level_mut(&mut self, price: OrderPrice) -> Option<&mut PriceLevel> {
+        self.0.get_mut(&S::key(price))
+    }
+
+    #[inline]
+    fn remove_level(&mut self, price: OrderPrice) {
+        self.0.remove(&S::key(price));
+    }
+
+    #[inline]
+    fn best_mut(&mut self) -> Option<&mut PriceLevel> {
+        self.0.values_mut().next()
+    }
+
+    #[inline]
+    fn levels(&self) -> impl Iterator<Item = &PriceLevel> {
+        self.0.values()
+    }
+}
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+// one level per tick within a bounded price range, preallocated, with a bitmap of the levels in use
+// and the index of the best one (the lowest price for asks, the highest for bids)
+pub(crate) struct ArrayLadder<S: Side> {
+    side: PhantomData<S>,
+    min_price: OrderPrice,
+    tick_size: OrderPrice,
+    levels: Vec<PriceLevel>,
+    occupied: Vec<u64>,
+    best: Option<usize>,
+}
+
+impl<S: Side> ArrayLadder<S> {
+    // the range must be valid (see PriceRange::ticks)
+    pub(crate) fn new(range: &PriceRange) -> Self {
+        let ticks = range.ticks().expect("valid price range");
+        let levels = (0..ticks)
+            .map(|tick| {
+                PriceLevel::new(OrderPrice::from_raw(
+                    range.min_price.raw() + tick as i64 * range.tick_size.raw(),
+                ))
+            })
+            .collect();
+        Self {
+            side: PhantomData,
+            min_price: range.min_price,
+            tick_size: range.tick_size,
+            levels,
+            occupied: vec![0; ticks.div_ceil(WORD_BITS)],
+            best: None,
+        }
+    }
+
+    #[inline]
+    fn index(&self, price: OrderPrice) -> Option<usize> {
+        let offset = price.checked_sub(self.min_price)?.raw();
+        if offset < 0 || offset % self.tick_size.raw() != 0 {
+            return None;
+        }
+        let idx = (offset / self.tick_size.raw()) as usize;
+        (idx < self.levels.len()).then_some(idx)
+    }
+
+    #[inline]
+    fn is_occupied(&self, idx: usize) -> bool {
+        self.occupied[idx / WORD_BITS] & (1 << (idx % WORD_BITS)) != 0
+    }
+
+    #[inline]
+    fn is_better(&self, idx: usize, than: usize) -> bool {
+        S::is_better(self.levels[idx].price, self.levels[than].price)
+    }
+
+    // the first level in use from `idx` (included) towards worse prices
+    #[inline]
+    fn next_occupied(&self, idx: usize) -> Option<usize> {
+        match S::SIDE {
+            OrderSide::Ask => {
+                let mut word = idx / WORD_BITS;
+                let mut bits = *self.occupied.get(word)? & (u64::MAX << (idx % WORD_BITS));
+                loop {
+                    if bits != 0 {
+                        return Some(word * WORD_BITS + bits.trailing_zeros() as usize);
+                    }
+                    word += 1;
+                    bits = *self.occupied.get(word)?;
+                }
+            }
+            OrderSide::Bid => {
+                let mut word = idx / WORD_BITS;
+                let mut bits = self.occupied[word] & (u64::MAX >> (WORD_BITS - 1 - idx % WORD_BITS));
+                loop {
+                    if bits != 0 {
+                        return Some(word * WORD_BITS + WORD_BITS - 1 - bits.leading_zeros() as usize);
+                    }
+                    word = word.checked_sub(1)?;
+                    bits = self.occupied[word];
+                }
             }
         }
+    }
 
-        Ok(self)
+    // the level in use right after `idx` towards worse prices
+    #[inline]
+    fn after(&self, idx: usize) -> Option<usize> {
+        match S::SIDE {
+            OrderSide::Ask => self.next_occupied(idx + 1),
+            OrderSide::Bid => self.next_occupied(idx.checked_sub(1)?),
+        }
     }
 }
 
-type AsksLadder = LadderWrapper<BTreeMap<OrderPrice, PriceLevel>>;
-type BidsLadder = LadderWrapper<BTreeMap<Reverse<OrderPrice>, PriceLevel>>;
+impl<S: Side> Ladder<S> for ArrayLadder<S> {
+    #[inline]
+    fn level_or_insert(&mut self, price: OrderPrice) -> Result<&mut PriceLevel, OrderbookError> {
+        let idx = self.index(price).ok_or(OrderbookError::PriceOutOfRange(price))?;
+        if !self.is_occupied(idx) {
+            self.occupied[idx / WORD_BITS] |= 1 << (idx % WORD_BITS);
+            if self.best.is_none_or(|best| self.is_better(idx, best)) {
+                self.best = Some(idx);
+            }
+        }
+        Ok(&mut self.levels[idx])
+    }
+
+    #[inline]
+    fn level(&self, price: OrderPrice) -> Option<&PriceLevel> {
+        let idx = self.index(price).filter(|idx| self.is_occupied(*idx))?;
+        Some(&self.levels[idx])
+    }
+
+    #[inline]
+    fn level_mut(&mut self, price: OrderPrice) -> Option<&mut PriceLevel> {
+        let idx = self.index(price).filter(|idx| self.is_occupied(*idx))?;
+        Some(&mut self.levels[idx])
+    }
+
+    #[inline]
+    fn remove_level(&mut self, price: OrderPrice) {
+        let Some(idx) = self.index(price).filter(|idx| self.is_occupied(*idx)) else {
+            return;
+        };
+        self.occupied[idx / WORD_BITS] &= !(1 << (idx % WORD_BITS));
+        self.levels[idx] = PriceLevel::new(price);
+        if self.best == Some(idx) {
+            self.best = self.after(idx);
+        }
+    }
+
+    #[inline]
+    fn best_mut(&mut self) -> Option<&mut PriceLevel> {
+        Some(&mut self.levels[self.best?])
+    }
+
+    #[inline]
+    fn levels(&self) -> impl Iterator<Item = &PriceLevel> {
+        std::iter::successors(self.best, |idx| self.after(*idx)).map(|idx| &self.levels[idx])
+    }
+}
+
+// bounded price range of an instrument, the levels of both sides can then be stored in arrays indexed by tick
+// instead of ordered maps
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PriceRange {
+    pub min_price: OrderPrice,
+    pub max_price: OrderPrice,
+    pub tick_size: OrderPrice,
+}
+
+// the levels of each side are preallocated, this bounds the memory of a book in arrays
+pub const MAX_LADDER_TICKS: usize = 1 << 18;
+
+impl PriceRange {
+    // number of levels of each side, none unless the range is positive, in order and no wider than the maximum
+    #[inline]
+    pub fn ticks(&self) -> Option<usize> {
+        if self.tick_size <= OrderPrice::ZERO || self.min_price <= OrderPrice::ZERO || self.min_price > self.max_price {
+            return None;
+        }
+        let ticks = self.max_price.checked_sub(self.min_price)?.raw() / self.tick_size.raw() + 1;
+        usize::try_from(ticks).ok().filter(|ticks| *ticks <= MAX_LADDER_TICKS)
+    }
+}
+
+// the ladder of a side, with the levels in a tree or in an array
+pub(crate) enum SideLadder<S: Side> {
+    Tree(TreeLadder<S>),
+    Array(ArrayLadder<S>),
+}
+
+impl<S: Side> Default for SideLadder<S> {
+    fn default() -> Self {
+        Self::Tree(TreeLadder::default())
+    }
+}
+
+// either kind of iterator over the levels
+enum Levels<A, B> {
+    Tree(A),
+    Array(B),
+}
+
+impl<'a, A, B> Iterator for Levels<A, B>
+where
+    A: Iterator<Item = &'a PriceLevel>,
+    B: Iterator<Item = &'a PriceLevel>,
+{
+    type Item = &'a PriceLevel;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Levels::Tree(levels) => levels.next(),
+            Levels::Array(levels) => levels.next(),
+        }
+    }
+}
+
+impl<S: Side> Ladder<S> for SideLadder<S> {
+    #[inline]
+    fn level_or_insert(&mut self, price: OrderPrice) -> Result<&mut PriceLevel, OrderbookError> {
+        match self {
+            SideLadder::Tree(ladder) => ladder.level_or_insert(price),
+            SideLadder::Array(ladder) => ladder.level_or_insert(price),
+        }
+    }
+
+    #[inline]
+    fn level(&self, price: OrderPrice) -> Option<&PriceLevel> {
+        match self {
+            SideLadder::Tree(ladder) => ladder.level(price),
+            SideLadder::Array(ladder) => ladder.level(price),
+        }
+    }
+
+    #[inline]
+    fn level_mut(&mut self, price: OrderPrice) -> Option<&mut PriceLevel> {
+        match self {
+            SideLadder::Tree(ladder) => ladder.level_mut(price),
+            SideLadder::Array(ladder) => ladder.level_mut(price),
+        }
+    }
+
+    #[inline]
+    fn remove_level(&mut self, price: OrderPrice) {
+        match self {
+            SideLadder::Tree(ladder) => ladder.remove_level(price),
+            SideLadder::Array(ladder) => ladder.remove_level(price),
+        }
+    }
+
+    #[inline]
+    fn best_mut(&mut self) -> Option<&mut PriceLevel> {
+        match self {
+            SideLadder::Tree(ladder) => ladder.best_mut(),
+            SideLadder::Array(ladder) => ladder.best_mut(),
+        }
+    }
+
+    #[inline]
+    fn levels(&self) -> impl Iterator<Item = &PriceLevel> {
+        match self {
+            SideLadder::Tree(ladder) => Levels::Tree(ladder.levels()),
+            SideLadder::Array(ladder) => Levels::Array(ladder.levels()),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct PriceLevel {
-    order_ids: VecDeque<OrderId>,
-    quantity: OrderQuantity,
-    price: OrderPrice,
+    queue: OrderQueue,
+    last_displayed: Option<Handle>, // where displayed orders queue when they go first
+    quantity: OrderQuantity,        // displayed quantity, the only one published
+    hidden_quantity: OrderQuantity,
+    pub(crate) price: OrderPrice,
 }
 
 impl PriceLevel {
     fn new(price: OrderPrice) -> Self {
         Self {
-            order_ids: VecDeque::with_capacity(DEFAULT_LEVEL_SIZE),
-            quantity: Decimal::ZERO,
+            queue: OrderQueue::default(),
+            last_displayed: None,
+            quantity: OrderQuantity::ZERO,
+            hidden_quantity: OrderQuantity::ZERO,
             price,
         }
     }
-}
 
-impl Deref for PriceLevel {
-    type Target = VecDeque<OrderId>;
+    #[inline]
+    pub(crate) fn total_quantity(&self) -> OrderQuantity {
+        self.quantity.saturating_add(self.hidden_quantity)
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.order_ids
+    #[inline]
+    pub(crate) fn front(&self) -> Option<Handle> {
+        self.queue.front()
     }
-}
 
-impl DerefMut for PriceLevel {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.order_ids
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    // the ladder checks first that the order fits (can_rest), so the quantities can't overflow
+    #[inline]
+    fn enqueue(&mut self, orders: &mut OrderArena, order: Order, priority: HiddenPriority) -> Handle {
+        if order.is_hidden() {
+            self.hidden_quantity = self.hidden_quantity.saturating_add(order.remaining());
+            return self.queue.push_back(orders, order);
+        }
+
+        self.quantity = self.quantity.saturating_add(order.remaining());
+        match priority {
+            // when displayed orders go first they are always queued ahead of the hidden ones
+            HiddenPriority::DisplayedFirst => {
+                let handle = self.queue.insert_after(orders, self.last_displayed, order);
+                self.last_displayed = Some(handle);
+                handle
+            }
+            HiddenPriority::TimePriority => self.queue.push_back(orders, order),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn fill(&mut self, maker: &Order, traded: OrderQuantity) {
+        if maker.is_hidden() {
+            self.hidden_quantity = self.hidden_quantity.saturating_sub(traded);
+        } else {
+            self.quantity = self.quantity.saturating_sub(traded);
+        }
+    }
+
+    // O(1) wherever the order is in the queue
+    #[inline]
+    pub(crate) fn unlink(&mut self, orders: &mut OrderArena, handle: Handle) -> Order {
+        if self.last_displayed == Some(handle) {
+            self.last_displayed = orders.prev(handle);
+        }
+        self.queue.remove(orders, handle)
+    }
+
+    #[inline]
+    fn dequeue(&mut self, orders: &mut OrderArena, handle: Handle) -> Order {
+        let order = self.unlink(orders, handle);
+        if order.is_hidden() {
+            self.hidden_quantity = self.hidden_quantity.saturating_sub(order.remaining());
+        } else {
+            self.quantity = self.quantity.saturating_sub(order.remaining());
+        }
+        order
     }
 }
 
 impl PriceLevel {
     #[inline]
-    fn is_closed(&self) -> bool {
-        self.quantity == OrderQuantity::zero()
+    pub(crate) fn is_closed(&self) -> bool {
+        self.total_quantity().is_zero()
     }
 
     #[inline]
     pub fn can_trade(&self, order: &Order) -> bool {
-        self.quantity.min(order.remaining()) != OrderQuantity::ZERO
+        self.total_quantity().min(order.remaining()) != OrderQuantity::ZERO
     }
 
     #[inline]
@@ -193,177 +536,453 @@ impl PriceLevel {
 
 impl Display for PriceLevel {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} [{:?}]", self.quantity, self.order_ids)
+        write!(f, "{} [{} orders]", self.quantity, self.queue.len())
     }
 }
 
-macro_rules! match_order {
-    ($incoming_order:ident, $orders:ident, $trades:ident, $order_ladder:ident, $opposite_ladder:ident) =>  {
-        'exit: {
-        // PostOnly orders should go directly to the book; otherwise, if they can be matched inmediately, then they should be canceled
-        if $incoming_order.is_post_only()
-            && $opposite_ladder
-                .peek_top($orders)
-                .is_some_and(|top_order| $incoming_order.matches(top_order))
-        {
-            $incoming_order.cancel();
-            break 'exit Ok(false);
-        }
-
-        // FOK orders should be canceled if they cannot be fill completely
-        if $incoming_order.is_fill_or_kill() {
-            let mut can_be_filled = false;
-            let mut remaining = $incoming_order.remaining();
-                for (_, price_level) in $opposite_ladder.iter_mut() {
-                if $incoming_order.is_closed() || !price_level.matches(& $incoming_order) {
-                    break;
-                }
-                remaining -= price_level.quantity;
-                if (remaining <= Decimal::ZERO) {
-                    can_be_filled = true;
-                    break;
-                }
-            }
-            if !can_be_filled {
-                $incoming_order.cancel();
-                break 'exit Ok(false);
+#[derive(Default)]
+pub struct Orderbook {
+    asks: SideLadder<Asks>,
+    bids: SideLadder<Bids>,
+    orders: OrderArena,                 // resting orders
+    handles: IndexMap<OrderId, Handle>, // where each resting order is in the arena
+    filled: Vec<OrderId>,               // makers closed while matching, reused to update the index after each sweep
+    stops: StopBook,
+    trades: TradeTape,           // until the engine drains them
+    triggered: Vec<OrderId>,     // reused by every trade that triggers stops
+    pending: Vec<OrderId>,       // stops triggered while the book wasn't matching, in the order they were triggered
+    dropped: Vec<Order>,         // triggered stops that couldn't fit in their level, until the engine drains them
+    matched_stops: Vec<OrderId>, // triggered stops that went through matching, until the engine drains them
+    last_trade_price: Option<OrderPrice>,
+    hidden_priority: HiddenPriority,
+    price_band: Option<PriceBand>,
+    band_breached: bool,
+    // crossed levels, reused by every indicative auction
+    auction_bids: Vec<(OrderPrice, OrderQuantity)>,
+    auction_asks: Vec<(OrderPrice, OrderQuantity)>,
+}
+
+type MatchResult = Result<bool, OrderbookError>;
+#[cfg(test)]
+const MATCHED: MatchResult = Ok(true);
+#[cfg(test)]
+const NOT_MATCHED: MatchResult = Ok(false);
+
+type CancelResult = Result<Order, OrderbookError>;
+type MassCancelResult = Result<Vec<Order>, OrderbookError>;
+
+// every filter set must match for the order to be canceled, an empty filter matches all orders
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MassCancelFilter {
+    pub account: Option<AccountId>,
+    pub side: Option<OrderSide>,
+    pub min_price: Option<OrderPrice>,
+    pub max_price: Option<OrderPrice>,
+}
+
+impl MassCancelFilter {
+    #[inline]
+    pub fn matches(&self, order: &Order) -> bool {
+        let price_filtered = self.min_price.is_some() || self.max_price.is_some();
+        let in_range = match order.limit_price() {
+            Some(limit_price) => {
+                self.min_price.is_none_or(|min_price| limit_price >= min_price)
+                    && self.max_price.is_none_or(|max_price| limit_price <= max_price)
             }
+            None => !price_filtered, // market stops have no price to filter by
+        };
+
+        in_range
+            && self.account.is_none_or(|account| order.account() == account)
+            && self.side.is_none_or(|side| order.side() == side)
+    }
+}
+
+impl Orderbook {
+    // preallocate room for `capacity` resting orders (and as many trades on the tape) so the book doesn't
+    // allocate while it stays below that
+    #[inline]
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.orders.reserve(capacity);
+        self.handles.reserve(capacity);
+        self.filled.reserve(capacity);
+        self.trades.reserve(capacity);
+        self
+    }
+
+    // a range that can't be laid out in arrays (see PriceRange::ticks) keeps the levels in trees
+    #[inline]
+    pub fn with_price_range(mut self, range: PriceRange) -> Self {
+        if range.ticks().is_none() {
+            return self;
         }
+        self.asks = SideLadder::Array(ArrayLadder::new(&range));
+        self.bids = SideLadder::Array(ArrayLadder::new(&range));
+        self
+    }
 
-        let mut matched = false;
-        let mut trades: Vec<Trade> = vec![];
-        let mut drained_levels = 0;
+    #[inline]
+    pub fn with_hidden_priority(mut self, hidden_priority: HiddenPriority) -> Self {
+        self.hidden_priority = hidden_priority;
+        self
+    }
 
-        for (_, price_level) in $opposite_ladder.iter_mut() {
-            if $incoming_order.is_closed() || !price_level.matches(&$incoming_order) {
-                break;
-            }
+    // top of the book as displayed, hidden orders are never shown
+    #[inline]
+    pub fn peek_top(&self, side: &OrderSide) -> Option<&Order> {
+        match side {
+            OrderSide::Ask => self.asks.peek_top_displayed(&self.orders),
+            OrderSide::Bid => self.bids.peek_top_displayed(&self.orders),
+        }
+    }
 
-            let mut total_traded = OrderQuantity::ZERO;
-            let mut orders_completed = 0;
+    // displayed quantity aggregated by price for the best `levels` prices of the side
+    #[inline]
+    pub fn depth(&self, side: &OrderSide, levels: usize) -> Vec<(OrderPrice, OrderQuantity)> {
+        match side {
+            OrderSide::Ask => self.asks.depth(levels),
+            OrderSide::Bid => self.bids.depth(levels),
+        }
+    }
 
-            for order_id in price_level.iter_mut() {
-                if $incoming_order.is_closed() {
-                    break;
-                }
+    // cost of an order of the side taking `quantity` right now (e.g. to reserve funds for a market bid)
+    #[inline]
+    pub fn sweep_cost(&self, side: &OrderSide, quantity: OrderQuantity) -> Decimal {
+        match side {
+            OrderSide::Ask => self.bids.sweep_cost(quantity),
+            OrderSide::Bid => self.asks.sweep_cost(quantity),
+        }
+    }
 
-                let maker = $orders
-                    .get_mut(order_id)
-                    .ok_or(OrderbookError::OrderToMatchNotFound(*order_id))?;
-                let traded = $incoming_order.can_trade(maker);
+    // a live order, either resting in the book or waiting to be triggered
+    #[inline]
+    pub fn order(&self, order_id: OrderId) -> Option<&Order> {
+        self.handles
+            .get(&order_id)
+            .map(|handle| &self.orders[*handle])
+            .or_else(|| self.stops.get(order_id))
+    }
 
-                let trade = Trade::new(&mut $incoming_order, maker, traded).map_err(OrderbookError::TradeError)?;
-                trades.push(trade);
+    // whether the order is live, either resting in the book or waiting to be triggered
+    #[inline]
+    pub fn contains(&self, order_id: OrderId) -> bool {
+        self.handles.contains_key(&order_id) || self.stops.contains(order_id)
+    }
 
-                matched = true;
+    #[inline]
+    pub fn trades(&self) -> &TradeTape {
+        &self.trades
+    }
 
-                total_traded += traded;
-                if maker.is_closed() {
-                    orders_completed += 1;
-                }
-            }
+    // hand the trades done so far over (e.g. to be settled), so the tape doesn't grow with every trade
+    #[inline]
+    pub fn drain_trades(&mut self, into: &mut Vec<Trade>) {
+        self.trades.drain_into(into);
+    }
 
-            price_level.quantity -= total_traded;
-            for _ in 0..orders_completed {
-                price_level.pop_front().and_then(|order_id| $orders.swap_remove(& order_id));
-            }
+    // hand the triggered stops dropped so far over (e.g. to be reported as canceled)
+    #[inline]
+    pub fn drain_dropped(&mut self, into: &mut Vec<Order>) {
+        into.append(&mut self.dropped);
+    }
 
-            if price_level.quantity == OrderQuantity::ZERO {
-                drained_levels += 1;
-            }
-        }
-        for _ in 0..drained_levels {
-            $opposite_ladder.pop_first();
-        }
+    // hand the triggered stops matched so far over (e.g. to close those that didn't rest, even without trading)
+    #[inline]
+    pub fn drain_matched_stops(&mut self, into: &mut Vec<OrderId>) {
+        into.append(&mut self.matched_stops);
+    }
 
-        // save trades
-        for trade in trades {
-            $trades.insert(trade.id(), trade);
-        }
+    #[inline]
+    pub fn last_trade_price(&self) -> Option<OrderPrice> {
+        self.last_trade_price
+    }
 
-        // IOC orders should be closed at the end of the matching phase (this is, no insertion in the book)
-        if $incoming_order.is_immediate_or_cancel() {
-            $incoming_order.cancel();
-            break 'exit Ok(matched);
+    #[inline]
+    pub fn handle_create(&mut self, mut order: Order) -> MatchResult {
+        if self.contains(order.id()) {
+            return Err(OrderbookError::OrderDuplicated(order.id()));
         }
 
-        // insert limit order in the book
-        if !$incoming_order.is_closed() && $incoming_order.is_bookable() {
-            $order_ladder.insert(&$incoming_order)?;
-            $orders.insert($incoming_order.id(), $incoming_order);
+        // stop orders wait outside the book until the last traded price crosses the stop price
+        if order.is_stop() {
+            let triggered = self
+                .last_trade_price
+                .is_some_and(|last_price| order.update_trigger(last_price));
+            if !triggered {
+                self.stops.insert(order);
+                return Ok(false);
+            }
+            order.activate();
         }
 
-        Ok(matched)
-    }};
-}
+        let first_trade = self.trades.len();
+        let matched = self.match_order(order)?;
+        self.trigger_stops(first_trade, true)?;
 
-#[derive(Default)]
-pub struct Orderbook {
-    asks: AsksLadder,
-    bids: BidsLadder,
-    orders: IndexMap<OrderId, Order>,
-    trades: IndexMap<TradeId, Trade>,
-}
+        Ok(matched)
+    }
 
-type MatchResult = Result<bool, OrderbookError>;
-#[cfg(test)]
-const MATCHED: MatchResult = Ok(true);
-#[cfg(test)]
-const NOT_MATCHED: MatchResult = Ok(false);
+    // trades are numbered in the order the book did them, so the ids only depend on what the book has seen
+    #[inline]
+    pub fn next_trade_id(&self) -> TradeId {
+        self.trades.next_id()
+    }
 
-type CancelResult = Result<Order, OrderbookError>;
+    // trades done off the book (e.g. block trades) only go to the trade tape
+    #[inline]
+    pub fn print_trade(&mut self, trade: Trade) {
+        self.trades.push(trade);
+    }
 
-impl Orderbook {
+    // band for the takers from now on (none to trade at any price)
     #[inline]
-    pub fn peek_top(&self, side: &OrderSide) -> Option<&Order> {
-        match side {
-            OrderSide::Ask => self.asks.peek_top(&self.orders),
-            OrderSide::Bid => self.bids.peek_top(&self.orders),
-        }
+    pub fn set_price_band(&mut self, price_band: Option<PriceBand>) {
+        self.price_band = price_band;
     }
 
+    // whether a taker was stopped at the price band since the last call
     #[inline]
-    pub fn handle_create(&mut self, mut order: Order) -> MatchResult {
-        if self.orders.contains_key(&order.id()) {
+    pub fn take_band_breach(&mut self) -> bool {
+        std::mem::take(&mut self.band_breached)
+    }
+
+    // rest the order in the book without matching it, e.g. while the instrument is not in continuous trading
+    pub fn handle_rest(&mut self, order: Order) -> Result<(), OrderbookError> {
+        if self.contains(order.id()) {
             return Err(OrderbookError::OrderDuplicated(order.id()));
         }
+        if order.is_stop() {
+            self.stops.insert(order);
+            return Ok(());
+        }
+        if !order.is_bookable() || order.is_immediate_or_cancel() || order.is_fill_or_kill() {
+            return Err(OrderbookError::OrderNotBookable(order.id()));
+        }
 
-        let orders = &mut self.orders;
-        let trades = &mut self.trades;
+        let hidden_priority = self.hidden_priority;
+        let order_id = order.id();
+        let handle = match order.side() {
+            OrderSide::Ask => self.asks.insert(&mut self.orders, order, hidden_priority)?,
+            OrderSide::Bid => self.bids.insert(&mut self.orders, order, hidden_priority)?,
+        };
+        self.handles.insert(order_id, handle);
 
-        match order.side() {
-            OrderSide::Ask => {
-                let order_ladder = &mut self.asks;
-                let opposite_ladder = &mut self.bids;
-                match_order!(order, orders, trades, order_ladder, opposite_ladder)
+        Ok(())
+    }
+
+    // outcome of uncrossing the book right now (none if the book is not crossed)
+    pub fn indicative_auction(&mut self, reference_price: Option<OrderPrice>) -> Option<AuctionResult> {
+        // only the crossed levels can trade, a bid below the best ask (or an ask above the best bid) never does
+        let best_bid = self.bids.levels().next()?.price;
+        let best_ask = self.asks.levels().next()?.price;
+        self.auction_bids.clear();
+        self.auction_bids.extend(
+            self.bids
+                .levels()
+                .take_while(|level| level.price >= best_ask)
+                .map(|level| (level.price, level.total_quantity())),
+        );
+        self.auction_asks.clear();
+        self.auction_asks.extend(
+            self.asks
+                .levels()
+                .take_while(|level| level.price <= best_bid)
+                .map(|level| (level.price, level.total_quantity())),
+        );
+        auction::clearing_price(&self.auction_bids, &self.auction_asks, reference_price)
+    }
+
+    // execute all the crossing orders at the clearing price in price-time order, with the bids as takers
+    // stops triggered by the auction price are only matched if the book is matching afterwards (otherwise they
+    // wait until it is, see trigger_pending_stops)
+    pub fn uncross(
+        &mut self,
+        reference_price: Option<OrderPrice>,
+        matching: bool,
+    ) -> Result<Option<AuctionResult>, OrderbookError> {
+        let Some(auction) = self.indicative_auction(reference_price) else {
+            return Ok(None);
+        };
+
+        let first_trade = self.trades.len();
+        while let (Some(bid_level), Some(ask_level)) = (self.bids.best_mut(), self.asks.best_mut()) {
+            if bid_level.price < auction.price || ask_level.price > auction.price {
+                break;
             }
-            OrderSide::Bid => {
-                let order_ladder = &mut self.bids;
-                let opposite_ladder = &mut self.asks;
-                match_order!(order, orders, trades, order_ladder, opposite_ladder)
+
+            let (Some(bid_handle), Some(ask_handle)) = (bid_level.front(), ask_level.front()) else {
+                break;
+            };
+            let Some((bid, ask)) = self.orders.pair_mut(bid_handle, ask_handle) else {
+                break;
+            };
+            let traded = bid.remaining().min(ask.remaining());
+            let trade = Trade::with_price(self.trades.next_id(), bid, ask, traded, auction.price)?;
+            self.trades.push(trade);
+
+            bid_level.fill(bid, traded);
+            ask_level.fill(ask, traded);
+            let (bid_closed, ask_closed) = (bid.is_closed(), ask.is_closed());
+            if bid_closed {
+                let bid = bid_level.unlink(&mut self.orders, bid_handle);
+                self.filled.push(bid.id());
             }
+            if ask_closed {
+                let ask = ask_level.unlink(&mut self.orders, ask_handle);
+                self.filled.push(ask.id());
+            }
+            let (bid_drained, ask_drained) = (bid_level.is_closed(), ask_level.is_closed());
+            if bid_drained {
+                self.bids.pop_best();
+            }
+            if ask_drained {
+                self.asks.pop_best();
+            }
+        }
+        for order_id in self.filled.drain(..) {
+            self.handles.swap_remove(&order_id);
+        }
+        self.trigger_stops(first_trade, matching)?;
+
+        Ok(Some(auction))
+    }
+
+    // match the stops triggered while the book wasn't matching, as if they had been triggered right now
+    pub fn trigger_pending_stops(&mut self) -> Result<(), OrderbookError> {
+        if self.pending.is_empty() {
+            return Ok(());
         }
+
+        let first_trade = self.trades.len();
+        for order_id in std::mem::take(&mut self.pending) {
+            // unless they have been canceled in the meantime
+            if let Some(order) = self.stops.remove(order_id) {
+                self.match_triggered(order)?;
+            }
+        }
+        self.trigger_stops(first_trade, true)
+    }
+
+    #[inline]
+    fn match_order(&mut self, order: Order) -> MatchResult {
+        let mut matcher = Matcher {
+            orders: &mut self.orders,
+            handles: &mut self.handles,
+            filled: &mut self.filled,
+            trades: &mut self.trades,
+            hidden_priority: self.hidden_priority,
+            price_band: self.price_band,
+            band_breached: false,
+        };
+        let matched = match order.side() {
+            OrderSide::Ask => matcher.match_order::<Asks>(order, &mut self.asks, &mut self.bids),
+            OrderSide::Bid => matcher.match_order::<Bids>(order, &mut self.bids, &mut self.asks),
+        };
+        self.band_breached |= matcher.band_breached;
+
+        matched
+    }
+
+    // a triggered stop that can't fit in its level is dropped before trading, the trades already done by the
+    // order that triggered it (and the other stops) stand
+    #[inline]
+    fn match_triggered(&mut self, mut order: Order) -> Result<(), OrderbookError> {
+        match self.match_order(order) {
+            Err(OrderbookError::QuantityOverflow(_)) => {
+                order.cancel();
+                self.dropped.push(order);
+                Ok(())
+            }
+            matched => {
+                self.matched_stops.push(order.id());
+                matched.map(|_| ())
+            }
+        }
+    }
+
+    // walk the trades produced since `next_trade` (including those of the stops triggered along the way)
+    // so trailing stops follow every traded price and the triggered ones go through the regular matching,
+    // if the book is not matching they are activated but stay with the stops until it is
+    fn trigger_stops(&mut self, mut next_trade: usize, matching: bool) -> Result<(), OrderbookError> {
+        while let Some(trade) = self.trades.get(next_trade) {
+            let last_price = trade.price();
+            self.last_trade_price = Some(last_price);
+            next_trade += 1;
+
+            if self.stops.is_empty() {
+                continue;
+            }
+
+            self.stops.trigger(last_price, &mut self.triggered);
+            for idx in 0..self.triggered.len() {
+                let order_id = self.triggered[idx];
+                if !matching {
+                    self.stops.activate(order_id);
+                    self.pending.push(order_id);
+                } else if let Some(mut order) = self.stops.remove(order_id) {
+                    order.activate();
+                    self.match_triggered(order)?;
+                }
+            }
+        }
+
+        Ok(())
     }
 
     #[inline]
     pub fn handle_cancel(&mut self, order_id: OrderId) -> CancelResult {
-        let order = self
-            .orders
+        if let Some(order) = self.stops.remove(order_id) {
+            return Ok(order);
+        }
+
+        let handle = self
+            .handles
             .swap_remove(&order_id)
             .ok_or(OrderbookError::OrderToCancelNotFound(order_id))?;
 
-        match order.side() {
+        match self.orders[handle].side() {
             OrderSide::Ask => {
                 let order_ladder = &mut self.asks;
-                order_ladder.remove(&order)?;
+                order_ladder.remove(&mut self.orders, handle)
             }
             OrderSide::Bid => {
                 let order_ladder = &mut self.bids;
-                order_ladder.remove(&order)?;
+                order_ladder.remove(&mut self.orders, handle)
             }
         }
+    }
 
-        Ok(order)
+    #[inline]
+    pub fn handle_mass_cancel(&mut self, filter: &MassCancelFilter) -> MassCancelResult {
+        self.cancel_matching(|order| filter.matches(order))
+    }
+
+    // persistent orders stay in the book when their session is closed
+    #[inline]
+    pub fn handle_session_closed(&mut self, session: SessionId) -> MassCancelResult {
+        self.cancel_matching(|order| order.session() == Some(session) && !order.is_persistent())
+    }
+
+    // all the matching orders (waiting stops, then the resting asks and bids in price-time order) are collected
+    // first and then canceled in one go
+    fn cancel_matching(&mut self, predicate: impl Fn(&Order) -> bool) -> MassCancelResult {
+        let mut order_ids = self.stops.select(&predicate);
+        let resting = self
+            .asks
+            .levels()
+            .chain(self.bids.levels())
+            .flat_map(|price_level| std::iter::successors(price_level.front(), |handle| self.orders.next(*handle)));
+        order_ids.extend(
+            resting
+                .map(|handle| &self.orders[handle])
+                .filter(|order| predicate(order))
+                .map(|order| order.id()),
+        );
+
+        order_ids
+            .into_iter()
+            .map(|order_id| self.handle_cancel(order_id))
+            .collect()
     }
 }
 
@@ -372,13 +991,19 @@ pub enum OrderbookError {
     #[error("an order with the same ID has been handled before! {0}")]
     OrderDuplicated(OrderId),
     #[error("order cannot be inserted into the book with no limit price! {0}")]
-    OrderToInsertWithNoLimitPrice(Order),
+    OrderToInsertWithNoLimitPrice(OrderId),
     #[error("order cannot be removed from the book with no limit price! {0}")]
-    OrderToRemoveWithNoLimitPrice(Order),
+    OrderToRemoveWithNoLimitPrice(OrderId),
+    #[error("order cannot rest in the book without matching! {0}")]
+    OrderNotBookable(OrderId),
     #[error("order to cancel not found in the book! {0}")]
     OrderToCancelNotFound(OrderId),
     #[error("order to match not found in the book! {0}")]
     OrderToMatchNotFound(OrderId),
+    #[error("price outside of the range of the ladder! {0}")]
+    PriceOutOfRange(OrderPrice),
+    #[error("order would take the quantity of its price level out of the fixed-point range! {0}")]
+    QuantityOverflow(OrderId),
     #[error("trade error: {0}")]
     TradeError(#[from] TradeError),
 }
@@ -550,7 +1175,7 @@ mod test {
             assert_ne!(ask_100_at_015.side(), bid_099_at_015.side());
             assert!(bid_099_at_015.matches(&ask_100_at_015));
 
-            let expected = ask_100_at_015.remaining() - bid_099_at_015.remaining();
+            let expected = ask_100_at_015.remaining().saturating_sub(bid_099_at_015.remaining());
             assert_eq!(orderbook.handle_create(bid_099_at_015), NOT_MATCHED);
             assert_eq!(orderbook.handle_create(ask_100_at_015), MATCHED);
 
@@ -578,7 +1203,9 @@ mod test {
             assert!(bid_099_at_015.matches(&ask_100_at_015));
             assert!(bid_020_at_016.matches(&ask_100_at_015));
 
-            let expected = bid_099_at_015.remaining() - (ask_100_at_015.remaining() - bid_020_at_016.remaining());
+            let expected = bid_099_at_015
+                .remaining()
+                .saturating_sub(ask_100_at_015.remaining().saturating_sub(bid_020_at_016.remaining()));
             assert_eq!(orderbook.handle_create(bid_099_at_015), NOT_MATCHED);
             assert_eq!(orderbook.handle_create(bid_020_at_016), NOT_MATCHED);
             assert_eq!(orderbook.handle_create(ask_100_at_015), MATCHED);
@@ -610,6 +1237,322 @@ mod test {
         }
     }
 
+    mod stop_orders {
+        use crate::order::StopTrigger;
+
+        use super::*;
+
+        #[rstest]
+        fn trigger_stop_on_trade(mut orderbook: Orderbook, bid_025_at_014: Order) {
+            // a sell stop at 14 with no trades yet waits outside the book
+            let stop = Order::market_order(OrderId::new(1), OrderSide::Ask, 4.into())
+                .with_trigger(StopTrigger::Fixed { stop_price: 14.into() });
+            assert_eq!(orderbook.handle_create(stop), NOT_MATCHED);
+            assert_eq!(orderbook.peek_top(&OrderSide::Ask), None);
+
+            // a trade at 14 triggers the stop which sells into the remaining bid
+            let ask = Order::limit_order(OrderId::new(2), OrderSide::Ask, 15.into(), 14.into());
+            assert_eq!(orderbook.handle_create(bid_025_at_014), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask), MATCHED);
+            assert!(orderbook.stops.is_empty());
+            assert_eq!(orderbook.trades.len(), 2);
+            assert_eq!(orderbook.peek_top(&OrderSide::Bid).unwrap().remaining(), 6.into());
+            assert_eq!(orderbook.last_trade_price(), Some(14.into()));
+        }
+
+        #[rstest]
+        fn trailing_stop_follows_trades(mut orderbook: Orderbook) {
+            let trailing = StopTrigger::TrailingAmount {
+                stop_price: 10.into(),
+                offset: 2.into(),
+            };
+            let stop = Order::market_order(OrderId::new(1), OrderSide::Ask, 1.into()).with_trigger(trailing);
+            assert_eq!(orderbook.handle_create(stop), NOT_MATCHED);
+
+            // a trade at 15 moves the stop up to 13 without triggering it
+            let bid = Order::limit_order(OrderId::new(2), OrderSide::Bid, 1.into(), 15.into());
+            let ask = Order::limit_order(OrderId::new(3), OrderSide::Ask, 1.into(), 15.into());
+            assert_eq!(orderbook.handle_create(bid), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask), MATCHED);
+            assert_eq!(
+                orderbook.stops.get(stop.id()).unwrap().trigger().unwrap().stop_price(),
+                13.into()
+            );
+
+            // a trade at 13 triggers the stop which hits the resting bid at 12
+            let resting_bid = Order::limit_order(OrderId::new(4), OrderSide::Bid, 5.into(), 12.into());
+            let bid = Order::limit_order(OrderId::new(5), OrderSide::Bid, 1.into(), 13.into());
+            let ask = Order::limit_order(OrderId::new(6), OrderSide::Ask, 1.into(), 13.into());
+            assert_eq!(orderbook.handle_create(resting_bid), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(bid), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask), MATCHED);
+            assert!(orderbook.stops.is_empty());
+            assert_eq!(orderbook.peek_top(&OrderSide::Bid).unwrap().remaining(), 4.into());
+            assert_eq!(orderbook.last_trade_price(), Some(12.into()));
+        }
+
+        #[rstest]
+        fn cancel_stop(mut orderbook: Orderbook) {
+            let stop = Order::market_order(OrderId::new(1), OrderSide::Bid, 1.into())
+                .with_trigger(StopTrigger::Fixed { stop_price: 20.into() });
+            assert_eq!(orderbook.handle_create(stop), NOT_MATCHED);
+            assert_eq!(orderbook.handle_cancel(stop.id()).ok(), Some(stop));
+            assert!(orderbook.stops.is_empty());
+        }
+    }
+
+    mod hidden_orders {
+        use crate::order::{OrderType, TimeInForce};
+
+        use super::*;
+
+        #[rstest]
+        fn hidden_not_displayed_but_matched(mut orderbook: Orderbook, ask_100_at_015: Order, bid_099_at_015: Order) {
+            let hidden_ask = ask_100_at_015.with_hidden(true);
+            assert!(hidden_ask.is_hidden());
+
+            // the hidden ask rests in the book but it's not displayed
+            assert_eq!(orderbook.handle_create(hidden_ask), NOT_MATCHED);
+            assert_eq!(orderbook.peek_top(&OrderSide::Ask), None);
+            assert!(orderbook.depth(&OrderSide::Ask, 10).is_empty());
+
+            // yet it's matched by an incoming bid
+            assert_eq!(orderbook.handle_create(bid_099_at_015), MATCHED);
+            assert_eq!(orderbook.order(hidden_ask.id()).unwrap().remaining(), 1.into());
+            assert_eq!(orderbook.peek_top(&OrderSide::Bid), None);
+        }
+
+        #[rstest]
+        fn fill_or_kill_counts_hidden(
+            mut orderbook: Orderbook,
+            ask_080_at_015: Order,
+            ask_100_at_015: Order,
+            bid_099_at_015: Order,
+        ) {
+            // only 80 are displayed but another 100 are hidden at the same price
+            assert_eq!(orderbook.handle_create(ask_080_at_015), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask_100_at_015.with_hidden(true)), NOT_MATCHED);
+            assert_eq!(orderbook.depth(&OrderSide::Ask, 10), vec![(15.into(), 80.into())]);
+
+            let limit_price = bid_099_at_015.limit_price().unwrap();
+            let mut bid_099_at_015 = bid_099_at_015;
+            bid_099_at_015.mutate_type(OrderType::Limit {
+                limit_price,
+                time_in_force: TimeInForce::ImmediateOrCancel { fill_or_kill: true },
+            });
+
+            // the FOK can be filled thanks to the hidden liquidity
+            assert_eq!(orderbook.handle_create(bid_099_at_015), MATCHED);
+            assert_eq!(orderbook.peek_top(&OrderSide::Ask), None);
+            assert_eq!(orderbook.order(ask_100_at_015.id()).unwrap().remaining(), 81.into());
+        }
+
+        #[rstest]
+        fn displayed_ahead_of_hidden(mut orderbook: Orderbook, ask_100_at_015: Order, ask_080_at_015: Order) {
+            // the hidden order arrives first but the displayed one is matched first
+            assert_eq!(orderbook.handle_create(ask_100_at_015.with_hidden(true)), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask_080_at_015), NOT_MATCHED);
+
+            let bid = Order::market_order(OrderId::new(1), OrderSide::Bid, 10.into());
+            assert_eq!(orderbook.handle_create(bid), MATCHED);
+            assert_eq!(orderbook.order(ask_080_at_015.id()).unwrap().remaining(), 70.into());
+            assert_eq!(orderbook.order(ask_100_at_015.id()).unwrap().remaining(), 100.into());
+        }
+
+        #[rstest]
+        fn displayed_first_after_cancel(mut orderbook: Orderbook, ask_100_at_015: Order, ask_080_at_015: Order) {
+            // once the only displayed order is canceled the next displayed one still goes ahead of the hidden one
+            assert_eq!(orderbook.handle_create(ask_100_at_015.with_hidden(true)), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask_080_at_015), NOT_MATCHED);
+            assert!(orderbook.handle_cancel(ask_080_at_015.id()).is_ok());
+            let ask_070_at_015 = Order::limit_order(OrderId::new(901_070_015), OrderSide::Ask, 70.into(), 15.into());
+            assert_eq!(orderbook.handle_create(ask_070_at_015), NOT_MATCHED);
+
+            let bid = Order::market_order(OrderId::new(1), OrderSide::Bid, 80.into());
+            assert_eq!(orderbook.handle_create(bid), MATCHED);
+            assert!(!orderbook.contains(ask_070_at_015.id()));
+            assert_eq!(orderbook.order(ask_100_at_015.id()).unwrap().remaining(), 90.into());
+        }
+
+        #[rstest]
+        fn hidden_with_time_priority(ask_100_at_015: Order, ask_080_at_015: Order) {
+            let mut orderbook = Orderbook::default().with_hidden_priority(HiddenPriority::TimePriority);
+
+            // the hidden order arrives first and it's matched first
+            assert_eq!(orderbook.handle_create(ask_100_at_015.with_hidden(true)), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask_080_at_015), NOT_MATCHED);
+
+            let bid = Order::market_order(OrderId::new(1), OrderSide::Bid, 10.into());
+            assert_eq!(orderbook.handle_create(bid), MATCHED);
+            assert_eq!(orderbook.order(ask_080_at_015.id()).unwrap().remaining(), 80.into());
+            assert_eq!(orderbook.order(ask_100_at_015.id()).unwrap().remaining(), 90.into());
+
+            // the top of the book only shows the displayed order
+            assert_eq!(orderbook.peek_top(&OrderSide::Ask), Some(&ask_080_at_015));
+        }
+    }
+
+    mod mass_cancel {
+        use crate::order::StopTrigger;
+
+        use super::*;
+
+        #[rstest]
+        fn mass_cancel_by_account(mut orderbook: Orderbook, ask_100_at_015: Order, ask_070_at_014: Order) {
+            let bid_025_at_013 = Order::limit_order(OrderId::new(900_025_013), OrderSide::Bid, 25.into(), 13.into());
+            let (account, other_account) = (AccountId::new(1), AccountId::new(2));
+            let stop = Order::market_order(OrderId::new(1), OrderSide::Bid, 1.into())
+                .with_account(account)
+                .with_trigger(StopTrigger::Fixed { stop_price: 20.into() });
+
+            assert_eq!(
+                orderbook.handle_create(ask_100_at_015.with_account(account)),
+                NOT_MATCHED
+            );
+            assert_eq!(
+                orderbook.handle_create(ask_070_at_014.with_account(other_account)),
+                NOT_MATCHED
+            );
+            assert_eq!(
+                orderbook.handle_create(bid_025_at_013.with_account(account)),
+                NOT_MATCHED
+            );
+            assert_eq!(orderbook.handle_create(stop), NOT_MATCHED);
+
+            // all the orders of the account are gone (including the stop) but not the ones of other accounts
+            let filter = MassCancelFilter {
+                account: Some(account),
+                ..Default::default()
+            };
+            let cancelled = orderbook.handle_mass_cancel(&filter).unwrap();
+            assert_eq!(cancelled.len(), 3);
+            assert!(cancelled.contains(&ask_100_at_015));
+            assert!(cancelled.contains(&bid_025_at_013));
+            assert!(cancelled.contains(&stop));
+            assert_eq!(orderbook.peek_top(&OrderSide::Ask), Some(&ask_070_at_014));
+            assert_eq!(orderbook.peek_top(&OrderSide::Bid), None);
+            assert!(orderbook.stops.is_empty());
+        }
+
+        #[rstest]
+        fn mass_cancel_by_side_and_price(
+            mut orderbook: Orderbook,
+            ask_100_at_015: Order,
+            ask_080_at_015: Order,
+            ask_070_at_014: Order,
+        ) {
+            let bid_025_at_013 = Order::limit_order(OrderId::new(900_025_013), OrderSide::Bid, 25.into(), 13.into());
+            assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask_080_at_015), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask_070_at_014), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(bid_025_at_013), NOT_MATCHED);
+
+            // only the asks at 15 or above are canceled, the whole level is gone
+            let filter = MassCancelFilter {
+                side: Some(OrderSide::Ask),
+                min_price: Some(15.into()),
+                ..Default::default()
+            };
+            assert_eq!(orderbook.handle_mass_cancel(&filter).unwrap().len(), 2);
+            assert_eq!(orderbook.depth(&OrderSide::Ask, 10), vec![(14.into(), 70.into())]);
+            assert_eq!(orderbook.peek_top(&OrderSide::Bid), Some(&bid_025_at_013));
+
+            // no filters cancels the whole instrument
+            let filter = MassCancelFilter::default();
+            assert_eq!(orderbook.handle_mass_cancel(&filter).unwrap().len(), 2);
+            assert_eq!(orderbook.peek_top(&OrderSide::Ask), None);
+            assert_eq!(orderbook.peek_top(&OrderSide::Bid), None);
+        }
+
+        #[rstest]
+        fn mass_cancel_in_price_time_order(
+            mut orderbook: Orderbook,
+            ask_080_at_015: Order,
+            ask_070_at_014: Order,
+            ask_100_at_015: Order,
+        ) {
+            let bid_025_at_013 = Order::limit_order(OrderId::new(900_025_013), OrderSide::Bid, 25.into(), 13.into());
+            let bid_070_at_014 = Order::limit_order(OrderId::new(900_070_014), OrderSide::Bid, 70.into(), 14.into());
+            assert_eq!(orderbook.handle_create(ask_080_at_015), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask_070_at_014), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(bid_025_at_013), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(bid_070_at_014), MATCHED);
+
+            // the asks then the bids, best price first and time priority within the level, whatever was filled before
+            let cancelled = orderbook.handle_mass_cancel(&MassCancelFilter::default()).unwrap();
+            assert_eq!(
+                cancelled.iter().map(Order::id).collect::<Vec<_>>(),
+                [ask_080_at_015.id(), ask_100_at_015.id(), bid_025_at_013.id()]
+            );
+        }
+    }
+
+    mod price_bands {
+        use super::*;
+
+        #[rstest]
+        fn stop_sweep_at_band(
+            mut orderbook: Orderbook,
+            ask_070_at_014: Order,
+            ask_100_at_015: Order,
+            bid_099_at_015: Order,
+        ) {
+            assert!(orderbook.handle_create(ask_070_at_014).is_ok());
+            assert!(orderbook.handle_create(ask_100_at_015).is_ok());
+            orderbook.set_price_band(Some(PriceBand {
+                min: 13.into(),
+                max: OrderPrice::try_from(Decimal::new(145, 1)).unwrap(),
+            }));
+
+            // the bid only takes the level within the band and the rest of it is cancelled
+            assert_eq!(orderbook.handle_create(bid_099_at_015), Ok(true));
+            assert_eq!(orderbook.trades().len(), 1);
+            assert!(orderbook.take_band_breach());
+            assert!(!orderbook.take_band_breach());
+            assert_eq!(orderbook.depth(&OrderSide::Bid, 1), vec![]);
+            assert_eq!(orderbook.depth(&OrderSide::Ask, 1), vec![(15.into(), 100.into())]);
+        }
+    }
+
+    mod sessions {
+        use super::*;
+
+        #[rstest]
+        fn session_closed_keeps_persistent(
+            mut orderbook: Orderbook,
+            ask_100_at_015: Order,
+            ask_080_at_015: Order,
+            ask_070_at_014: Order,
+        ) {
+            let (session, other_session) = (SessionId::new(1), SessionId::new(2));
+
+            assert_eq!(
+                orderbook.handle_create(ask_100_at_015.with_session(session, false)),
+                NOT_MATCHED
+            );
+            assert_eq!(
+                orderbook.handle_create(ask_080_at_015.with_session(session, true)),
+                NOT_MATCHED
+            );
+            assert_eq!(
+                orderbook.handle_create(ask_070_at_014.with_session(other_session, false)),
+                NOT_MATCHED
+            );
+
+            // only the non persistent order of the closed session is canceled
+            let cancelled = orderbook.handle_session_closed(session).unwrap();
+            assert_eq!(cancelled, vec![ask_100_at_015]);
+            assert_eq!(
+                orderbook.depth(&OrderSide::Ask, 10),
+                vec![(14.into(), 70.into()), (15.into(), 80.into())]
+            );
+
+            // closing the session again has nothing left to cancel
+            assert!(orderbook.handle_session_closed(session).unwrap().is_empty());
+        }
+    }
+
     mod features {
         use crate::order::{OrderType, TimeInForce};
 
@@ -649,11 +1592,14 @@ mod test {
             assert_eq!(orderbook.peek_top(&OrderSide::Bid), None);
 
             // now confirm that with another ask then the bid could be filled
-            assert!(bid_099_at_015.remaining() < (ask_080_at_015.remaining() + ask_100_at_015.remaining()));
+            assert!(bid_099_at_015.remaining() < ask_080_at_015.remaining().saturating_add(ask_100_at_015.remaining()));
             assert!(bid_099_at_015.matches(&ask_100_at_015));
 
             // this is the amount that should be left in the asks
-            let ask_remaining = ask_080_at_015.remaining() + ask_100_at_015.remaining() - bid_099_at_015.remaining();
+            let ask_remaining = ask_080_at_015
+                .remaining()
+                .saturating_add(ask_100_at_015.remaining())
+                .saturating_sub(bid_099_at_015.remaining());
 
             // send to the book, there should be matching
             assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
@@ -716,4 +1662,107 @@ mod test {
             assert_eq!(orderbook.peek_top(&OrderSide::Bid), None);
         }
     }
+
+    mod array_ladder {
+        use super::*;
+
+        fn range() -> PriceRange {
+            PriceRange {
+                min_price: 1.into(),
+                max_price: 200.into(),
+                tick_size: 1.into(),
+            }
+        }
+
+        fn prices<S: Side>(ladder: &ArrayLadder<S>) -> Vec<OrderPrice> {
+            ladder.levels().map(|level| level.price).collect()
+        }
+
+        #[rstest]
+        fn best_price_across_words() {
+            let mut orders = OrderArena::default();
+            let mut asks = ArrayLadder::<Asks>::new(&range());
+            let mut bids = ArrayLadder::<Bids>::new(&range());
+            let mut handles = vec![];
+            for (order_id, price) in [(1, 150), (2, 15), (3, 70), (4, 15)] {
+                let ask = Order::limit_order(OrderId::new(order_id), OrderSide::Ask, 10.into(), price.into());
+                handles.push(asks.insert(&mut orders, ask, HiddenPriority::default()).unwrap());
+                let bid = Order::limit_order(OrderId::new(order_id + 10), OrderSide::Bid, 10.into(), price.into());
+                bids.insert(&mut orders, bid, HiddenPriority::default()).unwrap();
+            }
+            assert_eq!(prices(&asks), [15, 70, 150].map(OrderPrice::from));
+            assert_eq!(prices(&bids), [150, 70, 15].map(OrderPrice::from));
+            assert_eq!(
+                asks.depth(5),
+                [(15.into(), 20.into()), (70.into(), 10.into()), (150.into(), 10.into())]
+            );
+
+            // the best level moves to the next one in use once emptied
+            assert_eq!(asks.remove(&mut orders, handles[1]).unwrap().id(), 2.into());
+            assert_eq!(asks.peek_top(&orders).unwrap().id(), 4.into());
+            asks.remove(&mut orders, handles[3]).unwrap();
+            assert_eq!(asks.best_mut().unwrap().price, 70.into());
+            bids.pop_best();
+            bids.pop_best();
+            assert_eq!(prices(&bids), [OrderPrice::from(15)]);
+            asks.pop_best();
+            asks.pop_best();
+            assert!(asks.best_mut().is_none());
+            assert_eq!(asks.levels().count(), 0);
+        }
+
+        #[rstest]
+        fn price_out_of_range() {
+            let mut orderbook = Orderbook::default().with_price_range(range());
+            let ask = Order::limit_order(OrderId::new(901_010_201), OrderSide::Ask, 10.into(), 201.into());
+            assert_eq!(
+                orderbook.handle_create(ask),
+                Err(OrderbookError::PriceOutOfRange(201.into()))
+            );
+            let off_tick = OrderPrice::try_from(Decimal::new(155, 1)).unwrap();
+            let bid = Order::limit_order(OrderId::new(900_010_155), OrderSide::Bid, 10.into(), off_tick);
+            assert_eq!(
+                orderbook.handle_create(bid),
+                Err(OrderbookError::PriceOutOfRange(off_tick))
+            );
+        }
+
+        #[rstest]
+        fn same_outcome_as_tree(
+            #[values(false, true)] array: bool,
+            ask_100_at_015: Order,
+            ask_070_at_014: Order,
+            bid_025_at_014: Order,
+            bid_099_at_015: Order,
+            bid_020_at_016: Order,
+        ) {
+            let mut orderbook = match array {
+                true => Orderbook::default().with_price_range(range()),
+                false => Orderbook::default(),
+            };
+            assert_eq!(orderbook.handle_create(bid_025_at_014), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(bid_020_at_016), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask_070_at_014), MATCHED);
+            assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(bid_099_at_015), MATCHED);
+
+            // 20 at 16 and 25 at 14 to the first ask, the last bid sweeps the rest of it and 74 at 15
+            let trades: Vec<_> = orderbook
+                .trades()
+                .iter()
+                .map(|trade| (trade.price(), trade.quantity()))
+                .collect();
+            assert_eq!(
+                trades,
+                [
+                    (16.into(), 20.into()),
+                    (14.into(), 25.into()),
+                    (14.into(), 25.into()),
+                    (15.into(), 74.into())
+                ]
+            );
+            assert_eq!(orderbook.depth(&OrderSide::Ask, 5), [(15.into(), 26.into())]);
+            assert_eq!(orderbook.depth(&OrderSide::Bid, 5), []);
+        }
+    }
 }
@@ -0,0 +1,135 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::throttle::Timestamp;
+
+// trading phases of an instrument, what each one allows:
+// - closed: no order entry at all
+// - pre-open: new orders and cancels, orders rest in the book without matching
+// - opening auction: new orders without matching but no cancels
+// - continuous: regular matching
+// - closing auction and volatility auction: new orders and cancels, orders rest in the book without matching
+// - halted and post-close: only cancels
+// the auction phases (and halts) end with the uncross when moving into continuous trading or the post-close
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TradingPhase {
+    Closed,
+    #[serde(rename = "PRE_OPEN")]
+    PreOpen,
+    #[serde(rename = "OPENING_AUCTION")]
+    OpeningAuction,
+    #[default]
+    Continuous,
+    #[serde(rename = "CLOSING_AUCTION")]
+    ClosingAuction,
+    #[serde(rename = "VOLATILITY_AUCTION")]
+    VolatilityAuction,
+    Halted,
+    #[serde(rename = "POST_CLOSE")]
+    PostClose,
+}
+
+impl TradingPhase {
+    #[inline]
+    pub fn accepts_orders(&self) -> bool {
+        matches!(
+            self,
+            TradingPhase::PreOpen
+                | TradingPhase::OpeningAuction
+                | TradingPhase::Continuous
+                | TradingPhase::ClosingAuction
+                | TradingPhase::VolatilityAuction
+        )
+    }
+
+    #[inline]
+    pub fn accepts_cancels(&self) -> bool {
+        !matches!(self, TradingPhase::Closed | TradingPhase::OpeningAuction)
+    }
+
+    #[inline]
+    pub fn is_matching(&self) -> bool {
+        matches!(self, TradingPhase::Continuous)
+    }
+
+    // orders accumulate in the book during the call period of an auction
+    #[inline]
+    pub fn is_auction(&self) -> bool {
+        matches!(
+            self,
+            TradingPhase::PreOpen
+                | TradingPhase::OpeningAuction
+                | TradingPhase::ClosingAuction
+                | TradingPhase::VolatilityAuction
+        )
+    }
+
+    // a halt may interrupt an auction and leave the book crossed
+    #[inline]
+    pub fn is_uncrossing(&self, next: TradingPhase) -> bool {
+        (self.is_auction() || *self == TradingPhase::Halted)
+            && matches!(next, TradingPhase::Continuous | TradingPhase::PostClose)
+    }
+}
+
+impl Display for TradingPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TradingPhase::Closed => write!(f, "CLOSED"),
+            TradingPhase::PreOpen => write!(f, "PRE_OPEN"),
+            TradingPhase::OpeningAuction => write!(f, "OPENING_AUCTION"),
+            TradingPhase::Continuous => write!(f, "CONTINUOUS"),
+            TradingPhase::ClosingAuction => write!(f, "CLOSING_AUCTION"),
+            TradingPhase::VolatilityAuction => write!(f, "VOLATILITY_AUCTION"),
+            TradingPhase::Halted => write!(f, "HALTED"),
+            TradingPhase::PostClose => write!(f, "POST_CLOSE"),
+        }
+    }
+}
+
+// phase transitions driven by the engine clock
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PhaseSchedule {
+    transitions: Vec<(Timestamp, TradingPhase)>,
+}
+
+impl PhaseSchedule {
+    #[inline]
+    pub fn with_transition(mut self, timestamp: Timestamp, phase: TradingPhase) -> Self {
+        let idx = self.transitions.partition_point(|(at, _)| *at <= timestamp);
+        self.transitions.insert(idx, (timestamp, phase));
+        self
+    }
+
+    // the transitions due by `now` (in order), they're removed from the schedule
+    #[inline]
+    pub fn due(&mut self, now: Timestamp) -> impl Iterator<Item = TradingPhase> + '_ {
+        let due = self.transitions.partition_point(|(at, _)| *at <= now);
+        self.transitions.drain(..due).map(|(_, phase)| phase)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    fn scheduled_transitions() {
+        let mut schedule = PhaseSchedule::default()
+            .with_transition(20, TradingPhase::Continuous)
+            .with_transition(10, TradingPhase::PreOpen)
+            .with_transition(30, TradingPhase::PostClose);
+
+        assert_eq!(schedule.due(5).count(), 0);
+        assert_eq!(
+            schedule.due(20).collect::<Vec<_>>(),
+            vec![TradingPhase::PreOpen, TradingPhase::Continuous]
+        );
+        assert_eq!(schedule.due(20).count(), 0);
+        assert_eq!(schedule.due(40).collect::<Vec<_>>(), vec![TradingPhase::PostClose]);
+    }
+}
@@ -0,0 +1,525 @@
+use std::{
+    fmt::Display,
+    io::{BufRead, BufWriter, Write},
+    sync::Arc,
+    thread::{self, Scope, ScopedJoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use tracing::{error, info, warn};
+
+use crate::{
+    engine::{Engine, Validator},
+    event::Event,
+    latency::{Histograms, Measure, Recorder, Reporter},
+    order::OrderRequest,
+    ring::{Consumer, Producer, ring},
+};
+
+pub const DEFAULT_RING_CAPACITY: usize = 1024;
+
+// stages of the pipeline, in order, each one on its own thread
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Decode,   // json lines into requests
+    Validate, // the checks that don't need the state of the engine, and the journal
+    Match,    // the engine itself
+    Publish,  // events out as json lines
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [Stage::Decode, Stage::Validate, Stage::Match, Stage::Publish];
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Stage::Decode => "decode",
+            Stage::Validate => "validate",
+            Stage::Match => "match",
+            Stage::Publish => "publish",
+        };
+        write!(f, "{name}")
+    }
+}
+
+// the stages are connected by bounded rings, a slow stage makes the ones before it wait instead of piling up
+// requests in memory, and each stage can be pinned to a core
+#[derive(Clone, Debug)]
+pub struct Pipeline {
+    capacity: usize,
+    cores: [Option<usize>; 4],
+    latency: Option<Option<Duration>>, // whether latencies are measured, and how often they are reported
+    admin: bool,                       // whether the input comes from the operator
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_RING_CAPACITY,
+            cores: [None; 4],
+            latency: None,
+            admin: false,
+        }
+    }
+}
+
+// a request on its way to the engine, with the time it was decoded at if latencies are measured
+type Timed = (OrderRequest, Option<Instant>);
+
+// the events of a batch on their way out, with the number of requests they are for (those of the batches without
+// events before it included) so the publish latency is counted once per request
+type Published = (Vec<Event>, u64);
+
+// what went through the pipeline
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PipelineStats {
+    pub decoded: u64,
+    pub malformed: u64, // lines that are not requests
+    pub rejected: u64,  // by the validation
+    pub failed: u64,    // by the engine
+    pub events: u64,
+    pub latency: Option<Histograms>, // if measured
+}
+
+impl Pipeline {
+    // slots of each ring between two stages
+    #[inline]
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    #[inline]
+    pub fn with_core(mut self, stage: Stage, core: usize) -> Self {
+        self.cores[stage as usize] = Some(core);
+        self
+    }
+
+    // measures the latency of every request at each stage (the clock isn't read at all otherwise), reporting the
+    // percentiles of all the stages together every so often if asked to, and at the end in the stats
+    #[inline]
+    pub fn with_latency(mut self, report_every: Option<Duration>) -> Self {
+        self.latency = Some(report_every);
+        self
+    }
+
+    // the input comes from the operator (e.g. a journal replayed), admin requests go through the engine too instead
+    // of being rejected as if sent by a client
+    #[inline]
+    pub fn with_admin(mut self, admin: bool) -> Self {
+        self.admin = admin;
+        self
+    }
+
+    // process every request of the input through the engine and write the events to the output, returns once
+    // the input is exhausted and everything has been published
+    #[inline]
+    pub fn run(
+        &self,
+        engine: &mut Engine,
+        input: impl BufRead + Send,
+        output: impl Write + Send,
+    ) -> Result<PipelineStats> {
+        self.run_stages(engine, input, output, None::<std::io::Sink>)
+    }
+
+    // the same, also writing the requests that pass the validation to the journal (as json lines, in the order
+    // the engine gets them), so replaying the journal through an engine gives back the same events
+    #[inline]
+    pub fn run_journaled(
+        &self,
+        engine: &mut Engine,
+        input: impl BufRead + Send,
+        output: impl Write + Send,
+        journal: impl Write + Send,
+    ) -> Result<PipelineStats> {
+        self.run_stages(engine, input, output, Some(journal))
+    }
+
+    fn run_stages(
+        &self,
+        engine: &mut Engine,
+        input: impl BufRead + Send,
+        output: impl Write + Send,
+        journal: Option<impl Write + Send>,
+    ) -> Result<PipelineStats> {
+        let validator = engine.validator();
+        let (decoded_tx, decoded_rx) = ring(self.capacity);
+        let (validated_tx, validated_rx) = ring(self.capacity);
+        let (events_tx, events_rx) = ring(self.capacity);
+        // the buffers of the events go back to the match stage once published
+        let (recycled_tx, recycled_rx) = ring(self.capacity);
+        // the stages add their latencies to it for the periodic reports (of all of them together)
+        let reporter = self.latency.flatten().map(|every| Arc::new(Reporter::new(every)));
+
+        thread::scope(|scope| {
+            let handles = [
+                spawn(scope, Stage::Decode, self, reporter.clone(), |stats, recorder| {
+                    decode(input, decoded_tx, stats, recorder)
+                })?,
+                spawn(scope, Stage::Validate, self, reporter.clone(), |stats, _| {
+                    validate(&validator, decoded_rx, validated_tx, journal, stats)
+                })?,
+                spawn(scope, Stage::Match, self, reporter.clone(), |stats, recorder| {
+                    process(engine, validated_rx, events_tx, recycled_rx, self, stats, recorder)
+                })?,
+                spawn(scope, Stage::Publish, self, reporter.clone(), |stats, recorder| {
+                    publish(events_rx, recycled_tx, output, stats, recorder)
+                })?,
+            ];
+
+            let mut stats = PipelineStats::default();
+            for (stage, handle) in Stage::ALL.into_iter().zip(handles) {
+                let stage_stats = handle.join().map_err(|_| anyhow!("{stage} stage panicked"))??;
+                stats.decoded += stage_stats.decoded;
+                stats.malformed += stage_stats.malformed;
+                stats.rejected += stage_stats.rejected;
+                stats.failed += stage_stats.failed;
+                stats.events += stage_stats.events;
+                if let Some(histograms) = stage_stats.latency {
+                    stats.latency.get_or_insert_with(Histograms::default).merge(&histograms);
+                }
+            }
+            Ok(stats)
+        })
+    }
+}
+
+// runs the stage on its own thread (named after it), pinned to its core if there's one and with its own
+// latency recorder if latencies are measured
+fn spawn<'scope>(
+    scope: &'scope Scope<'scope, '_>,
+    stage: Stage,
+    pipeline: &Pipeline,
+    reporter: Option<Arc<Reporter>>,
+    run: impl FnOnce(&mut PipelineStats, &mut Option<Recorder>) -> Result<()> + Send + 'scope,
+) -> Result<ScopedJoinHandle<'scope, Result<PipelineStats>>> {
+    let core = pipeline.cores[stage as usize];
+    let latency = pipeline.latency;
+    let handle = thread::Builder::new()
+        .name(stage.to_string())
+        .spawn_scoped(scope, move || {
+            if let Some(core) = core {
+                pin(stage, core);
+            }
+            let mut stats = PipelineStats::default();
+            let mut recorder = latency.map(|_| Recorder::new(reporter));
+            run(&mut stats, &mut recorder)?;
+            stats.latency = recorder.map(Recorder::into_histograms);
+            Ok(stats)
+        })?;
+    Ok(handle)
+}
+
+fn pin(stage: Stage, core: usize) {
+    let core_id =
+        core_affinity::get_core_ids().and_then(|core_ids| core_ids.into_iter().find(|core_id| core_id.id == core));
+    match core_id {
+        Some(core_id) if core_affinity::set_for_current(core_id) => info!("{stage} stage pinned to core {core}"),
+        _ => warn!("Unable to pin the {stage} stage to core {core}"),
+    }
+}
+
+fn decode(
+    mut input: impl BufRead,
+    mut tx: Producer<Timed>,
+    stats: &mut PipelineStats,
+    recorder: &mut Option<Recorder>,
+) -> Result<()> {
+    let mut buf = String::with_capacity(4096);
+    loop {
+        buf.clear();
+        if input.read_line(&mut buf)? == 0 {
+            break;
+        }
+        if buf.trim().is_empty() {
+            continue;
+        }
+        let start = recorder.as_ref().map(|_| Instant::now());
+        match serde_json::from_str(&buf) {
+            Ok(order_request) => {
+                stats.decoded += 1;
+                let decoded_at = recorder.as_mut().zip(start).map(|(recorder, start)| {
+                    let now = Instant::now();
+                    recorder.record(Measure::Decode, start, now);
+                    now
+                });
+                if tx.push((order_request, decoded_at)).is_err() {
+                    break; // the rest of the pipeline is gone
+                }
+            }
+            Err(error) => {
+                stats.malformed += 1;
+                error!("Error processing source of orders: {}", error);
+            }
+        }
+    }
+    Ok(())
+}
+
+// the requests are journaled on their way to the engine (buffered, and flushed once the input is exhausted)
+fn validate(
+    validator: &Validator,
+    rx: Consumer<Timed>,
+    mut tx: Producer<Timed>,
+    journal: Option<impl Write>,
+    stats: &mut PipelineStats,
+) -> Result<()> {
+    let mut journal = journal.map(BufWriter::new);
+    for (order_request, decoded_at) in rx {
+        if let Err(error) = validator.validate(&order_request) {
+            stats.rejected += 1;
+            error!("Error validating order request: {}", error);
+            continue;
+        }
+        if let Some(journal) = journal.as_mut() {
+            serde_json::to_writer(&mut *journal, &order_request)?;
+            journal.write_all(b"\n")?;
+        }
+        if tx.push((order_request, decoded_at)).is_err() {
+            break;
+        }
+    }
+    if let Some(journal) = journal.as_mut() {
+        journal.flush()?;
+    }
+    Ok(())
+}
+
+fn process(
+    engine: &mut Engine,
+    mut rx: Consumer<Timed>,
+    mut tx: Producer<Published>,
+    mut recycled: Consumer<Vec<Event>>,
+    pipeline: &Pipeline,
+    stats: &mut PipelineStats,
+    recorder: &mut Option<Recorder>,
+) -> Result<()> {
+    let batch_size = pipeline.capacity;
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut decoded_at = Vec::with_capacity(batch_size);
+    let mut errors = vec![];
+    let mut spare = None; // a buffer left empty by the last batch
+    let mut requests = 0; // since the last buffer handed over
+    // waits for a request and takes whatever else is already in the ring, so the engine gets bigger batches
+    // the more it falls behind
+    while let Some((order_request, at)) = rx.pop() {
+        batch.push(order_request);
+        decoded_at.extend(at);
+        while batch.len() < batch_size {
+            match rx.try_pop() {
+                Some((order_request, at)) => {
+                    batch.push(order_request);
+                    decoded_at.extend(at);
+                }
+                None => break,
+            }
+        }
+
+        requests += batch.len() as u64;
+        // a buffer already published if there's one, a new one otherwise
+        let mut events = spare.take().or_else(|| recycled.try_pop()).unwrap_or_default();
+        match recorder.as_mut() {
+            // the engine takes the requests of the batch one at a time, so each one is done when the next one
+            // is taken (or when the batch is)
+            Some(recorder) => {
+                let mut taken_at = None;
+                let timed = batch.drain(..).zip(decoded_at.drain(..)).map(|(order_request, at)| {
+                    let now = Instant::now();
+                    recorder.record(Measure::Queue, at, now);
+                    if let Some(taken_at) = taken_at.replace(now) {
+                        recorder.record(Measure::Match, taken_at, now);
+                    }
+                    order_request
+                });
+                match pipeline.admin {
+                    true => engine.process_admin_batch(timed, &mut events, &mut errors),
+                    false => engine.process_batch(timed, &mut events, &mut errors),
+                }
+                if let Some(taken_at) = taken_at {
+                    recorder.record(Measure::Match, taken_at, Instant::now());
+                }
+            }
+            None if pipeline.admin => engine.process_admin_batch(batch.drain(..), &mut events, &mut errors),
+            None => engine.process_batch(batch.drain(..), &mut events, &mut errors),
+        }
+        for (_, error) in errors.drain(..) {
+            stats.failed += 1;
+            error!("Error processing order request: {}", error);
+        }
+
+        if events.is_empty() {
+            spare = Some(events);
+        } else if tx.push((events, std::mem::take(&mut requests))).is_err() {
+            return Ok(());
+        }
+    }
+
+    // the last requests without events are published too (as nothing), every request gets its sample
+    if requests > 0 {
+        let _ = tx.push((spare.unwrap_or_default(), requests));
+    }
+    Ok(())
+}
+
+fn publish(
+    rx: Consumer<Published>,
+    mut recycled: Producer<Vec<Event>>,
+    output: impl Write,
+    stats: &mut PipelineStats,
+    recorder: &mut Option<Recorder>,
+) -> Result<()> {
+    let mut output = BufWriter::new(output);
+    for (mut events, requests) in rx {
+        let start = recorder.as_ref().map(|_| Instant::now());
+        for event in events.drain(..) {
+            serde_json::to_writer(&mut output, &event)?;
+            output.write_all(b"\n")?;
+            stats.events += 1;
+        }
+        if let Some((recorder, start)) = recorder.as_mut().zip(start) {
+            recorder.record_n(Measure::Publish, start, Instant::now(), requests);
+        }
+        // never waits for the match stage, the buffer is dropped if there are enough of them already
+        let _ = recycled.try_push(events);
+    }
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use compact_str::CompactString;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::order::util::{DEFAULT_PAIR, generate};
+
+    #[rstest]
+    fn same_events_as_sequential() {
+        let mut order_requests: Vec<OrderRequest> = generate(1..=2_000).collect();
+        // not for this engine, the validation rejects it before it reaches the engine (a create, the generated
+        // requests have a few random cancels that would go through)
+        let mut wrong_pair = order_requests[100..]
+            .iter()
+            .find(|order_request| matches!(order_request, OrderRequest::Create { .. }))
+            .unwrap()
+            .clone();
+        if let OrderRequest::Create { pair, .. } = &mut wrong_pair {
+            *pair = CompactString::new_inline("BTC/USDT");
+        }
+        order_requests.insert(50, wrong_pair);
+
+        let mut input: String = order_requests
+            .iter()
+            .map(|order_request| serde_json::to_string(order_request).unwrap() + "\n")
+            .collect();
+        input.push_str("not a request\n");
+
+        let mut expected = vec![];
+        let mut engine = Engine::new(DEFAULT_PAIR);
+        for order_request in order_requests {
+            for event in engine.process_admin(order_request).unwrap_or_default() {
+                serde_json::to_writer(&mut expected, &event).unwrap();
+                expected.push(b'\n');
+            }
+        }
+
+        // tiny rings so the stages wait for each other all the time
+        let mut output = vec![];
+        let mut engine = Engine::new(DEFAULT_PAIR);
+        let stats = Pipeline::default()
+            .with_capacity(4)
+            .with_admin(true)
+            .run(&mut engine, input.as_bytes(), &mut output)
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), String::from_utf8(expected).unwrap());
+        assert_eq!((stats.decoded, stats.malformed, stats.rejected), (2_019, 1, 1));
+        assert!(stats.events > 0);
+        assert!(stats.latency.is_none());
+    }
+
+    #[rstest]
+    fn replay_the_journal() {
+        let mut input: String = generate(1..=500)
+            .map(|order_request| serde_json::to_string(&order_request).unwrap() + "\n")
+            .collect();
+        input.push_str("not a request\n");
+
+        let (mut output, mut journal) = (vec![], vec![]);
+        let mut engine = Engine::new(DEFAULT_PAIR);
+        let stats = Pipeline::default()
+            .with_capacity(4)
+            .with_admin(true)
+            .run_journaled(&mut engine, input.as_bytes(), &mut output, &mut journal)
+            .unwrap();
+
+        // the journal has every request that reached the engine and gives back the same events
+        let mut replayed = vec![];
+        let mut replay = Engine::new(DEFAULT_PAIR);
+        let journal = String::from_utf8(journal).unwrap();
+        assert_eq!(journal.lines().count() as u64, stats.decoded - stats.rejected);
+        for line in journal.lines() {
+            for event in replay
+                .process_admin(serde_json::from_str(line).unwrap())
+                .unwrap_or_default()
+            {
+                serde_json::to_writer(&mut replayed, &event).unwrap();
+                replayed.push(b'\n');
+            }
+        }
+        assert_eq!(String::from_utf8(replayed).unwrap(), String::from_utf8(output).unwrap());
+        assert_eq!(replay.snapshot(), engine.snapshot());
+    }
+
+    #[rstest]
+    fn latency_per_stage() {
+        let order_requests: Vec<OrderRequest> = generate(1..=200).collect();
+        let input: String = order_requests
+            .iter()
+            .map(|order_request| serde_json::to_string(order_request).unwrap() + "\n")
+            .collect();
+
+        let mut engine = Engine::new(DEFAULT_PAIR);
+        let stats = Pipeline::default()
+            .with_capacity(4)
+            .with_admin(true)
+            .with_latency(None)
+            .run(&mut engine, input.as_bytes(), std::io::sink())
+            .unwrap();
+        let latency = stats.latency.unwrap();
+
+        // every request is measured at each stage it goes through, publish with the time of its batch
+        for (measure, count) in [
+            (Measure::Decode, stats.decoded),
+            (Measure::Queue, stats.decoded),
+            (Measure::Match, stats.decoded),
+            (Measure::Publish, stats.decoded),
+        ] {
+            let percentiles = latency.percentiles(measure);
+            assert_eq!(percentiles.count, count, "{measure}");
+            assert!(percentiles.p50 <= percentiles.p99 && percentiles.p99 <= percentiles.max);
+        }
+    }
+
+    #[rstest]
+    fn admin_requests_from_the_operator_only() {
+        // just the deposits that fund the accounts
+        let input: String = generate(std::iter::empty())
+            .map(|order_request| serde_json::to_string(&order_request).unwrap() + "\n")
+            .collect();
+
+        // the same deposits fail coming from clients
+        for (admin, failed, funds) in [(false, 18, 0), (true, 0, 1_000_000_000_000_000_i64)] {
+            let mut engine = Engine::new(DEFAULT_PAIR);
+            let stats = Pipeline::default()
+                .with_admin(admin)
+                .run(&mut engine, input.as_bytes(), std::io::sink())
+                .unwrap();
+            assert_eq!((stats.decoded, stats.failed), (18, failed));
+            assert_eq!(engine.balance("1", "ETH").available, funds.into());
+        }
+    }
+}
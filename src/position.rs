@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::order::{AccountId, OrderPrice, OrderQuantity, OrderSide};
+
+// how the realized pnl is computed when a position is reduced
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CostMethod {
+    #[default]
+    AverageCost,
+    Fifo,
+}
+
+// quantity (signed, long if positive) and price of the trades still open
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+struct Lot {
+    quantity: Decimal,
+    price: Decimal,
+}
+
+// net position of an account in the base asset, pnl is in the quote asset (before fees)
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Position {
+    quantity: Decimal,
+    realized_pnl: Decimal,
+    lots: VecDeque<Lot>,
+}
+
+impl Position {
+    #[inline]
+    pub fn quantity(&self) -> Decimal {
+        self.quantity
+    }
+
+    #[inline]
+    pub fn realized_pnl(&self) -> Decimal {
+        self.realized_pnl
+    }
+
+    // average entry price of the open lots (none if flat)
+    #[inline]
+    pub fn average_price(&self) -> Option<Decimal> {
+        if self.quantity.is_zero() {
+            return None;
+        }
+        let cost: Decimal = self.lots.iter().map(|lot| lot.quantity * lot.price).sum();
+        Some(cost / self.quantity)
+    }
+
+    #[inline]
+    pub fn unrealized_pnl(&self, mark_price: Option<OrderPrice>) -> Decimal {
+        match (self.average_price(), mark_price) {
+            (Some(average_price), Some(mark_price)) => (mark_price.to_decimal() - average_price) * self.quantity,
+            _ => Decimal::ZERO,
+        }
+    }
+
+    #[inline]
+    pub fn report(&self, mark_price: Option<OrderPrice>) -> PositionReport {
+        PositionReport {
+            quantity: self.quantity,
+            average_price: self.average_price(),
+            realized_pnl: self.realized_pnl,
+            unrealized_pnl: self.unrealized_pnl(mark_price),
+        }
+    }
+
+    // close the oldest lots first with the opposite quantity, what's left opens a new lot
+    fn apply(&mut self, side: OrderSide, price: OrderPrice, quantity: OrderQuantity, method: CostMethod) {
+        let price = price.to_decimal();
+        let mut remaining = match side {
+            OrderSide::Bid => quantity.to_decimal(),
+            OrderSide::Ask => -quantity.to_decimal(),
+        };
+        self.quantity += remaining;
+
+        while let Some(lot) = self.lots.front_mut()
+            && !remaining.is_zero()
+            && lot.quantity.is_sign_positive() != remaining.is_sign_positive()
+        {
+            let closed = if lot.quantity.abs() <= remaining.abs() {
+                lot.quantity
+            } else {
+                -remaining
+            };
+            self.realized_pnl += (price - lot.price) * closed;
+            lot.quantity -= closed;
+            remaining += closed;
+            if lot.quantity.is_zero() {
+                self.lots.pop_front();
+            }
+        }
+
+        if remaining.is_zero() {
+            return;
+        }
+        match (method, self.lots.front_mut()) {
+            // average cost keeps a single lot at the average entry price
+            (CostMethod::AverageCost, Some(lot)) => {
+                let quantity = lot.quantity + remaining;
+                lot.price = (lot.quantity * lot.price + remaining * price) / quantity;
+                lot.quantity = quantity;
+            }
+            _ => self.lots.push_back(Lot {
+                quantity: remaining,
+                price,
+            }),
+        }
+    }
+}
+
+// position marked to a price, as queried or included in the snapshots
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PositionReport {
+    pub quantity: Decimal,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub average_price: Option<Decimal>,
+    pub realized_pnl: Decimal,
+    pub unrealized_pnl: Decimal,
+}
+
+// positions of all the accounts trading an instrument
+#[derive(Default)]
+pub struct Positions {
+    method: CostMethod,
+    positions: IndexMap<AccountId, Position>,
+}
+
+impl Positions {
+    #[inline]
+    pub fn new(method: CostMethod) -> Self {
+        Self {
+            method,
+            positions: IndexMap::new(),
+        }
+    }
+
+    #[inline]
+    pub fn position(&self, account: AccountId) -> Option<&Position> {
+        self.positions.get(&account)
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&AccountId, &Position)> {
+        self.positions.iter()
+    }
+
+    #[inline]
+    pub fn apply(&mut self, account: AccountId, side: OrderSide, price: OrderPrice, quantity: OrderQuantity) {
+        let method = self.method;
+        self.positions
+            .entry(account)
+            .or_default()
+            .apply(side, price, quantity, method);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::average_cost(CostMethod::AverageCost, Decimal::new(-5, 0), Decimal::new(125, 1))]
+    #[case::fifo(CostMethod::Fifo, Decimal::new(20, 0), Decimal::new(15, 0))]
+    fn realized_and_unrealized_pnl(
+        #[case] method: CostMethod,
+        #[case] realized_pnl: Decimal,
+        #[case] average_price: Decimal,
+    ) {
+        let account = AccountId::new(1);
+        let mut positions = Positions::new(method);
+
+        // buy 10@10 and 10@15, then sell 10@12
+        positions.apply(account, OrderSide::Bid, 10.into(), 10.into());
+        positions.apply(account, OrderSide::Bid, 15.into(), 10.into());
+        positions.apply(account, OrderSide::Ask, 12.into(), 10.into());
+
+        let position = positions.position(account).unwrap();
+        assert_eq!(position.quantity(), 10.into());
+        assert_eq!(position.realized_pnl(), realized_pnl);
+        assert_eq!(position.average_price(), Some(average_price));
+        assert_eq!(
+            position.unrealized_pnl(Some(20.into())),
+            (Decimal::from(20) - average_price) * Decimal::from(10)
+        );
+
+        // selling 15@20 closes the long position and opens a short one
+        positions.apply(account, OrderSide::Ask, 20.into(), 15.into());
+        let position = positions.position(account).unwrap();
+        assert_eq!(position.quantity(), (-5).into());
+        assert_eq!(position.realized_pnl(), 70.into());
+        assert_eq!(position.average_price(), Some(20.into()));
+        assert_eq!(position.unrealized_pnl(Some(18.into())), 10.into());
+        assert_eq!(position.unrealized_pnl(None), Decimal::ZERO);
+    }
+}
@@ -0,0 +1,219 @@
+use std::ops::{Index, IndexMut};
+
+use slab::Slab;
+
+use crate::order::Order;
+
+// stable index of a resting order in the arena
+pub type Handle = usize;
+
+#[derive(Debug)]
+struct Node {
+    order: Order,
+    prev: Option<Handle>,
+    next: Option<Handle>,
+}
+
+// the resting orders of a book live in one arena, linked into the queues of their price levels,
+// so the matching loop walks them without hashing and any of them can be unlinked in O(1) by its handle
+#[derive(Debug, Default)]
+pub struct OrderArena {
+    nodes: Slab<Node>,
+}
+
+impl OrderArena {
+    // preallocate room for `capacity` resting orders
+    #[inline]
+    pub fn reserve(&mut self, capacity: usize) {
+        self.nodes.reserve(capacity);
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.nodes.capacity()
+    }
+
+    #[inline]
+    pub fn next(&self, handle: Handle) -> Option<Handle> {
+        self.nodes[handle].next
+    }
+
+    #[inline]
+    pub fn prev(&self, handle: Handle) -> Option<Handle> {
+        self.nodes[handle].prev
+    }
+
+    // both orders at once (e.g. the two sides of a trade), the handles must be different
+    #[inline]
+    pub fn pair_mut(&mut self, first: Handle, second: Handle) -> Option<(&mut Order, &mut Order)> {
+        self.nodes
+            .get2_mut(first, second)
+            .map(|(first, second)| (&mut first.order, &mut second.order))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+impl Index<Handle> for OrderArena {
+    type Output = Order;
+
+    #[inline]
+    fn index(&self, handle: Handle) -> &Order {
+        &self.nodes[handle].order
+    }
+}
+
+impl IndexMut<Handle> for OrderArena {
+    #[inline]
+    fn index_mut(&mut self, handle: Handle) -> &mut Order {
+        &mut self.nodes[handle].order
+    }
+}
+
+// intrusive FIFO queue of orders (a doubly-linked list through the arena)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OrderQueue {
+    head: Option<Handle>,
+    tail: Option<Handle>,
+    len: usize,
+}
+
+impl OrderQueue {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn front(&self) -> Option<Handle> {
+        self.head
+    }
+
+    #[inline]
+    pub fn push_back(&mut self, arena: &mut OrderArena, order: Order) -> Handle {
+        self.insert_after(arena, self.tail, order)
+    }
+
+    // queue the order right behind `after` (at the front if none)
+    pub fn insert_after(&mut self, arena: &mut OrderArena, after: Option<Handle>, order: Order) -> Handle {
+        let next = match after {
+            Some(after) => arena.nodes[after].next,
+            None => self.head,
+        };
+        let handle = arena.nodes.insert(Node {
+            order,
+            prev: after,
+            next,
+        });
+        match after {
+            Some(after) => arena.nodes[after].next = Some(handle),
+            None => self.head = Some(handle),
+        }
+        match next {
+            Some(next) => arena.nodes[next].prev = Some(handle),
+            None => self.tail = Some(handle),
+        }
+        self.len += 1;
+        handle
+    }
+
+    // the handle must belong to this queue, the order leaves the arena
+    pub fn remove(&mut self, arena: &mut OrderArena, handle: Handle) -> Order {
+        let node = arena.nodes.remove(handle);
+        match node.prev {
+            Some(prev) => arena.nodes[prev].next = node.next,
+            None => self.head = node.next,
+        }
+        match node.next {
+            Some(next) => arena.nodes[next].prev = node.prev,
+            None => self.tail = node.prev,
+        }
+        self.len -= 1;
+        node.order
+    }
+
+    #[inline]
+    pub fn iter<'a>(&self, arena: &'a OrderArena) -> impl Iterator<Item = (Handle, &'a Order)> + 'a {
+        let mut cursor = self.head;
+        std::iter::from_fn(move || {
+            let handle = cursor?;
+            cursor = arena.next(handle);
+            Some((handle, &arena[handle]))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::order::{OrderId, OrderSide};
+
+    fn order(order_id: u64) -> Order {
+        Order::limit_order(order_id.into(), OrderSide::Ask, 10.into(), 15.into())
+    }
+
+    fn order_ids(queue: &OrderQueue, arena: &OrderArena) -> Vec<OrderId> {
+        let ids: Vec<OrderId> = queue.iter(arena).map(|(_, order)| order.id()).collect();
+        let reversed: Vec<OrderId> = std::iter::successors(queue.tail, |handle| arena.prev(*handle))
+            .map(|handle| arena[handle].id())
+            .collect();
+        // both directions must agree
+        assert!(ids.iter().eq(reversed.iter().rev()));
+        ids
+    }
+
+    #[rstest]
+    fn fifo_with_removals_anywhere() {
+        let mut arena = OrderArena::default();
+        let mut queue = OrderQueue::default();
+        let handles: Vec<Handle> = (1..=4).map(|id| queue.push_back(&mut arena, order(id))).collect();
+        assert_eq!(order_ids(&queue, &arena), [1, 2, 3, 4].map(OrderId::new));
+
+        // removing from the middle, the back and the front keeps the order of the rest
+        assert_eq!(queue.remove(&mut arena, handles[1]).id(), 2.into());
+        assert_eq!(queue.remove(&mut arena, handles[3]).id(), 4.into());
+        assert_eq!(order_ids(&queue, &arena), [1, 3].map(OrderId::new));
+        queue.insert_after(&mut arena, None, order(5));
+        queue.insert_after(&mut arena, Some(handles[0]), order(6));
+        assert_eq!(order_ids(&queue, &arena), [5, 1, 6, 3].map(OrderId::new));
+        assert_eq!(queue.remove(&mut arena, queue.front().unwrap()).id(), 5.into());
+        assert_eq!(queue.len(), 3);
+
+        // the slots of the removed orders are reused
+        queue.push_back(&mut arena, order(7));
+        assert_eq!(order_ids(&queue, &arena), [1, 6, 3, 7].map(OrderId::new));
+        assert_eq!(arena.len(), 4);
+    }
+
+    #[rstest]
+    fn preallocated_arena() {
+        let mut arena = OrderArena::default();
+        arena.reserve(16);
+        let capacity = arena.capacity();
+        assert!(capacity >= 16);
+
+        // the arena doesn't grow while it's within its capacity
+        let mut queue = OrderQueue::default();
+        let handles: Vec<Handle> = (1..=16).map(|id| queue.push_back(&mut arena, order(id))).collect();
+        assert_eq!(arena.capacity(), capacity);
+
+        let (first, last) = arena.pair_mut(handles[0], handles[15]).unwrap();
+        assert!(first.fill(5.into()).is_ok());
+        assert_eq!((first.remaining(), last.remaining()), (5.into(), 10.into()));
+    }
+}
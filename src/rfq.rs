@@ -0,0 +1,265 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use thiserror::Error;
+
+use crate::order::{AccountId, Order, OrderPrice, OrderQuantity, OrderSide};
+
+// a request for quote open until the requester accepts one of the quotes (or cancels the request),
+// each quote is kept as the maker order it would trade as (so its funds stay reserved until it's withdrawn)
+#[derive(Clone, Debug)]
+pub struct QuoteRequest {
+    requester: AccountId,
+    side: OrderSide,
+    quantity: OrderQuantity,
+    quotes: IndexMap<CompactString, Order>,
+}
+
+impl QuoteRequest {
+    #[inline]
+    pub fn requester(&self) -> AccountId {
+        self.requester
+    }
+
+    #[inline]
+    pub fn side(&self) -> OrderSide {
+        self.side
+    }
+
+    #[inline]
+    pub fn quantity(&self) -> OrderQuantity {
+        self.quantity
+    }
+
+    #[inline]
+    pub fn quote(&self, quote_id: &str) -> Option<&Order> {
+        self.quotes.get(quote_id)
+    }
+
+    #[inline]
+    pub fn quotes(&self) -> impl Iterator<Item = &Order> {
+        self.quotes.values()
+    }
+}
+
+#[derive(Default)]
+pub struct Rfqs {
+    requests: IndexMap<CompactString, QuoteRequest>,
+}
+
+impl Rfqs {
+    #[inline]
+    pub fn request(
+        &mut self,
+        rfq_id: CompactString,
+        requester: AccountId,
+        side: OrderSide,
+        quantity: OrderQuantity,
+    ) -> Result<(), RfqError> {
+        if self.requests.contains_key(&rfq_id) {
+            return Err(RfqError::RfqDuplicated(rfq_id));
+        }
+        let request = QuoteRequest {
+            requester,
+            side,
+            quantity,
+            quotes: IndexMap::new(),
+        };
+        self.requests.insert(rfq_id, request);
+        Ok(())
+    }
+
+    // the maker order answering the request (not added until its funds are reserved)
+    #[inline]
+    pub fn validate_quote(&self, rfq_id: &str, quote_id: &str, maker: &Order) -> Result<(), RfqError> {
+        let request = self
+            .requests
+            .get(rfq_id)
+            .ok_or_else(|| RfqError::RfqNotFound(rfq_id.into()))?;
+        if maker.account() == request.requester {
+            return Err(RfqError::OwnRfq(rfq_id.into()));
+        }
+        if request.quotes.contains_key(quote_id) {
+            return Err(RfqError::QuoteDuplicated(quote_id.into()));
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn quote(&mut self, rfq_id: &str, quote_id: CompactString, maker: Order) {
+        if let Some(request) = self.requests.get_mut(rfq_id) {
+            request.quotes.insert(quote_id, maker);
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, rfq_id: &str) -> Option<&QuoteRequest> {
+        self.requests.get(rfq_id)
+    }
+
+    // the request is closed once accepted, all its quotes go away
+    #[inline]
+    pub fn accept(&mut self, rfq_id: &str, quote_id: &str) -> Result<(QuoteRequest, Order), RfqError> {
+        let request = self
+            .requests
+            .get(rfq_id)
+            .ok_or_else(|| RfqError::RfqNotFound(rfq_id.into()))?;
+        let maker = *request.quotes.get(quote_id).ok_or_else(|| RfqError::QuoteNotFound {
+            rfq_id: rfq_id.into(),
+            quote_id: quote_id.into(),
+        })?;
+        let request = self.requests.swap_remove(rfq_id).expect("request checked above");
+        Ok((request, maker))
+    }
+
+    // only the requester can cancel the request, all its quotes go away
+    #[inline]
+    pub fn cancel(&mut self, rfq_id: &str, requester: AccountId) -> Result<QuoteRequest, RfqError> {
+        if !self
+            .requests
+            .get(rfq_id)
+            .is_some_and(|request| request.requester == requester)
+        {
+            return Err(RfqError::RfqNotFound(rfq_id.into()));
+        }
+        Ok(self.requests.swap_remove(rfq_id).expect("request checked above"))
+    }
+
+    // only the maker can withdraw its quote, the request stays open for the others
+    #[inline]
+    pub fn withdraw(&mut self, rfq_id: &str, quote_id: &str, maker: AccountId) -> Result<Order, RfqError> {
+        let request = self
+            .requests
+            .get_mut(rfq_id)
+            .ok_or_else(|| RfqError::RfqNotFound(rfq_id.into()))?;
+        if !request
+            .quotes
+            .get(quote_id)
+            .is_some_and(|quote| quote.account() == maker)
+        {
+            return Err(RfqError::QuoteNotFound {
+                rfq_id: rfq_id.into(),
+                quote_id: quote_id.into(),
+            });
+        }
+        Ok(request.quotes.shift_remove(quote_id).expect("quote checked above"))
+    }
+}
+
+// block trades must be priced within the current bid/ask (either side can be missing)
+#[inline]
+pub fn check_band(
+    price: OrderPrice,
+    best_bid: Option<OrderPrice>,
+    best_ask: Option<OrderPrice>,
+) -> Result<(), RfqError> {
+    let above_bid = best_bid.is_none_or(|best_bid| price >= best_bid);
+    let below_ask = best_ask.is_none_or(|best_ask| price <= best_ask);
+    if !(above_bid && below_ask) {
+        return Err(RfqError::OutsideBand {
+            price,
+            best_bid,
+            best_ask,
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RfqError {
+    #[error("rfq id already in use! {0}")]
+    RfqDuplicated(CompactString),
+    #[error("rfq not found! {0}")]
+    RfqNotFound(CompactString),
+    #[error("quote id already in use! {0}")]
+    QuoteDuplicated(CompactString),
+    #[error("quote not found (rfq_id={}, quote_id={})", .rfq_id, .quote_id)]
+    QuoteNotFound {
+        rfq_id: CompactString,
+        quote_id: CompactString,
+    },
+    #[error("requester cannot quote its own rfq! {0}")]
+    OwnRfq(CompactString),
+    #[error("block price outside of the bid/ask (price={}, best_bid={:?}, best_ask={:?})", .price, .best_bid, .best_ask)]
+    OutsideBand {
+        price: OrderPrice,
+        best_bid: Option<OrderPrice>,
+        best_ask: Option<OrderPrice>,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::order::OrderId;
+
+    #[rstest]
+    fn request_quote_and_accept() {
+        let (requester, maker) = (AccountId::new(1), AccountId::new(2));
+        let mut rfqs = Rfqs::default();
+        assert!(
+            rfqs.request("rfq".into(), requester, OrderSide::Bid, 100.into())
+                .is_ok()
+        );
+        assert_eq!(
+            rfqs.request("rfq".into(), requester, OrderSide::Bid, 100.into()),
+            Err(RfqError::RfqDuplicated("rfq".into()))
+        );
+
+        let quote = Order::limit_order(OrderId::new(1), OrderSide::Ask, 100.into(), 15.into()).with_account(maker);
+        assert_eq!(
+            rfqs.validate_quote("rfq", "q1", &quote.with_account(requester)),
+            Err(RfqError::OwnRfq("rfq".into()))
+        );
+        assert!(rfqs.validate_quote("rfq", "q1", &quote).is_ok());
+        rfqs.quote("rfq", "q1".into(), quote);
+        assert_eq!(
+            rfqs.validate_quote("rfq", "q1", &quote),
+            Err(RfqError::QuoteDuplicated("q1".into()))
+        );
+
+        assert!(rfqs.accept("rfq", "q2").is_err());
+        let (request, accepted) = rfqs.accept("rfq", "q1").unwrap();
+        assert_eq!(request.requester(), requester);
+        assert_eq!(accepted.id(), quote.id());
+        assert!(rfqs.get("rfq").is_none());
+    }
+
+    #[rstest]
+    fn cancel_and_withdraw() {
+        let (requester, maker) = (AccountId::new(1), AccountId::new(2));
+        let mut rfqs = Rfqs::default();
+        assert!(
+            rfqs.request("rfq".into(), requester, OrderSide::Bid, 100.into())
+                .is_ok()
+        );
+        let quote = Order::limit_order(OrderId::new(1), OrderSide::Ask, 100.into(), 15.into()).with_account(maker);
+        rfqs.quote("rfq", "q1".into(), quote);
+        rfqs.quote("rfq", "q2".into(), quote);
+
+        // only the maker can withdraw its quote and only the requester can cancel
+        assert!(rfqs.withdraw("rfq", "q1", requester).is_err());
+        assert_eq!(
+            rfqs.withdraw("rfq", "q1", maker).map(|quote| quote.id()),
+            Ok(quote.id())
+        );
+        assert!(rfqs.accept("rfq", "q1").is_err());
+        assert_eq!(
+            rfqs.cancel("rfq", maker).map(|_| ()),
+            Err(RfqError::RfqNotFound("rfq".into()))
+        );
+        let request = rfqs.cancel("rfq", requester).unwrap();
+        assert_eq!(request.quotes().count(), 1);
+        assert!(rfqs.get("rfq").is_none());
+    }
+
+    #[rstest]
+    fn block_price_within_bid_ask() {
+        assert!(check_band(15.into(), Some(14.into()), Some(16.into())).is_ok());
+        assert!(check_band(15.into(), None, None).is_ok());
+        assert!(check_band(17.into(), Some(14.into()), None).is_ok());
+        assert!(check_band(17.into(), Some(14.into()), Some(16.into())).is_err());
+        assert!(check_band(13.into(), Some(14.into()), Some(16.into())).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ops::Deref,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+};
+
+// bounded single-producer single-consumer ring buffer connecting two stages of the pipeline, a full ring makes
+// the producer wait (backpressure) instead of buffering without bounds
+pub fn ring<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    let capacity = capacity.max(1).next_power_of_two();
+    let ring = Arc::new(Ring {
+        slots: (0..capacity).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect(),
+        mask: capacity - 1,
+        head: CachePadded(AtomicUsize::new(0)),
+        tail: CachePadded(AtomicUsize::new(0)),
+        closed: AtomicBool::new(false),
+    });
+    (
+        Producer {
+            ring: ring.clone(),
+            tail: 0,
+            cached_head: 0,
+        },
+        Consumer {
+            ring,
+            head: 0,
+            cached_tail: 0,
+        },
+    )
+}
+
+// keeps the indices written by each side in their own cache line
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+// positions only grow (wrapping), the slot is the position masked by the capacity
+struct Ring<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    mask: usize,
+    head: CachePadded<AtomicUsize>, // next position to read, written by the consumer
+    tail: CachePadded<AtomicUsize>, // next position to write, written by the producer
+    closed: AtomicBool,             // either side is gone
+}
+
+// SAFETY: ring hands out exactly one producer and one consumer (neither can be cloned), the producer only writes
+// the free slots (from tail up to head plus the capacity) and the consumer only reads the filled ones (from head up
+// to tail), a slot changes hands only through the release store of head or tail and the acquire load of the other
+// side, so no slot is ever accessed from both threads at once (and the values only move across them, T: Send)
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        let (head, tail) = (*self.head.0.get_mut(), *self.tail.0.get_mut());
+        let mut position = head;
+        while position != tail {
+            // SAFETY: the ring is only dropped once both sides are gone (&mut self), the slots from head to tail hold
+            // the values pushed and never popped, initialized and dropped only here
+            unsafe { self.slots[position & self.mask].get_mut().assume_init_drop() };
+            position = position.wrapping_add(1);
+        }
+    }
+}
+
+pub struct Producer<T> {
+    ring: Arc<Ring<T>>,
+    tail: usize,
+    cached_head: usize, // last head seen, to avoid reading the consumer's cache line on every push
+}
+
+impl<T> Producer<T> {
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.ring.slots.len()
+    }
+
+    // the value back if the ring is full
+    #[inline]
+    pub fn try_push(&mut self, value: T) -> Result<(), T> {
+        if self.tail.wrapping_sub(self.cached_head) == self.capacity() {
+            self.cached_head = self.ring.head.load(Ordering::Acquire);
+            if self.tail.wrapping_sub(self.cached_head) == self.capacity() {
+                return Err(value);
+            }
+        }
+
+        // SAFETY: the slot at tail is free, the consumer popped whatever it held before publishing the head seen above
+        // (the cached head is never ahead of the real one) and won't read it until the tail below is published,
+        // writing doesn't drop what was there (moved out by the pop already)
+        unsafe { (*self.ring.slots[self.tail & self.ring.mask].get()).write(value) };
+        self.tail = self.tail.wrapping_add(1);
+        self.ring.tail.store(self.tail, Ordering::Release);
+        Ok(())
+    }
+
+    // waits while the ring is full, the value back if the consumer is gone
+    #[inline]
+    pub fn push(&mut self, mut value: T) -> Result<(), T> {
+        let mut backoff = Backoff::default();
+        loop {
+            if self.ring.closed.load(Ordering::Acquire) {
+                return Err(value);
+            }
+            match self.try_push(value) {
+                Ok(()) => return Ok(()),
+                Err(full) => value = full,
+            }
+            backoff.wait();
+        }
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.ring.closed.store(true, Ordering::Release);
+    }
+}
+
+pub struct Consumer<T> {
+    ring: Arc<Ring<T>>,
+    head: usize,
+    cached_tail: usize, // last tail seen, to avoid reading the producer's cache line on every pop
+}
+
+impl<T> Consumer<T> {
+    // none if the ring is empty
+    #[inline]
+    pub fn try_pop(&mut self) -> Option<T> {
+        if self.head == self.cached_tail {
+            self.cached_tail = self.ring.tail.load(Ordering::Acquire);
+            if self.head == self.cached_tail {
+                return None;
+            }
+        }
+
+        // SAFETY: the slot at head is before the tail loaded with acquire, so the producer's write to it is visible,
+        // the producer won't write it again until the head below is published and it's never read twice (the head
+        // moves past it right away)
+        let value = unsafe { (*self.ring.slots[self.head & self.ring.mask].get()).assume_init_read() };
+        self.head = self.head.wrapping_add(1);
+        self.ring.head.store(self.head, Ordering::Release);
+        Some(value)
+    }
+
+    // waits while the ring is empty, none once the producer is gone and everything it pushed has been popped
+    #[inline]
+    pub fn pop(&mut self) -> Option<T> {
+        let mut backoff = Backoff::default();
+        loop {
+            // checked before trying so the last values pushed are never missed
+            let closed = self.ring.closed.load(Ordering::Acquire);
+            if let Some(value) = self.try_pop() {
+                return Some(value);
+            }
+            if closed {
+                return None;
+            }
+            backoff.wait();
+        }
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.ring.closed.store(true, Ordering::Release);
+    }
+}
+
+impl<T> Iterator for Consumer<T> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+// spins first (the other side is usually a pinned thread that's about to catch up) and then yields
+#[derive(Default)]
+struct Backoff {
+    step: u32,
+}
+
+impl Backoff {
+    const SPIN_LIMIT: u32 = 64;
+
+    #[inline]
+    fn wait(&mut self) {
+        if self.step < Self::SPIN_LIMIT {
+            std::hint::spin_loop();
+            self.step += 1;
+        } else {
+            std::thread::yield_now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    fn bounded_fifo() {
+        let (mut producer, mut consumer) = ring(3);
+        assert_eq!(producer.capacity(), 4);
+        for value in 1..=4 {
+            assert_eq!(producer.try_push(value), Ok(()));
+        }
+        // full until the consumer makes room
+        assert_eq!(producer.try_push(5), Err(5));
+        assert_eq!(consumer.try_pop(), Some(1));
+        assert_eq!(producer.try_push(5), Ok(()));
+
+        drop(producer);
+        assert_eq!(consumer.collect::<Vec<_>>(), [2, 3, 4, 5]);
+    }
+
+    #[rstest]
+    fn backpressure_across_threads() {
+        let (mut producer, consumer) = ring(8);
+        let writer = std::thread::spawn(move || {
+            for value in 0..100_000u64 {
+                producer.push(value).unwrap();
+            }
+        });
+
+        // the producer waits for the consumer, nothing is lost or reordered
+        assert!(consumer.into_iter().eq(0..100_000));
+        writer.join().unwrap();
+    }
+
+    #[rstest]
+    fn consumer_gone() {
+        let (mut producer, consumer) = ring(2);
+        let value = Arc::new(());
+        assert!(producer.push(value.clone()).is_ok());
+        drop(consumer);
+        assert!(producer.push(value.clone()).is_err());
+
+        // the values left in the ring are dropped with it
+        drop(producer);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
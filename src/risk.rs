@@ -0,0 +1,357 @@
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    order::{AccountId, Order, OrderFeatures, OrderId, OrderPrice, OrderQuantity, OrderSide},
+    trade::Trade,
+};
+
+// every limit is optional, the price collar is the max distance (e.g. 5 for 5%) from the reference price
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RiskLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_order_quantity: Option<OrderQuantity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_notional: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_open_orders: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_resting_notional: Option<Decimal>, // per side
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_collar: Option<Decimal>,
+}
+
+// open orders and resting notional (bids, asks) of an account
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Exposure {
+    open_orders: usize,
+    resting_notional: [Decimal; 2],
+}
+
+impl Exposure {
+    #[inline]
+    fn resting_notional(&mut self, side: OrderSide) -> &mut Decimal {
+        match side {
+            OrderSide::Bid => &mut self.resting_notional[0],
+            OrderSide::Ask => &mut self.resting_notional[1],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct OpenOrder {
+    account: AccountId,
+    side: OrderSide,
+    limit_price: Option<OrderPrice>,
+    resting_notional: Decimal,
+}
+
+// pre-trade checks of an instrument, the instrument limits apply to all the accounts on top of their own limits
+#[derive(Default)]
+pub struct Risk {
+    instrument_limits: RiskLimits,
+    account_limits: IndexMap<AccountId, RiskLimits>,
+    reference_price: Option<OrderPrice>,
+    exposures: IndexMap<AccountId, Exposure>,
+    open_orders: IndexMap<OrderId, OpenOrder>,
+}
+
+impl Risk {
+    #[inline]
+    pub fn new(instrument_limits: RiskLimits) -> Self {
+        Self {
+            instrument_limits,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn set_instrument_limits(&mut self, limits: RiskLimits) {
+        self.instrument_limits = limits;
+    }
+
+    #[inline]
+    pub fn set_account_limits(&mut self, account: AccountId, limits: RiskLimits) {
+        self.account_limits.insert(account, limits);
+    }
+
+    #[inline]
+    pub fn set_reference_price(&mut self, reference_price: OrderPrice) {
+        self.reference_price = Some(reference_price);
+    }
+
+    #[inline]
+    pub fn reference_price(&self) -> Option<OrderPrice> {
+        self.reference_price
+    }
+
+    #[inline]
+    pub fn open_orders(&self, account: AccountId) -> usize {
+        self.exposures.get(&account).map_or(0, |exposure| exposure.open_orders)
+    }
+
+    // the last trade price (if any) is the reference for the price collar, otherwise the configured reference price
+    pub fn check(&self, order: &Order, last_trade_price: Option<OrderPrice>) -> Result<(), RiskError> {
+        let reference_price = last_trade_price.or(self.reference_price);
+        let exposure = self.exposures.get(&order.account()).copied().unwrap_or_default();
+
+        let account_limits = self.account_limits.get(&order.account());
+        for limits in [Some(&self.instrument_limits), account_limits].into_iter().flatten() {
+            check_limits(limits, order, reference_price, exposure)?;
+        }
+
+        Ok(())
+    }
+
+    // track the order once it rests in the book (or waits to be triggered)
+    #[inline]
+    pub fn open(&mut self, order: &Order) {
+        if self.open_orders.contains_key(&order.id()) {
+            return;
+        }
+
+        let open_order = OpenOrder {
+            account: order.account(),
+            side: order.side(),
+            limit_price: order.limit_price(),
+            resting_notional: order.limit_price().unwrap_or_default() * order.remaining(),
+        };
+        self.open_orders.insert(order.id(), open_order);
+
+        let exposure = self.exposures.entry(order.account()).or_default();
+        exposure.open_orders += 1;
+        *exposure.resting_notional(order.side()) += open_order.resting_notional;
+    }
+
+    #[inline]
+    pub fn fill(&mut self, trade: &Trade) {
+        for order_id in [trade.taker(), trade.maker()] {
+            self.reduce(order_id, trade.quantity());
+        }
+    }
+
+    // stop tracking the order once it's no longer live (filled or canceled)
+    #[inline]
+    pub fn close(&mut self, order_id: OrderId) {
+        if let Some(open_order) = self.open_orders.swap_remove(&order_id)
+            && let Some(exposure) = self.exposures.get_mut(&open_order.account)
+        {
+            exposure.open_orders -= 1;
+            *exposure.resting_notional(open_order.side) -= open_order.resting_notional;
+        }
+    }
+
+    #[inline]
+    fn reduce(&mut self, order_id: OrderId, quantity: OrderQuantity) {
+        let Some(open_order) = self.open_orders.get_mut(&order_id) else {
+            return;
+        };
+        let notional = open_order.limit_price.unwrap_or_default() * quantity;
+        open_order.resting_notional -= notional;
+        if let Some(exposure) = self.exposures.get_mut(&open_order.account) {
+            *exposure.resting_notional(open_order.side) -= notional;
+        }
+    }
+}
+
+fn check_limits(
+    limits: &RiskLimits,
+    order: &Order,
+    reference_price: Option<OrderPrice>,
+    mut exposure: Exposure,
+) -> Result<(), RiskError> {
+    let quantity = order.remaining();
+    if let Some(max) = limits.max_order_quantity
+        && quantity > max
+    {
+        return Err(RiskError::MaxOrderQuantity { quantity, max });
+    }
+
+    // market orders are valued at the reference price (if there's one)
+    let notional = order.limit_price().or(reference_price).map(|price| price * quantity);
+    if let (Some(max), Some(notional)) = (limits.max_notional, notional)
+        && notional > max
+    {
+        return Err(RiskError::MaxNotional { notional, max });
+    }
+
+    if let (Some(percent), Some(reference_price), Some(limit_price)) =
+        (limits.price_collar, reference_price, order.limit_price())
+    {
+        let band = reference_price.percent(percent);
+        if limit_price.saturating_sub(reference_price).abs() > band {
+            return Err(RiskError::PriceCollar {
+                limit_price,
+                reference_price,
+                percent,
+            });
+        }
+    }
+
+    // only orders that can rest in the book count towards the open orders and the resting notional
+    if !order.is_bookable() || order.is_immediate_or_cancel() {
+        return Ok(());
+    }
+
+    if let Some(max) = limits.max_open_orders
+        && exposure.open_orders >= max
+    {
+        return Err(RiskError::MaxOpenOrders { max });
+    }
+
+    let resting_notional = *exposure.resting_notional(order.side()) + notional.unwrap_or_default();
+    if let Some(max) = limits.max_resting_notional
+        && resting_notional > max
+    {
+        return Err(RiskError::MaxRestingNotional {
+            side: order.side(),
+            resting_notional,
+            max,
+        });
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RiskError {
+    #[error("order quantity exceeds max (quantity={}, max={})", .quantity, .max)]
+    MaxOrderQuantity {
+        quantity: OrderQuantity,
+        max: OrderQuantity,
+    },
+    #[error("order notional exceeds max (notional={}, max={})", .notional, .max)]
+    MaxNotional { notional: Decimal, max: Decimal },
+    #[error("max open orders reached (max={})", .max)]
+    MaxOpenOrders { max: usize },
+    #[error("resting notional exceeds max (side={}, resting_notional={}, max={})", .side, .resting_notional, .max)]
+    MaxRestingNotional {
+        side: OrderSide,
+        resting_notional: Decimal,
+        max: Decimal,
+    },
+    #[error("limit price outside of the price collar (limit_price={}, reference_price={}, percent={})", .limit_price, .reference_price, .percent)]
+    PriceCollar {
+        limit_price: OrderPrice,
+        reference_price: OrderPrice,
+        percent: Decimal,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+
+    // convention for order ids: 3-digit side (bid = 900, ask = 901), 3-digit quantity, 3-digit price (for market orders always 999)
+
+    #[fixture]
+    fn bid_010_at_100() -> Order {
+        let order_id = OrderId::new(900_010_100);
+        Order::limit_order(order_id, OrderSide::Bid, 10.into(), 100.into())
+    }
+
+    #[fixture]
+    fn bid_010_at_market() -> Order {
+        let order_id = OrderId::new(900_010_999);
+        Order::market_order(order_id, OrderSide::Bid, 10.into())
+    }
+
+    #[rstest]
+    fn max_order_quantity_and_notional(bid_010_at_100: Order, bid_010_at_market: Order) {
+        let limits = RiskLimits {
+            max_order_quantity: Some(5.into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            Risk::new(limits).check(&bid_010_at_100, None),
+            Err(RiskError::MaxOrderQuantity {
+                quantity: 10.into(),
+                max: 5.into()
+            })
+        );
+
+        // the market order is valued at the last trade price
+        let limits = RiskLimits {
+            max_notional: Some(500.into()),
+            ..Default::default()
+        };
+        let risk = Risk::new(limits);
+        assert!(risk.check(&bid_010_at_market, None).is_ok());
+        assert_eq!(
+            risk.check(&bid_010_at_market, Some(60.into())),
+            Err(RiskError::MaxNotional {
+                notional: 600.into(),
+                max: 500.into()
+            })
+        );
+    }
+
+    #[rstest]
+    fn max_open_orders_and_resting_notional(bid_010_at_100: Order) {
+        let account = AccountId::new(1);
+        let limits = RiskLimits {
+            max_open_orders: Some(2),
+            max_resting_notional: Some(2_500.into()),
+            ..Default::default()
+        };
+        let mut risk = Risk::default();
+        risk.set_account_limits(account, limits);
+
+        let first = bid_010_at_100.with_account(account);
+        assert!(risk.check(&first, None).is_ok());
+        risk.open(&first);
+
+        // the second bid would take the resting notional over the max
+        let second = Order::limit_order(OrderId::new(2), OrderSide::Bid, 20.into(), 100.into()).with_account(account);
+        assert_eq!(
+            risk.check(&second, None),
+            Err(RiskError::MaxRestingNotional {
+                side: OrderSide::Bid,
+                resting_notional: 3_000.into(),
+                max: 2_500.into()
+            })
+        );
+
+        // once the first one is half filled there's room for a third one but not for a fourth one
+        let third = Order::limit_order(OrderId::new(3), OrderSide::Bid, 5.into(), 100.into()).with_account(account);
+        risk.reduce(first.id(), 5.into());
+        assert!(risk.check(&second, None).is_ok());
+        risk.open(&third);
+        assert_eq!(risk.open_orders(account), 2);
+        assert_eq!(risk.check(&second, None), Err(RiskError::MaxOpenOrders { max: 2 }));
+
+        // other accounts are not limited
+        assert!(risk.check(&bid_010_at_100, None).is_ok());
+
+        risk.close(first.id());
+        assert_eq!(risk.open_orders(account), 1);
+    }
+
+    #[rstest]
+    fn price_collar(bid_010_at_100: Order) {
+        let limits = RiskLimits {
+            price_collar: Some(10.into()),
+            ..Default::default()
+        };
+        let mut risk = Risk::new(limits);
+
+        // no reference price yet
+        assert!(risk.check(&bid_010_at_100, None).is_ok());
+
+        // within 10% of the reference price but not of the last trade price
+        risk.set_reference_price(95.into());
+        assert!(risk.check(&bid_010_at_100, None).is_ok());
+        assert_eq!(
+            risk.check(&bid_010_at_100, Some(80.into())),
+            Err(RiskError::PriceCollar {
+                limit_price: 100.into(),
+                reference_price: 80.into(),
+                percent: 10.into()
+            })
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use indexmap::{IndexMap, IndexSet};
+
+use crate::order::{Order, OrderId, OrderPrice, OrderSide, StopTrigger};
+
+// arrival of a stop, the stops triggered by the same trade are matched in that order
+type Sequence = u64;
+
+// the stops waiting outside a book, fixed stops are indexed by stop price on each side so a trade only visits
+// the ones it triggers, trailing stops move with every trade so they are all visited
+#[derive(Debug, Default)]
+pub struct StopBook {
+    orders: IndexMap<OrderId, (Sequence, Order)>,
+    buys: BTreeMap<(OrderPrice, Sequence), OrderId>, // triggered at or above the stop price, lowest first
+    sells: BTreeMap<(Reverse<OrderPrice>, Sequence), OrderId>, // triggered at or below the stop price, highest first
+    trailing: IndexSet<OrderId>,
+    next_sequence: Sequence,
+}
+
+impl StopBook {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    #[inline]
+    pub fn contains(&self, order_id: OrderId) -> bool {
+        self.orders.contains_key(&order_id)
+    }
+
+    #[inline]
+    pub fn get(&self, order_id: OrderId) -> Option<&Order> {
+        self.orders.get(&order_id).map(|(_, order)| order)
+    }
+
+    // a stop already activated (e.g. triggered while the book wasn't matching) is kept until it's removed
+    #[inline]
+    pub fn insert(&mut self, order: Order) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        let order_id = order.id();
+        match (order.trigger(), order.side()) {
+            (Some(StopTrigger::Fixed { stop_price }), OrderSide::Bid) => {
+                self.buys.insert((stop_price, sequence), order_id);
+            }
+            (Some(StopTrigger::Fixed { stop_price }), OrderSide::Ask) => {
+                self.sells.insert((Reverse(stop_price), sequence), order_id);
+            }
+            (Some(_), _) => {
+                self.trailing.insert(order_id);
+            }
+            (None, _) => {}
+        }
+        self.orders.insert(order_id, (sequence, order));
+    }
+
+    #[inline]
+    pub fn remove(&mut self, order_id: OrderId) -> Option<Order> {
+        let (sequence, order) = self.orders.swap_remove(&order_id)?;
+        // the triggered ones are not indexed anymore
+        match (order.trigger(), order.side()) {
+            (Some(StopTrigger::Fixed { stop_price }), OrderSide::Bid) => {
+                self.buys.remove(&(stop_price, sequence));
+            }
+            (Some(StopTrigger::Fixed { stop_price }), OrderSide::Ask) => {
+                self.sells.remove(&(Reverse(stop_price), sequence));
+            }
+            (Some(_), _) => {
+                self.trailing.swap_remove(&order_id);
+            }
+            (None, _) => {}
+        }
+        Some(order)
+    }
+
+    // turn a triggered stop into a regular order without taking it out (see trigger)
+    #[inline]
+    pub fn activate(&mut self, order_id: OrderId) {
+        if let Some((_, order)) = self.orders.get_mut(&order_id) {
+            order.activate();
+        }
+    }
+
+    // follow the last traded price with the trailing stops and unindex all the stops it triggers, which are put
+    // in `triggered` in the order they came in (they stay here until they are removed or activated)
+    pub fn trigger(&mut self, last_price: OrderPrice, triggered: &mut Vec<OrderId>) {
+        triggered.clear();
+
+        while let Some(entry) = self.buys.first_entry()
+            && entry.key().0 <= last_price
+        {
+            triggered.push(entry.remove());
+        }
+        while let Some(entry) = self.sells.first_entry()
+            && entry.key().0.0 >= last_price
+        {
+            triggered.push(entry.remove());
+        }
+
+        let fixed = triggered.len();
+        for order_id in &self.trailing {
+            if let Some((_, order)) = self.orders.get_mut(order_id)
+                && order.update_trigger(last_price)
+            {
+                triggered.push(*order_id);
+            }
+        }
+        for order_id in &triggered[fixed..] {
+            self.trailing.swap_remove(order_id);
+        }
+
+        if triggered.len() > 1 {
+            triggered.sort_unstable_by_key(|order_id| self.orders.get(order_id).map(|(sequence, _)| *sequence));
+        }
+    }
+
+    // the stops the predicate holds for, in the order they came in
+    pub fn select(&self, predicate: impl Fn(&Order) -> bool) -> Vec<OrderId> {
+        let mut selected: Vec<_> = self
+            .orders
+            .values()
+            .filter(|(_, order)| predicate(order))
+            .map(|(sequence, order)| (*sequence, order.id()))
+            .collect();
+        selected.sort_unstable_by_key(|(sequence, _)| *sequence);
+        selected.into_iter().map(|(_, order_id)| order_id).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+    use rust_decimal::Decimal;
+
+    use super::*;
+
+    fn stop(order_id: u64, side: OrderSide, trigger: StopTrigger) -> Order {
+        Order::market_order(OrderId::new(order_id), side, 100.into()).with_trigger(trigger)
+    }
+
+    fn fixed(stop_price: i32) -> StopTrigger {
+        StopTrigger::Fixed {
+            stop_price: stop_price.into(),
+        }
+    }
+
+    #[rstest]
+    fn only_crossed_stops_are_triggered() {
+        let mut stops = StopBook::default();
+        stops.insert(stop(1, OrderSide::Bid, fixed(12)));
+        stops.insert(stop(2, OrderSide::Bid, fixed(11)));
+        stops.insert(stop(3, OrderSide::Ask, fixed(9)));
+        stops.insert(stop(4, OrderSide::Ask, fixed(8)));
+        stops.insert(stop(5, OrderSide::Bid, fixed(13)));
+
+        let mut triggered = vec![];
+        stops.trigger(10.into(), &mut triggered);
+        assert!(triggered.is_empty());
+
+        // in the order they came in, whatever their stop price
+        stops.trigger(12.into(), &mut triggered);
+        assert_eq!(triggered, [OrderId::new(1), OrderId::new(2)]);
+        stops.trigger(8.into(), &mut triggered);
+        assert_eq!(triggered, [OrderId::new(3), OrderId::new(4)]);
+
+        // triggered stops are no longer indexed but still there until removed
+        stops.trigger(12.into(), &mut triggered);
+        assert!(triggered.is_empty());
+        assert_eq!(stops.len(), 5);
+        for order_id in 1..=4 {
+            assert!(stops.remove(OrderId::new(order_id)).is_some());
+        }
+
+        // a canceled stop is never triggered
+        assert!(stops.remove(OrderId::new(5)).is_some());
+        stops.trigger(20.into(), &mut triggered);
+        assert!(triggered.is_empty() && stops.is_empty());
+    }
+
+    #[rstest]
+    fn trailing_stops_follow_every_trade() {
+        let mut stops = StopBook::default();
+        let trailing = StopTrigger::TrailingPercent {
+            stop_price: 9.into(),
+            percent: Decimal::from(10),
+        };
+        stops.insert(stop(1, OrderSide::Ask, trailing));
+        stops.insert(stop(2, OrderSide::Ask, fixed(14)));
+
+        let mut triggered = vec![];
+        stops.trigger(20.into(), &mut triggered);
+        assert!(triggered.is_empty());
+        assert_eq!(
+            stops.get(OrderId::new(1)).unwrap().trigger().unwrap().stop_price(),
+            18.into()
+        );
+
+        // both triggered by the same trade, in the order they came in
+        stops.trigger(14.into(), &mut triggered);
+        assert_eq!(triggered, [OrderId::new(1), OrderId::new(2)]);
+        stops.activate(OrderId::new(1));
+        assert!(!stops.get(OrderId::new(1)).unwrap().is_stop());
+        assert_eq!(stops.select(|order| order.is_stop()), [OrderId::new(2)]);
+    }
+}
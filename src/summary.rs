@@ -13,7 +13,16 @@ pub struct Summary {
 impl Summary {
     fn spread(&self) -> Option<OrderPrice> {
         match (self.best_bid, self.best_ask) {
-            (Some(bid_price), Some(ask_price)) => Some(ask_price - bid_price),
+            (Some(bid_price), Some(ask_price)) => Some(ask_price.saturating_sub(bid_price)),
+            _ => None,
+        }
+    }
+
+    // mark price for the unrealized pnl
+    #[inline]
+    pub fn mid(&self) -> Option<OrderPrice> {
+        match (self.best_bid, self.best_ask) {
+            (Some(bid_price), Some(ask_price)) => Some(bid_price.midpoint(ask_price)),
             _ => None,
         }
     }
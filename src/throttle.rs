@@ -0,0 +1,284 @@
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::order::AccountId;
+
+// engine clock in nanoseconds, only moved forward by the clock requests so replays are deterministic
+pub type Timestamp = u64;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+const DEFAULT_RATIO_WINDOW: Timestamp = 60 * NANOS_PER_SECOND;
+
+// every limit is optional, the order-to-trade ratio counts orders entered per trade within a window (in nanoseconds,
+// a minute if not set) so an account that stopped trading gets to enter orders again once the next one starts
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ThrottleLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orders_per_second: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancels_per_second: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_order_to_trade_ratio: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ratio_window: Option<Timestamp>,
+}
+
+// tokens are kept in billionths so refilling at a rate per second is exact with a nanosecond clock,
+// the bucket holds up to one second worth of tokens (and starts full)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct TokenBucket {
+    tokens: Option<u64>,
+    last_refill: Timestamp,
+}
+
+impl TokenBucket {
+    #[inline]
+    fn try_take(&mut self, rate: u64, now: Timestamp) -> bool {
+        // a rate too high to count in billionths is as good as no limit at all
+        let capacity = rate.saturating_mul(NANOS_PER_SECOND);
+        let elapsed = now.saturating_sub(self.last_refill);
+        let tokens = self.tokens.map_or(capacity, |tokens| {
+            tokens.saturating_add(elapsed.saturating_mul(rate)).min(capacity)
+        });
+        self.last_refill = now;
+
+        if tokens < NANOS_PER_SECOND {
+            self.tokens = Some(tokens);
+            return false;
+        }
+        self.tokens = Some(tokens - NANOS_PER_SECOND);
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct ThrottleState {
+    orders: TokenBucket,
+    cancels: TokenBucket,
+    // orders entered and trades done since the window started
+    window_start: Timestamp,
+    orders_entered: u64,
+    trades: u64,
+}
+
+impl ThrottleState {
+    // the windows follow each other from the first order or trade of the account
+    #[inline]
+    fn roll(&mut self, window: Timestamp, now: Timestamp) {
+        if now.saturating_sub(self.window_start) >= window {
+            self.window_start = now;
+            self.orders_entered = 0;
+            self.trades = 0;
+        }
+    }
+}
+
+// order entry throttles per account, accounts without their own limits get the default ones
+#[derive(Default)]
+pub struct Throttle {
+    default_limits: ThrottleLimits,
+    account_limits: IndexMap<AccountId, ThrottleLimits>,
+    states: IndexMap<AccountId, ThrottleState>,
+}
+
+impl Throttle {
+    #[inline]
+    pub fn new(default_limits: ThrottleLimits) -> Self {
+        Self {
+            default_limits,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn set_default_limits(&mut self, limits: ThrottleLimits) {
+        self.default_limits = limits;
+    }
+
+    #[inline]
+    pub fn set_account_limits(&mut self, account: AccountId, limits: ThrottleLimits) {
+        self.account_limits.insert(account, limits);
+    }
+
+    #[inline]
+    fn limits(&self, account: AccountId) -> ThrottleLimits {
+        self.account_limits
+            .get(&account)
+            .copied()
+            .unwrap_or(self.default_limits)
+    }
+
+    // every new order takes a token, even if it ends up rejected afterwards
+    pub fn order(&mut self, account: AccountId, now: Timestamp) -> Result<(), ThrottleError> {
+        let limits = self.limits(account);
+        let state = self.states.entry(account).or_default();
+        state.roll(limits.ratio_window.unwrap_or(DEFAULT_RATIO_WINDOW), now);
+
+        if let Some(max) = limits.max_order_to_trade_ratio
+            && state.orders_entered >= max.saturating_mul(state.trades.max(1))
+        {
+            return Err(ThrottleError::OrderToTradeRatio {
+                orders: state.orders_entered,
+                trades: state.trades,
+                max,
+            });
+        }
+
+        if let Some(max) = limits.orders_per_second
+            && !state.orders.try_take(max, now)
+        {
+            return Err(ThrottleError::OrderRate { max });
+        }
+
+        state.orders_entered += 1;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn cancel(&mut self, account: AccountId, now: Timestamp) -> Result<(), ThrottleError> {
+        let limits = self.limits(account);
+        let state = self.states.entry(account).or_default();
+
+        if let Some(max) = limits.cancels_per_second
+            && !state.cancels.try_take(max, now)
+        {
+            return Err(ThrottleError::CancelRate { max });
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn trade(&mut self, account: AccountId, now: Timestamp) {
+        let limits = self.limits(account);
+        let state = self.states.entry(account).or_default();
+        state.roll(limits.ratio_window.unwrap_or(DEFAULT_RATIO_WINDOW), now);
+        state.trades += 1;
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ThrottleError {
+    #[error("order rate exceeded (max={}/s)", .max)]
+    OrderRate { max: u64 },
+    #[error("cancel rate exceeded (max={}/s)", .max)]
+    CancelRate { max: u64 },
+    #[error("order-to-trade ratio exceeded (orders={}, trades={}, max={})", .orders, .trades, .max)]
+    OrderToTradeRatio { orders: u64, trades: u64, max: u64 },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+
+    #[fixture]
+    fn account() -> AccountId {
+        AccountId::new(1)
+    }
+
+    #[rstest]
+    fn order_and_cancel_rates(account: AccountId) {
+        let limits = ThrottleLimits {
+            orders_per_second: Some(2),
+            cancels_per_second: Some(1),
+            ..Default::default()
+        };
+        let mut throttle = Throttle::new(limits);
+
+        // the bucket starts full and refills one token every half second
+        assert!(throttle.order(account, 0).is_ok());
+        assert!(throttle.order(account, 0).is_ok());
+        assert_eq!(throttle.order(account, 0), Err(ThrottleError::OrderRate { max: 2 }));
+        assert_eq!(
+            throttle.order(account, NANOS_PER_SECOND / 4),
+            Err(ThrottleError::OrderRate { max: 2 })
+        );
+        assert!(throttle.order(account, NANOS_PER_SECOND / 2).is_ok());
+
+        // cancels have their own bucket
+        assert!(throttle.cancel(account, NANOS_PER_SECOND / 2).is_ok());
+        assert_eq!(
+            throttle.cancel(account, NANOS_PER_SECOND),
+            Err(ThrottleError::CancelRate { max: 1 })
+        );
+        assert!(throttle.cancel(account, 3 * NANOS_PER_SECOND / 2).is_ok());
+
+        // other accounts are not affected
+        assert!(throttle.order(AccountId::new(2), 0).is_ok());
+
+        // a huge rate doesn't overflow the bucket
+        throttle.set_account_limits(
+            account,
+            ThrottleLimits {
+                orders_per_second: Some(u64::MAX),
+                ..Default::default()
+            },
+        );
+        for now in [NANOS_PER_SECOND, u64::MAX] {
+            assert!(throttle.order(account, now).is_ok());
+        }
+    }
+
+    #[rstest]
+    fn order_to_trade_ratio(account: AccountId) {
+        let mut throttle = Throttle::default();
+        let limits = ThrottleLimits {
+            max_order_to_trade_ratio: Some(2),
+            ..Default::default()
+        };
+        throttle.set_account_limits(account, limits);
+
+        assert!(throttle.order(account, 0).is_ok());
+        assert!(throttle.order(account, 0).is_ok());
+        assert_eq!(
+            throttle.order(account, 0),
+            Err(ThrottleError::OrderToTradeRatio {
+                orders: 2,
+                trades: 0,
+                max: 2
+            })
+        );
+
+        // trading makes room for more orders
+        throttle.trade(account, 0);
+        throttle.trade(account, 0);
+        assert!(throttle.order(account, 0).is_ok());
+        assert!(throttle.order(account, 0).is_ok());
+        assert!(throttle.order(account, 0).is_err());
+
+        // the counters start over with the next window, even without trading
+        assert!(throttle.order(account, DEFAULT_RATIO_WINDOW - 1).is_err());
+        assert!(throttle.order(account, DEFAULT_RATIO_WINDOW).is_ok());
+        assert!(throttle.order(account, DEFAULT_RATIO_WINDOW).is_ok());
+        assert!(throttle.order(account, DEFAULT_RATIO_WINDOW).is_err());
+
+        // and so does a shorter one
+        throttle.set_account_limits(
+            account,
+            ThrottleLimits {
+                max_order_to_trade_ratio: Some(2),
+                ratio_window: Some(NANOS_PER_SECOND),
+                ..Default::default()
+            },
+        );
+        assert!(
+            throttle
+                .order(account, DEFAULT_RATIO_WINDOW + NANOS_PER_SECOND / 2)
+                .is_err()
+        );
+        assert!(throttle.order(account, DEFAULT_RATIO_WINDOW + NANOS_PER_SECOND).is_ok());
+
+        // a huge ratio doesn't overflow, it's as good as no limit at all
+        throttle.set_account_limits(
+            account,
+            ThrottleLimits {
+                max_order_to_trade_ratio: Some(u64::MAX),
+                ..Default::default()
+            },
+        );
+        assert!(throttle.order(account, DEFAULT_RATIO_WINDOW + NANOS_PER_SECOND).is_ok());
+    }
+}
@@ -1,7 +1,4 @@
-use std::{
-    fmt::Display,
-    sync::atomic::{AtomicU64, Ordering::Relaxed},
-};
+use std::fmt::Display;
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
@@ -36,19 +33,28 @@ pub struct Trade {
 
 impl Trade {
     #[inline]
-    pub fn new(taker: &mut Order, maker: &mut Order, traded: OrderQuantity) -> Result<Trade, TradeError> {
+    pub fn new(id: TradeId, taker: &mut Order, maker: &mut Order, traded: OrderQuantity) -> Result<Trade, TradeError> {
         let price = maker
             .limit_price()
             .ok_or(TradeError::MakerWithoutLimitPrice(maker.id()))?;
 
+        Self::with_price(id, taker, maker, traded, price)
+    }
+
+    // trade at a given price instead of the maker one (e.g. the clearing price of an auction)
+    #[inline]
+    pub fn with_price(
+        id: TradeId,
+        taker: &mut Order,
+        maker: &mut Order,
+        traded: OrderQuantity,
+        price: OrderPrice,
+    ) -> Result<Trade, TradeError> {
         taker.fill(traded).map_err(TradeError::OrderError)?;
         maker.fill(traded).map_err(TradeError::OrderError)?;
 
-        static TRADE_ID_GENERATOR: AtomicU64 = AtomicU64::new(0);
-        let trade_id = TRADE_ID_GENERATOR.fetch_add(1, Relaxed);
-
         Ok(Trade {
-            id: trade_id.into(),
+            id,
             taker: taker.id(),
             maker: maker.id(),
             price,
@@ -61,10 +67,25 @@ impl Trade {
         self.id
     }
 
+    #[inline]
+    pub fn taker(&self) -> OrderId {
+        self.taker
+    }
+
+    #[inline]
+    pub fn maker(&self) -> OrderId {
+        self.maker
+    }
+
     #[inline]
     pub fn price(&self) -> OrderPrice {
         self.price
     }
+
+    #[inline]
+    pub fn quantity(&self) -> OrderQuantity {
+        self.quantity
+    }
 }
 
 impl Display for Trade {
@@ -77,6 +98,58 @@ impl Display for Trade {
     }
 }
 
+// the trades of a book in the order they were done, kept until they are drained (e.g. once settled) so the tape
+// only holds the ones not handled yet, the ids keep counting from the first trade of the book
+#[derive(Debug, Default)]
+pub struct TradeTape {
+    trades: Vec<Trade>,
+    drained: u64,
+}
+
+impl TradeTape {
+    #[inline]
+    pub fn reserve(&mut self, capacity: usize) {
+        self.trades.reserve(capacity);
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.trades.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.trades.is_empty()
+    }
+
+    #[inline]
+    pub fn get(&self, idx: usize) -> Option<&Trade> {
+        self.trades.get(idx)
+    }
+
+    #[inline]
+    pub fn iter(&self) -> std::slice::Iter<'_, Trade> {
+        self.trades.iter()
+    }
+
+    #[inline]
+    pub fn next_id(&self) -> TradeId {
+        TradeId::new(self.drained + self.trades.len() as u64 + 1)
+    }
+
+    #[inline]
+    pub fn push(&mut self, trade: Trade) {
+        self.trades.push(trade);
+    }
+
+    // move the trades to `into`, the tape keeps its buffer
+    #[inline]
+    pub fn drain_into(&mut self, into: &mut Vec<Trade>) {
+        self.drained += self.trades.len() as u64;
+        into.append(&mut self.trades);
+    }
+}
+
 #[derive(Debug, Error, PartialEq)]
 pub enum TradeError {
     #[error("maker should be a limit order, always with a limit price! {0}")]
@@ -89,7 +162,7 @@ pub enum TradeError {
 mod test {
     use crate::{
         order::{Order, OrderId, OrderQuantity, OrderSide},
-        trade::Trade,
+        trade::{Trade, TradeTape},
     };
 
     use rstest::{fixture, rstest};
@@ -122,7 +195,7 @@ mod test {
         let traded = taker.remaining().min(maker.remaining());
         assert_eq!(traded, taker.can_trade(&maker));
 
-        let trade = Trade::new(&mut taker, &mut maker, traded);
+        let trade = Trade::new(1.into(), &mut taker, &mut maker, traded);
         assert!(trade.is_ok());
 
         // check that the orders have been filled correctly
@@ -145,7 +218,7 @@ mod test {
         let traded = taker.remaining().min(maker.remaining());
         assert_eq!(traded, taker.can_trade(&maker));
 
-        let trade = Trade::new(&mut taker, &mut maker, traded);
+        let trade = Trade::new(1.into(), &mut taker, &mut maker, traded);
         assert!(trade.is_ok());
 
         // check that the orders have been filled correctly
@@ -159,4 +232,21 @@ mod test {
         assert_eq!(trade.quantity, traded);
         assert_eq!(trade.price, maker.limit_price().unwrap());
     }
+
+    #[rstest]
+    fn drain_tape(bid_015_at_100: Order, ask_010_at_100: Order) {
+        let (mut taker, mut maker) = (bid_015_at_100, ask_010_at_100);
+        let mut tape = TradeTape::default();
+        for _ in 0..2 {
+            tape.push(Trade::new(tape.next_id(), &mut taker, &mut maker, 5.into()).unwrap());
+        }
+
+        let mut drained = vec![];
+        tape.drain_into(&mut drained);
+        assert!(tape.is_empty());
+        assert_eq!(drained.iter().map(Trade::id).collect::<Vec<_>>(), [1.into(), 2.into()]);
+
+        // the ids go on from the drained trades
+        assert_eq!(tape.next_id(), 3.into());
+    }
 }
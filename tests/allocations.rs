@@ -0,0 +1,110 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use matchina::{
+    order::{Order, OrderId, OrderSide, StopTrigger},
+    orderbook::{Orderbook, PriceRange},
+    trade::Trade,
+};
+use rstest::rstest;
+
+// counts the allocations of each thread, so tests running in parallel don't add to each other (it's the allocator
+// of this test binary only, so it doesn't get in the way of the unit tests)
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count() {
+    let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count();
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+// rests, sweeps two levels, triggers a stop and cancels what's left, so the book ends up empty again, the trades
+// (and the triggered stops) are drained as the engine does to settle them
+fn cycle(orderbook: &mut Orderbook, trades: &mut Vec<Trade>, stops: &mut Vec<OrderId>, cycle: u64) {
+    let id = |n: u64| OrderId::new(cycle * 10 + n);
+    let bid = Order::limit_order(id(1), OrderSide::Bid, 20.into(), 14.into());
+    let first_ask = Order::limit_order(id(2), OrderSide::Ask, 10.into(), 15.into());
+    let second_ask = Order::limit_order(id(3), OrderSide::Ask, 10.into(), 16.into());
+    let taker = Order::limit_order(id(4), OrderSide::Bid, 15.into(), 16.into());
+    let stop =
+        Order::market_order(id(5), OrderSide::Ask, 4.into()).with_trigger(StopTrigger::Fixed { stop_price: 14.into() });
+    let trigger = Order::limit_order(id(6), OrderSide::Ask, 10.into(), 14.into());
+
+    assert_eq!(orderbook.handle_create(bid), Ok(false));
+    assert_eq!(orderbook.handle_create(first_ask), Ok(false));
+    assert_eq!(orderbook.handle_create(second_ask), Ok(false));
+    assert_eq!(orderbook.handle_create(taker), Ok(true));
+    assert_eq!(orderbook.handle_create(stop), Ok(false));
+    assert_eq!(orderbook.handle_create(trigger), Ok(true));
+    assert_eq!(orderbook.handle_cancel(id(1)).unwrap().remaining(), 6.into());
+    assert_eq!(orderbook.handle_cancel(id(3)).unwrap().remaining(), 5.into());
+
+    orderbook.drain_trades(trades);
+    assert_eq!(trades.len(), 4);
+    trades.clear();
+    orderbook.drain_matched_stops(stops);
+    assert_eq!(stops, &[id(5)]);
+    stops.clear();
+}
+
+#[rstest]
+fn steady_state_without_allocations(#[values(false, true)] array: bool) {
+    let orderbook = Orderbook::default().with_capacity(16);
+    let mut orderbook = match array {
+        true => orderbook.with_price_range(PriceRange {
+            min_price: 1.into(),
+            max_price: 100.into(),
+            tick_size: 1.into(),
+        }),
+        false => orderbook,
+    };
+    let mut trades = Vec::with_capacity(16);
+    let mut stops = Vec::with_capacity(16);
+
+    // the first cycle sets up the buffers, the rest reuse them (the capacity is way below the orders and trades
+    // of all the cycles, nothing grows with them)
+    cycle(&mut orderbook, &mut trades, &mut stops, 0);
+    let allocated = allocations(|| {
+        for n in 1..=100 {
+            cycle(&mut orderbook, &mut trades, &mut stops, n);
+        }
+    });
+    assert_eq!(allocated, 0);
+    assert_eq!(orderbook.next_trade_id(), 405.into());
+    assert!(orderbook.trades().is_empty());
+    assert!((0..=1_006).all(|order_id| !orderbook.contains(OrderId::new(order_id))));
+}
@@ -0,0 +1,120 @@
+use compact_str::CompactString;
+use matchina::{
+    dispatcher::{Dispatcher, DispatcherError, Sequenced},
+    engine::{Engine, EngineError},
+    order::{OrderRequest, util::generate},
+};
+use rstest::rstest;
+
+// the dispatcher is only reachable through the library (the binary runs a single pair), so it's exercised here
+// through the public api as an application embedding the engines would use it
+
+const PAIRS: [&str; 3] = ["ETH/USDT", "BTC/USDT", "SOL/USDT"];
+
+// the requests of every pair interleaved, the creates carry the pair they are routed by
+fn requests() -> Vec<(CompactString, OrderRequest)> {
+    let per_pair: Vec<Vec<OrderRequest>> = PAIRS.iter().map(|_| generate(1..=300).collect()).collect();
+    let mut requests = vec![];
+    for idx in 0..per_pair[0].len() {
+        for (pair, order_requests) in PAIRS.iter().zip(&per_pair) {
+            let mut order_request = order_requests[idx].clone();
+            if let OrderRequest::Create { pair: create_pair, .. } = &mut order_request {
+                *create_pair = CompactString::new(pair);
+            }
+            requests.push((CompactString::new(pair), order_request));
+        }
+    }
+    requests
+}
+
+fn engines() -> Vec<Engine> {
+    PAIRS.iter().map(|pair| Engine::new(pair)).collect()
+}
+
+fn run(dispatcher: &Dispatcher, requests: &[(CompactString, OrderRequest)]) -> (Vec<Engine>, Vec<Sequenced>) {
+    let mut outcomes = vec![];
+    let engines = dispatcher
+        .run(engines(), requests.to_vec(), |sequenced| outcomes.push(sequenced))
+        .unwrap();
+    (engines, outcomes)
+}
+
+#[rstest]
+fn sharded_as_each_engine_alone(#[values(1, 2, 4)] workers: usize) {
+    let requests = requests();
+    let dispatcher = Dispatcher::new(workers).with_capacity(8).with_admin(true);
+    let (engines, outcomes) = run(&dispatcher, &requests);
+
+    // the outcomes come out in the order of the requests
+    assert_eq!(outcomes.len(), requests.len());
+    assert!(
+        (0..)
+            .zip(&outcomes)
+            .all(|(sequence, sequenced)| sequenced.sequence == sequence)
+    );
+
+    // every engine ends up as if it had processed the requests of its pair on its own
+    for (pair, engine) in PAIRS.iter().zip(&engines) {
+        assert_eq!(engine.pair(), *pair);
+        let mut alone = Engine::new(pair);
+        let (mut expected, mut events) = (vec![], vec![]);
+        for ((_, order_request), sequenced) in requests.iter().zip(&outcomes) {
+            if sequenced.pair == *pair {
+                expected.extend(alone.process_admin(order_request.clone()).unwrap_or_default());
+                events.extend(sequenced.result.as_ref().cloned().unwrap_or_default());
+            }
+        }
+        assert!(!events.is_empty());
+        assert_eq!(
+            serde_json::to_string(&events).unwrap(),
+            serde_json::to_string(&expected).unwrap()
+        );
+        assert_eq!(engine.snapshot(), alone.snapshot());
+    }
+}
+
+#[rstest]
+fn clients_and_unknown_pairs() {
+    let mut requests = requests();
+    requests.push(("DOGE/USDT".into(), requests[0].1.clone()));
+    let (_, outcomes) = run(&Dispatcher::new(2), &requests);
+
+    // the deposits only come from the operator, and no engine takes a pair it doesn't have
+    assert!(matches!(
+        &outcomes[0].result,
+        Err(DispatcherError::EngineError(EngineError::AdminOnly(_)))
+    ));
+    assert!(matches!(
+        &outcomes.last().unwrap().result,
+        Err(DispatcherError::UnknownPair(pair)) if *pair == "DOGE/USDT"
+    ));
+}
+
+#[rstest]
+fn one_engine_per_pair() {
+    let mut engines = engines();
+    engines.push(Engine::new(PAIRS[1]));
+    let Err(error) = Dispatcher::new(2).run(engines, requests(), |_| {}) else {
+        panic!("two engines for the same pair");
+    };
+    assert!(matches!(
+        error.downcast_ref::<DispatcherError>(),
+        Some(DispatcherError::DuplicatePair(pair)) if *pair == PAIRS[1]
+    ));
+}
+
+#[rstest]
+fn output_panicking() {
+    // the workers keep filling their rings while the output is gone, run has to return instead of waiting on them
+    let (done_tx, done_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let dispatcher = Dispatcher::new(2).with_capacity(1).with_admin(true);
+        let run = std::panic::catch_unwind(|| {
+            dispatcher.run(engines(), requests(), |sequenced| {
+                assert!(sequenced.sequence < 10, "output failed");
+            })
+        });
+        done_tx.send(run.is_err()).unwrap();
+    });
+    assert!(done_rx.recv_timeout(std::time::Duration::from_secs(30)).unwrap());
+}